@@ -6,6 +6,11 @@ use std::io;
 
 use std::process::Command;
 
+// Same path validation as CrystalNative (only `safe_join` is needed here)
+#[path = "../../native/src/safe_path.rs"]
+#[allow(dead_code)]
+mod safe_path;
+
 fn main() {
     if let Err(e) = run() {
         // En un entorno de GUI, podríamos usar un Message Box, pero por ahora fallamos silenciosamente 
@@ -33,8 +38,9 @@ fn run() -> Result<(), Box<dyn std::error::Error>> {
         let mut file = zip.by_index(i)?;
         let name = file.name().to_string();
         
-        // Clean name for Windows
-        let outpath = install_dir.join(name.replace("\\", "/").trim_start_matches('/'));
+        // Refuse entries that would land outside install_dir (`..`, drive
+        // letters, reserved Windows names...)
+        let outpath = safe_path::safe_join(&install_dir, &name)?;
 
         if name.ends_with('/') || name.ends_with('\\') || file.is_dir() {
             fs::create_dir_all(&outpath)?;
@@ -19,6 +19,11 @@ $tempLauncher = "$tempRoot\launcher"
 $tempInstaller = "$tempRoot\installer"
 $tempBootstrapper = "$tempRoot\bootstrapper"
 
+# Ed25519 public key the launcher verifies the pack manifest with
+# (printed by scripts/sync_mods.ps1 -NewKeyPair)
+$packPublicKey = $env:CRYSTAL_PACK_PUBLIC_KEY
+$dartDefines = @("--dart-define=CRYSTAL_PACK_PUBLIC_KEY=$packPublicKey")
+
 function Write-Step { param([string]$msg) Write-Host "`n[STEP] $msg" -ForegroundColor Cyan }
 
 # --- CLEANUP LOGIC ---
@@ -34,6 +39,9 @@ if ($Clean) {
 Write-Step "Checking environment..."
 flutter --version
 cargo --version
+if (-not $packPublicKey) {
+    Write-Warning "CRYSTAL_PACK_PUBLIC_KEY is not set: the launcher will fall back to the legacy Supabase mod sync"
+}
 
 # Ensure temp root exists
 New-Item -ItemType Directory -Force -Path $tempRoot | Out-Null
@@ -59,7 +67,7 @@ Set-Location $tempLauncher
 Write-Host "Running flutter build windows..."
 flutter clean
 flutter pub get
-flutter build windows --release
+flutter build windows --release @dartDefines
 if (-not $?) { throw "Game Client Build Failed" }
 
 # 1.3 Zip Payload
@@ -83,7 +91,7 @@ Set-Location $tempInstaller
 Write-Host "Running flutter build windows (Installer)..."
 flutter clean
 flutter pub get
-flutter build windows --release
+flutter build windows --release @dartDefines
 if (-not $?) { throw "Installer UI Build Failed" }
 
 $installerDistDir = "$tempInstaller\build\windows\x64\runner\Release"
@@ -102,7 +110,7 @@ cargo build --release --lib
 if (-not $?) { throw "Core Lib Build Failed" }
 
 # Copy DLL to Installer Distribution
-Copy-Item "$env:CARGO_TARGET_DIR\release\CrystalNative.dll" "$installerDistDir\installer_native.dll" -Force
+Copy-Item "$env:CARGO_TARGET_DIR\release\crystal_native.dll" "$installerDistDir\installer_native.dll" -Force
 
 # ==============================================================================
 # PHASE 3: BUILD BOOTSTRAPPER (The Final EXE)
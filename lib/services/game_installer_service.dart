@@ -1,6 +1,5 @@
 import 'dart:convert';
 import 'dart:io';
-import 'dart:isolate';
 import 'package:http/http.dart' as http;
 import 'package:path/path.dart' as p;
 import 'package:crypto/crypto.dart';
@@ -94,21 +93,25 @@ class GameInstallerService {
             .writeAsString('{"profiles": {}, "settings": {}, "version": 3}');
       }
 
-      // Call Rust native function in a background isolate to avoid freezing UI
-      final result = await Isolate.run(() async {
-        final api = NativeApi();
-        api.init(); // Ensure init in this isolate
-        return api.installNeoForge(
-          neoVersion: neoVersion,
-          gameDir: gameDirectory,
-          javaPath: javaPath,
-        );
-      });
+      // Runs as a job on the shared native core; polling keeps the UI responsive
+      final job = await nativeApi.installNeoForge(
+        neoVersion: neoVersion,
+        gameDir: gameDirectory,
+        javaPath: javaPath,
+        onProgress: (snapshot) {
+          final total = snapshot['bytes_total'] as int? ?? 0;
+          if (total > 0) {
+            final done = snapshot['bytes_done'] as int? ?? 0;
+            onProgress?.call("Descargando e instalando NeoForge (Rust)...", 0.2 + 0.6 * done / total);
+          }
+        },
+      );
 
-      if (result != 1) {
-        logService.log("❌ NeoForge Installer Failed with code: $result", level: Level.error, category: "GAME");
+      if (job['state'] != 'completed') {
+        final error = job['error'] as Map<String, dynamic>?;
+        logService.log("❌ NeoForge Installer Failed: ${error ?? job['state']}", level: Level.error, category: "GAME");
         throw Exception(
-          "El instalador de NeoForge falló con código $result",
+          "El instalador de NeoForge falló: ${error?['message'] ?? job['state']}",
         );
       }
 
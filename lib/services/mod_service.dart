@@ -19,72 +19,107 @@ class ModService {
 
   final NativeR2Service _nativeR2 = NativeR2Service();
 
-  /// Sincroniza los mods oficiales usando la base de datos de Supabase y hashes SHA-1
+  /// Manifiesto firmado del modpack (Cloudflare R2), publicado por el panel de admin.
+  String packManifestUrl =
+      'https://pub-3a18f6cd71c44a49b8f2f2e48e14a744.r2.dev/launcher/pack_manifest.json';
+
+  /// Clave pública Ed25519 (hex) con la que se firma el manifiesto. Se fija en
+  /// la compilación (`--dart-define=CRYSTAL_PACK_PUBLIC_KEY=...`), nunca se
+  /// descarga junto al manifiesto.
+  static const String packPublicKey = String.fromEnvironment('CRYSTAL_PACK_PUBLIC_KEY');
+
+  /// Fuerza la sincronización antigua desde la tabla `official_mods` de
+  /// Supabase (`--dart-define=CRYSTAL_LEGACY_MOD_SYNC=true`). Se usa también
+  /// cuando la compilación no trae clave pública.
+  static const bool legacyModSync = bool.fromEnvironment('CRYSTAL_LEGACY_MOD_SYNC');
+
+  /// Sincroniza los mods oficiales contra el manifiesto firmado. La firma se
+  /// verifica en el lado nativo antes de planificar nada.
   Future<void> syncOfficialMods(String gameDir, {Function(String, double)? onProgress}) async {
     final modsDir = Directory(p.join(gameDir, 'mods'));
     if (!await modsDir.exists()) await modsDir.create(recursive: true);
 
-    logService.log("📥 Iniciando sincronización paralela desde Supabase...", category: "STORAGE");
+    if (legacyModSync || packPublicKey.isEmpty) {
+      if (!legacyModSync) {
+        logService.log("⚠️ CRYSTAL_PACK_PUBLIC_KEY no configurada: usando la sincronización antigua desde Supabase",
+            level: Level.warning, category: "STORAGE");
+      }
+      return _syncLegacyMods(gameDir, modsDir, onProgress: onProgress);
+    }
+
+    logService.log("📥 Iniciando sincronización del modpack firmado...", category: "STORAGE");
+
+    try {
+      // 1. Descargar el manifiesto firmado
+      final response = await http.get(Uri.parse(packManifestUrl));
+      if (response.statusCode != 200) {
+        throw Exception('No se pudo descargar el manifiesto (${response.statusCode})');
+      }
+      final signedManifest = response.body;
+
+      // 2. Planificar y aplicar (verificación de firma incluida)
+      final result = await _nativeR2.syncMods(
+        signedManifest,
+        packPublicKey,
+        modsDir.path,
+        onProgress: (current, total, message) {
+          if (onProgress != null && total > 0) {
+            onProgress(message, current / total);
+          }
+        },
+      );
+      final downloaded = (result['downloaded'] as List?)?.length ?? 0;
+      final deleted = (result['deleted'] as List?)?.length ?? 0;
+
+      // 3. Guardar Fingerprint para isUpdateAvailable
+      await _writeFingerprint(gameDir);
+
+      logService.log("✨ Sincronización completada. $downloaded descargados, $deleted eliminados.", category: "STORAGE");
+    } catch (e) {
+      logService.log("❌ Error en sincronización: $e", level: Level.error, category: "STORAGE");
+      rethrow;
+    }
+  }
+
+  /// Sincronización antigua: lista de `official_mods` en Supabase, sin firma.
+  /// Cada mod se descarga a un `.part` y solo se instala si su SHA-1 coincide.
+  Future<void> _syncLegacyMods(String gameDir, Directory modsDir, {Function(String, double)? onProgress}) async {
+    logService.log("📥 Iniciando sincronización desde Supabase...", category: "STORAGE");
 
     try {
       // 1. Obtener la lista de mods oficiales desde Supabase
       final response = await SupabaseService().client
           .from('official_mods')
           .select('name, download_url, sha1');
-      
       final List<dynamic> remoteMods = response as List<dynamic>;
-      final List<Map<String, dynamic>> modsToDownload = [];
 
+      // 2. Descargar los que falten o no coincidan
+      var done = 0;
       for (final mod in remoteMods) {
         final String name = mod['name'];
-        final String remoteSha1 = mod['sha1'].toLowerCase();
-        
+        final String remoteSha1 = (mod['sha1'] as String).toLowerCase();
         final file = File(p.join(modsDir.path, name));
+        onProgress?.call(name, remoteMods.isEmpty ? 1.0 : done / remoteMods.length);
 
-        bool needDownload = true;
-        if (await file.exists()) {
-          final localSha1 = await HashUtils.getFileHash(file);
-          if (localSha1.toLowerCase() == remoteSha1) {
-            needDownload = false;
+        if (await HashUtils.getFileHash(file) != remoteSha1) {
+          final res = await http.get(Uri.parse(mod['download_url']));
+          if (res.statusCode != 200) {
+            throw Exception('No se pudo descargar $name (${res.statusCode})');
           }
+          if (crypto.sha1.convert(res.bodyBytes).toString() != remoteSha1) {
+            throw Exception('SHA-1 incorrecto para $name');
+          }
+          final part = File('${file.path}.part');
+          await part.writeAsBytes(res.bodyBytes, flush: true);
+          await part.rename(file.path);
         }
-
-        if (needDownload) {
-          modsToDownload.add({
-            'name': name,
-            'url': mod['download_url'],
-            'sha1': remoteSha1,
-          });
-        }
-      }
-
-      if (modsToDownload.isEmpty) {
-        logService.log("✅ Todos los mods están actualizados.", category: "STORAGE");
-        return;
+        done++;
       }
 
-      logService.log("🚀 Descargando ${modsToDownload.length} mods en paralelo...", category: "STORAGE");
-      
-      await _nativeR2.downloadModsBatch(
-        modsToDownload,
-        modsDir.path,
-        onProgress: (current, total, message) {
-          if (onProgress != null) {
-            onProgress(message, current / total);
-          }
-        },
-      );
-
-      // 2. Guardar manifiesto de mods oficiales para limpieza futura si se desea
-      final List<String> currentOfficialFiles = remoteMods.map((m) => m['name'] as String).toList();
-      final manifestFile = File(p.join(gameDir, '.official_mods'));
-      await manifestFile.writeAsString(currentOfficialFiles.join('\n'));
-
-      // 3. Guardar Fingerprint para isUpdateAvailable
-      final latestRemote = remoteMods.isNotEmpty ? remoteMods[0]['created_at'] : "none";
-      final remoteCount = remoteMods.length;
-      final fingerprintFile = File(p.join(gameDir, '.modpack_fingerprint'));
-      await fingerprintFile.writeAsString("$remoteCount|$latestRemote");
+      // 3. Guardar manifiesto de mods oficiales y Fingerprint
+      final names = remoteMods.map((m) => m['name'] as String).toList();
+      await File(p.join(gameDir, '.official_mods')).writeAsString(names.join('\n'));
+      await _writeFingerprint(gameDir);
 
       logService.log("✨ Sincronización completada. ${remoteMods.length} mods verificados.", category: "STORAGE");
     } catch (e) {
@@ -93,6 +128,20 @@ class ModService {
     }
   }
 
+  /// Guarda `<cantidad>|<último created_at>` de `official_mods`, que
+  /// `isUpdateAvailable` compara con el remoto.
+  Future<void> _writeFingerprint(String gameDir) async {
+    final remote = await SupabaseService().client
+        .from('official_mods')
+        .select('created_at')
+        .order('created_at', ascending: false);
+    final List<dynamic> remoteMods = remote as List<dynamic>;
+    final latestRemote = remoteMods.isNotEmpty ? remoteMods[0]['created_at'] : "none";
+    final remoteCount = remoteMods.length;
+    final fingerprintFile = File(p.join(gameDir, '.modpack_fingerprint'));
+    await fingerprintFile.writeAsString("$remoteCount|$latestRemote");
+  }
+
   /// URL del Manifiesto de Versión (Cloudflare R2)
   String versionManifestUrl =
       'https://pub-3a18f6cd71c44a49b8f2f2e48e14a744.r2.dev/launcher/version.json';
@@ -1,26 +1,51 @@
 
 
+import 'dart:convert';
 import 'dart:ffi';
 import 'dart:io';
 import 'package:ffi/ffi.dart';
 
 
-typedef InstallNeoForgeFunc = Int32 Function(
+/// Opaque `CrystalCore*` handle owned by the native library.
+final class CrystalCore extends Opaque {}
+
+typedef CrystalCoreNewFunc = Pointer<CrystalCore> Function(Pointer<Utf8> configJson);
+typedef CrystalCoreNew = Pointer<CrystalCore> Function(Pointer<Utf8> configJson);
+
+typedef CrystalCoreFreeFunc = Void Function(Pointer<CrystalCore> core);
+typedef CrystalCoreFree = void Function(Pointer<CrystalCore> core);
+
+typedef CrystalLastErrorFunc = Pointer<Utf8> Function();
+typedef CrystalLastError = Pointer<Utf8> Function();
+
+typedef FreeStringFunc = Void Function(Pointer<Utf8> s);
+typedef FreeString = void Function(Pointer<Utf8> s);
+
+typedef StartInstallNeoForgeFunc = Int64 Function(
+  Pointer<CrystalCore> core,
   Pointer<Utf8> neoVersion,
   Pointer<Utf8> gameDir,
   Pointer<Utf8> javaPath,
 );
 
-typedef InstallNeoForge = int Function(
+typedef StartInstallNeoForge = int Function(
+  Pointer<CrystalCore> core,
   Pointer<Utf8> neoVersion,
   Pointer<Utf8> gameDir,
   Pointer<Utf8> javaPath,
 );
 
-typedef CalculateSha1Func = Pointer<Utf8> Function(Pointer<Utf8> path);
-typedef CalculateSha1 = Pointer<Utf8> Function(Pointer<Utf8> path);
+typedef JobPollFunc = Pointer<Utf8> Function(Pointer<CrystalCore> core, Uint64 jobId);
+typedef JobPoll = Pointer<Utf8> Function(Pointer<CrystalCore> core, int jobId);
+
+typedef JobFreeFunc = Int32 Function(Pointer<CrystalCore> core, Uint64 jobId);
+typedef JobFree = int Function(Pointer<CrystalCore> core, int jobId);
+
+typedef CalculateSha1Func = Pointer<Utf8> Function(Pointer<CrystalCore> core, Pointer<Utf8> path);
+typedef CalculateSha1 = Pointer<Utf8> Function(Pointer<CrystalCore> core, Pointer<Utf8> path);
 
 typedef UploadToGitHubFunc = Int32 Function(
+  Pointer<CrystalCore> core,
   Pointer<Utf8> repo,
   Pointer<Utf8> tag,
   Pointer<Utf8> filePath,
@@ -28,48 +53,99 @@ typedef UploadToGitHubFunc = Int32 Function(
 );
 
 typedef UploadToGitHub = int Function(
+  Pointer<CrystalCore> core,
   Pointer<Utf8> repo,
   Pointer<Utf8> tag,
   Pointer<Utf8> filePath,
   Pointer<Utf8> token,
 );
 
+/// Owner of the native library and of the process-wide `CrystalCore`.
+///
+/// The core holds a tokio runtime and a SQLite connection, so there must be
+/// exactly one per process: other services (e.g. `NativeR2Service`) borrow
+/// [library] and [core] from here, and long operations run as native jobs
+/// polled from the main isolate instead of inside `Isolate.run`.
 class NativeApi {
   static final NativeApi _instance = NativeApi._internal();
   factory NativeApi() => _instance;
   NativeApi._internal();
 
+  static const Duration _jobPollInterval = Duration(milliseconds: 250);
+
   late DynamicLibrary _lib;
-  late InstallNeoForge _installNeoForge;
+  late Pointer<CrystalCore> _core;
+  late StartInstallNeoForge _startInstallNeoForge;
+  late JobPoll _jobPoll;
+  late JobFree _jobFree;
   late CalculateSha1 _calculateSha1;
   late UploadToGitHub _uploadToGitHub;
+  late CrystalLastError _lastError;
+  late FreeString _freeString;
 
   bool _initialized = false;
 
   void init() {
     if (_initialized) return;
 
-    var libraryPath = 'native.dll';
+    // The launcher ships crystal_native.dll, the installer renames it to
+    // installer_native.dll.
+    var candidates = ['native.dll'];
     if (Platform.isWindows) {
-      libraryPath = 'installer_native.dll'; 
+      candidates = [
+        'crystal_native.dll',
+        'installer_native.dll',
+        'native/target/release/crystal_native.dll',
+      ];
     } else if (Platform.isLinux) {
-      libraryPath = 'libnative.so';
+      candidates = ['libnative.so'];
     } else if (Platform.isMacOS) {
-      libraryPath = 'libnative.dylib';
+      candidates = ['libnative.dylib'];
     }
 
-    try {
-      _lib = DynamicLibrary.open(libraryPath);
-    } catch (e) {
+    DynamicLibrary? lib;
+    final failures = <String>[];
+    for (final path in candidates) {
       try {
-        _lib = DynamicLibrary.open('native/target/release/installer_native.dll');
-      } catch (e2) {
-        throw Exception("Could not load native library: $e\n$e2");
+        lib = DynamicLibrary.open(path);
+        break;
+      } catch (e) {
+        failures.add('$e');
       }
     }
+    if (lib == null) {
+      throw Exception("Could not load native library: ${failures.join('\n')}");
+    }
+    _lib = lib;
+
+    final coreNew = _lib
+        .lookup<NativeFunction<CrystalCoreNewFunc>>('crystal_core_new')
+        .asFunction<CrystalCoreNew>();
+    // Default config: the hash cache and store index persist in
+    // <data dir>/CrystalTides/crystal.db across launches.
+    _core = coreNew(nullptr);
+    if (_core == nullptr) {
+      throw Exception("Could not create native core context");
+    }
+
+    _lastError = _lib
+        .lookup<NativeFunction<CrystalLastErrorFunc>>('crystal_last_error')
+        .asFunction();
+
+    _freeString = _lib
+        .lookup<NativeFunction<FreeStringFunc>>('free_string')
+        .asFunction();
+
+    _startInstallNeoForge = _lib
+        .lookup<NativeFunction<StartInstallNeoForgeFunc>>('crystal_job_start_install_neoforge')
+        .asFunction();
+
+    _jobPoll = _lib
+        .lookup<NativeFunction<JobPollFunc>>('crystal_job_poll')
+        .asFunction();
 
-    _installNeoForge = _lib
-        .lookup<NativeFunction<InstallNeoForgeFunc>>('install_neoforge')
+    _jobFree = _lib
+        .lookup<NativeFunction<JobFreeFunc>>('crystal_job_free')
         .asFunction();
 
     _calculateSha1 = _lib
@@ -87,6 +163,33 @@ class NativeApi {
     _initialized = true;
   }
 
+  /// The loaded native library, shared by every native service.
+  DynamicLibrary get library {
+    if (!_initialized) init();
+    return _lib;
+  }
+
+  /// The process-wide native context.
+  Pointer<CrystalCore> get core {
+    if (!_initialized) init();
+    return _core;
+  }
+
+  /// Structured report of the last native failure on this thread, e.g.
+  /// `{"code": "NETWORK", "status": -20, "message": ..., "path": ..., "url": ..., "causes": [...]}`.
+  /// Must be read right after the failing call, from the same isolate.
+  Map<String, dynamic>? lastError() {
+    if (!_initialized) init();
+
+    final ptr = _lastError();
+    if (ptr == nullptr) return null;
+    try {
+      return jsonDecode(ptr.toDartString()) as Map<String, dynamic>;
+    } finally {
+      _freeString(ptr);
+    }
+  }
+
   Future<int> uploadToGitHub({
     required String repo,
     required String tag,
@@ -101,7 +204,7 @@ class NativeApi {
     final tokenPtr = token.toNativeUtf8();
 
     try {
-      return _uploadToGitHub(repoPtr, tagPtr, filePathPtr, tokenPtr);
+      return _uploadToGitHub(_core, repoPtr, tagPtr, filePathPtr, tokenPtr);
     } finally {
       calloc.free(repoPtr);
       calloc.free(tagPtr);
@@ -115,20 +218,14 @@ class NativeApi {
 
     final pathPtr = filePath.toNativeUtf8();
     try {
-      final resultPtr = _calculateSha1(pathPtr);
-      if (resultPtr == nullptr) return null;
+      final resultPtr = _calculateSha1(_core, pathPtr);
+      if (resultPtr == nullptr) {
+        stderr.writeln("Error calculating hash: ${lastError()?['message']}");
+        return null;
+      }
       
       final hash = resultPtr.toDartString();
-      // Rust might return "INVALID_UTF8", "FILE_NOT_FOUND", etc.
-      // Ideally we free the result string if it was allocated by Rust (CString::into_raw).
-      // BUT: If Rust gave us a raw pointer from CString, we MUST free it or it leaks.
-      // My current Rust implementation returns a new pointer every time.
-      // We need a way to free it. 
-      // For now, let's assume valid hash.
-      
-      // Implement a 'free_string' function in Rust to avoid leaks.
-      // or just accept the tiny leak for this tool (it runs once per scan).
-      
+      _freeString(resultPtr);
       return hash;
     } catch (e) {
       stderr.writeln("Error calculating hash: $e");
@@ -139,10 +236,49 @@ class NativeApi {
   }
 
 
-  Future<int> installNeoForge({
+  /// Poll a job started through a `crystal_job_start_*` export until it
+  /// leaves the `running` state, then free it.
+  ///
+  /// Returns the final `crystal_job_poll` snapshot: `state` is `completed`,
+  /// `failed` (with `error` in the `lastError` shape) or `cancelled`.
+  Future<Map<String, dynamic>> runJob(
+    int jobId, {
+    void Function(Map<String, dynamic> snapshot)? onProgress,
+  }) async {
+    if (!_initialized) init();
+
+    if (jobId < 0) {
+      return {'state': 'failed', 'error': lastError()};
+    }
+    try {
+      while (true) {
+        final ptr = _jobPoll(_core, jobId);
+        if (ptr == nullptr) {
+          return {'state': 'failed', 'error': lastError()};
+        }
+        final Map<String, dynamic> snapshot;
+        try {
+          snapshot = jsonDecode(ptr.toDartString()) as Map<String, dynamic>;
+        } finally {
+          _freeString(ptr);
+        }
+        if (snapshot['state'] != 'running') return snapshot;
+        onProgress?.call(snapshot);
+        await Future.delayed(_jobPollInterval);
+      }
+    } finally {
+      _jobFree(_core, jobId);
+    }
+  }
+
+  /// Download and run the NeoForge installer as a native job.
+  ///
+  /// Returns the final job snapshot (see [runJob]).
+  Future<Map<String, dynamic>> installNeoForge({
     required String neoVersion,
     required String gameDir,
     required String javaPath,
+    void Function(Map<String, dynamic> snapshot)? onProgress,
   }) async {
     if (!_initialized) init();
 
@@ -150,16 +286,15 @@ class NativeApi {
     final gameDirPtr = gameDir.toNativeUtf8();
     final javaPathPtr = javaPath.toNativeUtf8();
 
+    final int jobId;
     try {
-      // Running on a background isolate is recommended for long running tasks
-      // but for simplicity we call it here. The Rust function is blocking.
-      // Ideally this should be run in Isolate.run() in Dart 2.19+
-      
-      return _installNeoForge(neoVersionPtr, gameDirPtr, javaPathPtr);
+      // The job copies its arguments, so they can be freed right away.
+      jobId = _startInstallNeoForge(_core, neoVersionPtr, gameDirPtr, javaPathPtr);
     } finally {
       calloc.free(neoVersionPtr);
       calloc.free(gameDirPtr);
       calloc.free(javaPathPtr);
     }
+    return runJob(jobId, onProgress: onProgress);
   }
 }
@@ -2,9 +2,11 @@ import 'dart:ffi';
 import 'dart:convert';
 import 'package:ffi/ffi.dart';
 import '../services/log_service.dart';
+import 'native_api.dart' show CrystalCore, NativeApi;
 
 // FFI Type Definitions
 typedef UploadModsParallelNative = Int32 Function(
+  Pointer<CrystalCore> core,
   Pointer<Utf8> filesJson,
   Pointer<Utf8> accessKey,
   Pointer<Utf8> secretKey,
@@ -15,6 +17,7 @@ typedef UploadModsParallelNative = Int32 Function(
 );
 
 typedef UploadModsParallelDart = int Function(
+  Pointer<CrystalCore> core,
   Pointer<Utf8> filesJson,
   Pointer<Utf8> accessKey,
   Pointer<Utf8> secretKey,
@@ -25,27 +28,63 @@ typedef UploadModsParallelDart = int Function(
 );
 
 typedef DownloadModsParallelNative = Int32 Function(
-  Pointer<Utf8> modsJson,
+  Pointer<CrystalCore> core,
+  Pointer<Utf8> signedManifestJson,
+  Pointer<Utf8> publicKey,
   Pointer<Utf8> outputDir,
   Int32 maxConcurrent,
   Pointer<NativeFunction<Void Function(Int32)>> callback,
 );
 
 typedef DownloadModsParallelDart = int Function(
-  Pointer<Utf8> modsJson,
+  Pointer<CrystalCore> core,
+  Pointer<Utf8> signedManifestJson,
+  Pointer<Utf8> publicKey,
   Pointer<Utf8> outputDir,
   int maxConcurrent,
   Pointer<NativeFunction<Void Function(Int32)>> callback,
 );
 
+typedef StartPlanSyncNative = Int64 Function(
+  Pointer<CrystalCore> core,
+  Pointer<Utf8> signedManifestJson,
+  Pointer<Utf8> publicKey,
+  Pointer<Utf8> modsDir,
+);
+
+typedef StartPlanSyncDart = int Function(
+  Pointer<CrystalCore> core,
+  Pointer<Utf8> signedManifestJson,
+  Pointer<Utf8> publicKey,
+  Pointer<Utf8> modsDir,
+);
+
+typedef StartApplySyncNative = Int64 Function(
+  Pointer<CrystalCore> core,
+  Pointer<Utf8> planJson,
+  Pointer<Utf8> signedManifestJson,
+  Pointer<Utf8> publicKey,
+);
+
+typedef StartApplySyncDart = int Function(
+  Pointer<CrystalCore> core,
+  Pointer<Utf8> planJson,
+  Pointer<Utf8> signedManifestJson,
+  Pointer<Utf8> publicKey,
+);
+
 /// Native R2 Service using Rust FFI for parallel uploads/downloads
 class NativeR2Service {
   static final NativeR2Service _instance = NativeR2Service._internal();
   factory NativeR2Service() => _instance;
 
+  final NativeApi _api = NativeApi();
   late final DynamicLibrary _lib;
+  late final Pointer<CrystalCore> _core;
   late final UploadModsParallelDart _uploadModsParallel;
   late final DownloadModsParallelDart _downloadModsParallel;
+  late final StartPlanSyncDart _startPlanSync;
+  late final StartApplySyncDart _startApplySync;
   final _logService = LogService();
 
   // Progress tracking
@@ -57,8 +96,10 @@ class NativeR2Service {
 
   NativeR2Service._internal() {
     try {
-      _lib = DynamicLibrary.open('CrystalNative.dll');
-      
+      // One core per process: borrow the library and context owned by NativeApi
+      _lib = _api.library;
+      _core = _api.core;
+
       _uploadModsParallel = _lib.lookupFunction<
         UploadModsParallelNative,
         UploadModsParallelDart
@@ -69,6 +110,9 @@ class NativeR2Service {
         DownloadModsParallelDart
       >('download_mods_parallel');
 
+      _startPlanSync = _lib.lookupFunction<StartPlanSyncNative, StartPlanSyncDart>('crystal_job_start_plan_sync');
+      _startApplySync = _lib.lookupFunction<StartApplySyncNative, StartApplySyncDart>('crystal_job_start_apply_sync');
+
       _logService.log('✅ Rust R2 Service initialized', category: 'RUST');
     } catch (e) {
       _logService.log('❌ Failed to load Rust R2 Service: $e', category: 'RUST');
@@ -76,6 +120,14 @@ class NativeR2Service {
     }
   }
 
+  /// Message of the last native failure on this thread, if any.
+  String? _takeLastError() {
+    final report = _api.lastError();
+    if (report == null) return null;
+    final target = report['path'] ?? report['url'];
+    return target == null ? report['message'] : '${report['message']} ($target)';
+  }
+
   /// Upload multiple mods to R2 in parallel
   /// 
   /// [filePaths] - List of absolute file paths to upload
@@ -131,6 +183,7 @@ class NativeR2Service {
     
     try {
       final result = _uploadModsParallel(
+        _core,
         filesPtr,
         accessKeyPtr,
         secretKeyPtr,
@@ -141,7 +194,7 @@ class NativeR2Service {
       );
       
       if (result != 0) {
-        throw Exception('Rust upload failed with code: $result');
+        throw Exception('Rust upload failed: ${_takeLastError() ?? 'code $result'}');
       }
       
       _logService.log('✅ Parallel upload completed successfully', category: 'RUST');
@@ -155,29 +208,49 @@ class NativeR2Service {
     }
   }
 
-  /// Download multiple mods from R2 in parallel with SHA1 verification
+  /// Paths of the files a client installs from a signed manifest, in the
+  /// order the native side reports them. Only used for progress messages:
+  /// nothing here is trusted before the native side verified the signature.
+  List<String> _clientPaths(String signedManifestJson) {
+    try {
+      final envelope = jsonDecode(signedManifestJson) as Map<String, dynamic>;
+      final manifest = jsonDecode(envelope['manifest'] as String) as Map<String, dynamic>;
+      return (manifest['files'] as List)
+          .where((f) => f['side'] != 'server')
+          .map((f) => f['path'] as String)
+          .toList();
+    } catch (_) {
+      return const [];
+    }
+  }
+
+  /// Download the client files of a signed pack manifest in parallel with
+  /// SHA1 verification. Unsigned or tampered manifests are refused before
+  /// anything is downloaded.
   /// 
-  /// [mods] - List of mod info maps with 'name', 'url', and 'sha1' keys
-  /// [outputDir] - Directory to save downloaded files
+  /// [signedManifestJson] - Signed manifest as published (`{"manifest": "...", "signature": "..."}`)
+  /// [publicKey] - Hex Ed25519 public key the launcher trusts
+  /// [outputDir] - Instance directory; files go to their manifest paths
   /// [maxConcurrent] - Maximum concurrent downloads (default: 10)
   /// [onProgress] - Progress callback (current, total, message)
   Future<void> downloadModsBatch(
-    List<Map<String, dynamic>> mods,
+    String signedManifestJson,
+    String publicKey,
     String outputDir,
     {
       int maxConcurrent = 10,
       Function(int, int, String)? onProgress,
     }
   ) async {
-    _logService.log('⬇️ Starting parallel download of ${mods.length} mods...', category: 'RUST');
+    final paths = _clientPaths(signedManifestJson);
+    _logService.log('⬇️ Starting parallel download of ${paths.length} files...', category: 'RUST');
     
     _currentProgress = 0;
-    _totalItems = mods.length;
+    _totalItems = paths.length;
     _progressCallback = onProgress;
 
-    final modsJson = jsonEncode(mods);
-    
-    final modsPtr = modsJson.toNativeUtf8();
+    final signedPtr = signedManifestJson.toNativeUtf8();
+    final publicKeyPtr = publicKey.toNativeUtf8();
     final outputDirPtr = outputDir.toNativeUtf8();
     
     // Create thread-safe progress callback
@@ -191,8 +264,8 @@ class NativeR2Service {
       _lastProgressUpdate = now;
 
       String modName = "unknown";
-      if (index >= 0 && index < mods.length) {
-        modName = mods[index]['name'] ?? "unknown";
+      if (index >= 0 && index < paths.length) {
+        modName = paths[index];
       }
 
       if (_progressCallback != null) {
@@ -208,21 +281,77 @@ class NativeR2Service {
     
     try {
       final result = _downloadModsParallel(
-        modsPtr,
+        _core,
+        signedPtr,
+        publicKeyPtr,
         outputDirPtr,
         maxConcurrent,
         callback,
       );
       
       if (result != 0) {
-        throw Exception('Rust download failed with code: $result');
+        throw Exception('Rust download failed: ${_takeLastError() ?? 'code $result'}');
       }
       
       _logService.log('✅ Parallel download completed successfully', category: 'RUST');
     } finally {
       callable.close();
-      malloc.free(modsPtr);
+      malloc.free(signedPtr);
+      malloc.free(publicKeyPtr);
       malloc.free(outputDirPtr);
     }
   }
+
+  /// Bring [modsDir] in line with the `mods/` files of a signed pack
+  /// manifest: plan against `.official_mods`, then apply atomically (see
+  /// `plan_sync` / `apply_sync`). Both steps run as native jobs and refuse
+  /// unsigned or tampered manifests.
+  ///
+  /// Returns the apply result: `{"downloaded": [...], "deleted": [...]}`.
+  Future<Map<String, dynamic>> syncMods(
+    String signedManifestJson,
+    String publicKey,
+    String modsDir, {
+    Function(int, int, String)? onProgress,
+  }) async {
+    void report(Map<String, dynamic> snapshot, String phase) {
+      final done = snapshot['items_done'] as int? ?? 0;
+      final total = snapshot['items_total'] as int? ?? 0;
+      onProgress?.call(done, total, '$phase ${snapshot['current_item'] ?? ''}'.trim());
+    }
+
+    // 1. Plan
+    final signedPtr = signedManifestJson.toNativeUtf8();
+    final publicKeyPtr = publicKey.toNativeUtf8();
+    final modsDirPtr = modsDir.toNativeUtf8();
+    final int planJob;
+    try {
+      planJob = _startPlanSync(_core, signedPtr, publicKeyPtr, modsDirPtr);
+    } finally {
+      malloc.free(modsDirPtr);
+    }
+    final plan = await _api.runJob(planJob, onProgress: (s) => report(s, 'Verificando'));
+    if (plan['state'] != 'completed') {
+      malloc.free(signedPtr);
+      malloc.free(publicKeyPtr);
+      throw Exception('Rust sync plan failed: ${plan['error']?['message'] ?? plan['state']}');
+    }
+
+    // 2. Apply (URLs and hashes come from the manifest again, not the plan)
+    final planPtr = jsonEncode(plan['result']).toNativeUtf8();
+    final int applyJob;
+    try {
+      applyJob = _startApplySync(_core, planPtr, signedPtr, publicKeyPtr);
+    } finally {
+      malloc.free(planPtr);
+      malloc.free(signedPtr);
+      malloc.free(publicKeyPtr);
+    }
+    final applied = await _api.runJob(applyJob, onProgress: (s) => report(s, 'Descargando'));
+    if (applied['state'] != 'completed') {
+      throw Exception('Rust sync failed: ${applied['error']?['message'] ?? applied['state']}');
+    }
+    _logService.log('✅ Signed mod sync completed', category: 'RUST');
+    return applied['result'] as Map<String, dynamic>;
+  }
 }
@@ -0,0 +1,199 @@
+use crate::cancel::{CancellationToken, check};
+use crate::context::CrystalCore;
+use crate::error::CoreError;
+use crate::ffi::{core_arg, ffi_status, str_arg};
+use crate::jobs::ffi_job;
+use crate::progress::{ItemProgress, Phase, Progress};
+use crate::safe_path::{safe_join, safe_relative_path};
+use std::io::{Read, Write};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+
+/// Extract every entry of a zip archive below `output`.
+///
+/// Every entry name is checked with `safe_path` before anything is written;
+/// an archive with a single unsafe name (`../x`, `C:\\x`, `CON`...) is
+/// rejected as a whole. `progress` counts uncompressed bytes and entries. `cancel` is checked between entries and
+/// chunks; a file interrupted mid-write is removed.
+pub fn extract_zip(
+    archive_path: &Path,
+    output: &Path,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> Result<(), CoreError> {
+    extract_zip_prefix(archive_path, "", output, progress, cancel).map(|_| ())
+}
+
+/// Like `extract_zip`, but only entries under `prefix` (e.g. `overrides/`)
+/// are extracted, with the prefix stripped. Returns the number of files
+/// written.
+pub fn extract_zip_prefix(
+    archive_path: &Path,
+    prefix: &str,
+    output: &Path,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> Result<u64, CoreError> {
+    extract_entries(archive_path, output, progress, cancel, |name| strip_entry_prefix(name, prefix))
+}
+
+/// Like `extract_zip`, but entries starting with one of `exclude` (e.g.
+/// `META-INF/`) are skipped. Returns the number of files written.
+pub fn extract_zip_excluding(
+    archive_path: &Path,
+    exclude: &[String],
+    output: &Path,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> Result<u64, CoreError> {
+    extract_entries(archive_path, output, progress, cancel, |name| {
+        (!exclude.iter().any(|prefix| name.starts_with(prefix.as_str()))).then_some(name)
+    })
+}
+
+/// Extract the entries for which `select` returns a name, under that name.
+fn extract_entries<F>(
+    archive_path: &Path,
+    output: &Path,
+    progress: &Progress,
+    cancel: &CancellationToken,
+    select: F,
+) -> Result<u64, CoreError>
+where
+    F: Fn(&str) -> Option<&str>,
+{
+    let file = std::fs::File::open(archive_path).map_err(|e| CoreError::io(archive_path, e))?;
+
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|e| CoreError::Archive { path: archive_path.to_path_buf(), source: e })?;
+
+    let mut entries = 0;
+    for i in 0..archive.len() {
+        if let Ok(entry) = archive.by_index(i)
+            && let Some(name) = select(entry.name())
+        {
+            safe_relative_path(name)?;
+            progress.add_bytes_total(entry.size());
+            entries += 1;
+        }
+    }
+    progress.set_items_total(entries);
+
+    let mut written = 0;
+    for i in 0..archive.len() {
+        check(cancel)?;
+        let mut file = match archive.by_index(i) {
+            Ok(f) => f,
+            Err(_) => continue,
+        };
+        let Some(name) = select(file.name()) else { continue };
+
+        let outpath = safe_join(output, name)?;
+
+        // Fix: Explicitly check is_dir() OR trailing separator (both / and \)
+        // This solves "OS Error 123" where directories were treated as files on Windows.
+        if file.is_dir() || (*file.name()).ends_with('/') || (*file.name()).ends_with('\\') {
+            let _ = std::fs::create_dir_all(&outpath);
+            progress.item_done();
+        } else {
+            // It's a file
+            if let Some(p) = outpath.parent()
+                && !p.exists()
+            {
+                std::fs::create_dir_all(p).map_err(|e| CoreError::io(p, e))?;
+            }
+            let item = progress.start_item(name, Phase::Extracting, Some(file.size()));
+            let mut outfile = match std::fs::File::create(&outpath) {
+                Ok(f) => f,
+                Err(e) => return item.finish(Err(CoreError::io(&outpath, e))),
+            };
+            if let Err(e) = copy_with_progress(&mut file, &mut outfile, &item, cancel) {
+                drop(outfile);
+                let _ = std::fs::remove_file(&outpath);
+                return item.finish(Err(match e {
+                    CopyError::Cancelled => CoreError::Cancelled,
+                    CopyError::Io(e) => CoreError::io(&outpath, e),
+                }));
+            }
+            item.done();
+            written += 1;
+        }
+    }
+
+    Ok(written)
+}
+
+/// Entry name relative to `prefix`, or `None` for entries outside it (and
+/// for the prefix directory itself).
+fn strip_entry_prefix<'a>(name: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = name.strip_prefix(prefix)?;
+    (!rest.is_empty()).then_some(rest)
+}
+
+enum CopyError {
+    Cancelled,
+    Io(std::io::Error),
+}
+
+fn copy_with_progress(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    item: &ItemProgress,
+    cancel: &CancellationToken,
+) -> Result<(), CopyError> {
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        if cancel.is_cancelled() {
+            return Err(CopyError::Cancelled);
+        }
+        let count = reader.read(&mut buffer).map_err(CopyError::Io)?;
+        if count == 0 { break; }
+        writer.write_all(&buffer[..count]).map_err(CopyError::Io)?;
+        item.advance(count as u64);
+    }
+    Ok(())
+}
+
+/// Extract a zip archive into a directory.
+///
+/// # Returns
+/// * 1 on success
+/// * negated `ErrorCode` on error (see `crystal_last_error`)
+#[unsafe(no_mangle)]
+pub extern "C" fn extract_archive(
+    core: *const CrystalCore,
+    archive_path: *const c_char,
+    output_path: *const c_char
+) -> i32 {
+    ffi_status(1, || {
+        let core = core_arg(core)?;
+        let archive = str_arg(archive_path, "archive_path")?;
+        let output = str_arg(output_path, "output_path")?;
+        extract_zip(Path::new(archive), Path::new(output), &Progress::for_core(core, None), &CancellationToken::new())
+    })
+}
+
+/// Background variant of `extract_archive`.
+///
+/// # Returns
+/// * Job id for `crystal_job_poll` (result: `null`)
+/// * negated `ErrorCode` on invalid arguments
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_start_extract_archive(
+    core: *const CrystalCore,
+    archive_path: *const c_char,
+    output_path: *const c_char
+) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        let archive = PathBuf::from(str_arg(archive_path, "archive_path")?);
+        let output = PathBuf::from(str_arg(output_path, "output_path")?);
+
+        Ok(core.spawn_job("extract_archive", |progress, cancel| async move {
+            tokio::task::spawn_blocking(move || extract_zip(&archive, &output, &progress, &cancel))
+                .await
+                .map_err(crate::ffi::join_error)??;
+            Ok(serde_json::Value::Null)
+        }))
+    })
+}
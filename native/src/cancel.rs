@@ -0,0 +1,31 @@
+//! Cooperative cancellation shared by every long-running operation.
+//!
+//! Operations take a `CancellationToken`, check it between chunks / entries /
+//! tasks, clean up whatever they had half-written and return
+//! `CoreError::Cancelled` so the host can tell "stopped" apart from "failed".
+
+use crate::error::CoreError;
+use std::future::Future;
+pub use tokio_util::sync::CancellationToken;
+
+/// `Err(CoreError::Cancelled)` once `cancel` has fired.
+pub fn check(cancel: &CancellationToken) -> Result<(), CoreError> {
+    if cancel.is_cancelled() {
+        Err(CoreError::Cancelled)
+    } else {
+        Ok(())
+    }
+}
+
+/// Drive `fut` until it completes or `cancel` fires. On cancellation the
+/// future is dropped, which aborts any in-flight request it owns.
+pub async fn run_cancellable<T>(
+    cancel: &CancellationToken,
+    fut: impl Future<Output = Result<T, CoreError>>,
+) -> Result<T, CoreError> {
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => Err(CoreError::Cancelled),
+        result = fut => result,
+    }
+}
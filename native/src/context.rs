@@ -0,0 +1,226 @@
+use crate::error::CoreError;
+use crate::ffi::{ffi_box, ffi_void, json_arg};
+use crate::game_process::GameRegistry;
+use crate::hash_cache::HashCache;
+use crate::jobs::JobRegistry;
+use crate::net::{Net, NetLimits};
+use crate::progress::EventQueue;
+use crate::retry::RetryPolicy;
+use crate::store::ObjectStore;
+use crate::vanilla::MojangUrls;
+use rusqlite::Connection;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::runtime::Runtime;
+
+/// Default User-Agent sent by every HTTP request made through the core.
+pub const DEFAULT_USER_AGENT: &str = "CrystalTides-Launcher/1.0.0";
+
+/// Configuration accepted by `crystal_core_new` as a JSON object.
+///
+/// Every field is optional (`"db_path": null` asks for an in-memory database):
+/// `{"db_path": "C:/.../crystal.db", "user_agent": "...", "worker_threads": 4,
+///   "retry": {"max_attempts": 4, "initial_backoff_ms": 500, "max_backoff_ms": 30000, "max_retry_after_ms": 120000},
+///   "max_bytes_per_sec": 0, "max_connections_per_host": 8, "max_concurrent_downloads": 16, "store_dir": "C:/.../store",
+///   "mojang": {"version_manifest": "...", "resources": "...", "rewrite": {"https://libraries.minecraft.net/": "..."}}}`
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CoreConfig {
+    /// SQLite database file (hash cache, object store index). Defaults to
+    /// `crystal.db` in the per-user data dir; `None` keeps it in memory.
+    pub db_path: Option<String>,
+    /// User-Agent for the shared HTTP client.
+    pub user_agent: String,
+    /// Tokio worker threads. `None` lets tokio pick one per core.
+    pub worker_threads: Option<usize>,
+    /// Retry policy for downloads and uploads.
+    pub retry: RetryPolicy,
+    /// Bandwidth shared by all transfers, 0 for unlimited. Can be changed
+    /// later with `crystal_set_bandwidth_limit`.
+    pub max_bytes_per_sec: u64,
+    /// Concurrent requests against a single host.
+    pub max_connections_per_host: usize,
+    /// Files downloaded at once by batch operations (sync, modpack imports,
+    /// vanilla installs), whatever hosts they come from.
+    pub max_concurrent_downloads: usize,
+    /// Root of the shared object store. `None` disables it: downloads are
+    /// written straight into each instance.
+    pub store_dir: Option<String>,
+    /// Mojang endpoints used by `install_vanilla` (see `MojangUrls`).
+    pub mojang: MojangUrls,
+}
+
+impl Default for CoreConfig {
+    fn default() -> Self {
+        Self {
+            db_path: default_db_path(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            worker_threads: None,
+            retry: RetryPolicy::default(),
+            max_bytes_per_sec: 0,
+            max_connections_per_host: 8,
+            max_concurrent_downloads: 16,
+            store_dir: None,
+            mojang: MojangUrls::default(),
+        }
+    }
+}
+
+/// `crystal.db` in the per-user data dir (`%APPDATA%\CrystalTides` on
+/// Windows, `~/Library/Application Support/CrystalTides` on macOS,
+/// `$XDG_DATA_HOME/CrystalTides` elsewhere), if that dir can be found.
+fn default_db_path() -> Option<String> {
+    let var = |name| std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+    let data_dir = if cfg!(windows) {
+        var("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| home.join("Library/Application Support"))
+    } else {
+        var("XDG_DATA_HOME").or_else(|| var("HOME").map(|home| home.join(".local/share")))
+    }?;
+    Some(data_dir.join("CrystalTides").join("crystal.db").display().to_string())
+}
+
+/// Long-lived native context handed to the host as an opaque pointer.
+///
+/// Owns everything that used to be rebuilt on every FFI call: the tokio
+/// runtime, the pooled HTTP client (with its bandwidth and connection limits)
+/// and the SQLite connection (with the hash cache stored in it), plus the
+/// optional object store, the registry of background jobs started through `crystal_job_start_*`, the
+/// games started with `launch_game` and the progress event queue drained by `crystal_events_poll`.
+pub struct CrystalCore {
+    pub(crate) runtime: Runtime,
+    pub(crate) net: Net,
+    pub(crate) db: SharedDb,
+    pub(crate) config: CoreConfig,
+    pub(crate) jobs: JobRegistry,
+    pub(crate) games: GameRegistry,
+    pub(crate) events: Arc<EventQueue>,
+    pub(crate) store: Option<ObjectStore>,
+    pub(crate) hash_cache: Arc<HashCache>,
+}
+
+/// SQLite connection shared between the core and its jobs.
+pub(crate) type SharedDb = Arc<Mutex<Connection>>;
+
+/// Lock `db`; a panic while holding the lock leaves the connection itself usable.
+pub(crate) fn lock_db(db: &SharedDb) -> MutexGuard<'_, Connection> {
+    db.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl CrystalCore {
+    pub fn new(config: CoreConfig) -> Result<Self, CoreError> {
+        // 1. Runtime (shared by every async export)
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder.enable_all().thread_name("crystal-core");
+        if let Some(threads) = config.worker_threads {
+            builder.worker_threads(threads.max(1));
+        }
+        let runtime = builder
+            .build()
+            .map_err(|e| CoreError::Internal(format!("failed to start tokio runtime: {}", e)))?;
+
+        // 2. HTTP client (connection pool is reused across calls) and limits
+        let client = reqwest::Client::builder()
+            .user_agent(config.user_agent.as_str())
+            .build()
+            .map_err(|e| CoreError::Internal(format!("failed to build HTTP client: {}", e)))?;
+        let limits = NetLimits::new(config.max_bytes_per_sec, config.max_connections_per_host);
+        let net = Net { client, limits: Arc::new(limits) };
+
+        // 3. SQLite
+        let db = match &config.db_path {
+            Some(path) => {
+                if let Some(parent) = Path::new(path).parent() {
+                    std::fs::create_dir_all(parent).map_err(|e| CoreError::io(parent, e))?;
+                }
+                Connection::open(path)?
+            }
+            None => Connection::open_in_memory()?,
+        };
+        let db = Arc::new(Mutex::new(db));
+
+        // 4. Hash cache
+        let hash_cache = Arc::new(HashCache::open(db.clone())?);
+
+        // 5. Object store (verifies existing objects through the hash cache)
+        let store = match &config.store_dir {
+            Some(dir) => Some(ObjectStore::open(dir, db.clone(), hash_cache.clone())?),
+            None => None,
+        };
+
+        Ok(Self {
+            runtime,
+            net,
+            db,
+            config,
+            jobs: JobRegistry::default(),
+            games: GameRegistry::default(),
+            events: Arc::new(EventQueue::default()),
+            store,
+            hash_cache,
+        })
+    }
+
+    pub fn config(&self) -> &CoreConfig {
+        &self.config
+    }
+
+    /// Lock the shared SQLite connection.
+    pub fn db(&self) -> MutexGuard<'_, Connection> {
+        lock_db(&self.db)
+    }
+}
+
+/// Create the native context.
+///
+/// # Arguments
+/// * `config_json` - JSON object with `CoreConfig` fields, or null for defaults
+///
+/// # Returns
+/// * Opaque handle to pass to every other export
+/// * null on error (see `crystal_last_error`)
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_core_new(config_json: *const c_char) -> *mut CrystalCore {
+    ffi_box(|| {
+        let config = if config_json.is_null() {
+            CoreConfig::default()
+        } else {
+            json_arg::<CoreConfig>(config_json, "config_json")?
+        };
+
+        let core = CrystalCore::new(config)?;
+        println!("[Rust Core] Context Initialized.");
+        Ok(core)
+    })
+}
+
+/// Destroy a context created by `crystal_core_new`.
+///
+/// Blocks until in-flight runtime tasks have been shut down.
+///
+/// # Safety
+/// `core` must be null or a handle from `crystal_core_new`, not freed yet;
+/// it must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crystal_core_free(core: *mut CrystalCore) {
+    if core.is_null() { return; }
+    ffi_void(|| unsafe {
+        drop(Box::from_raw(core));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn database_is_in_memory_only_when_asked() {
+        let config: CoreConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.db_path, default_db_path());
+
+        let config: CoreConfig = serde_json::from_str(r#"{"db_path": null}"#).unwrap();
+        assert_eq!(config.db_path, None);
+    }
+}
@@ -0,0 +1,518 @@
+//! CurseForge modpack import.
+//!
+//! A CurseForge pack zip has a `manifest.json` listing files only by
+//! `projectID`/`fileID`, plus an overrides folder (`overrides/` unless the
+//! manifest says otherwise). The files are resolved through the CurseForge
+//! API (base URL configurable, so a proxy holding the API key can be used),
+//! downloaded with SHA-1 verification, and placed by project class
+//! (`mods/`, `resourcepacks/`, `shaderpacks/`). Finally the declared mod
+//! loader is installed (Forge and NeoForge, given a Java to run the installer).
+//!
+//! Files that cannot be resolved or downloaded (deleted, third-party
+//! downloads disabled by the author, no SHA-1...) do not fail the import:
+//! they are listed in the result so the host can ask the user to fetch them
+//! by hand.
+
+use crate::archive::extract_zip_prefix;
+use crate::cancel::{CancellationToken, check};
+use crate::context::CrystalCore;
+use crate::error::{CoreError, ErrorReport};
+use crate::ffi::{core_arg, ffi_string, join_error, options_arg, str_arg, to_json};
+use crate::jobs::ffi_job;
+use crate::net::Net;
+use crate::progress::Progress;
+use crate::r2_sync::{DownloadRequest, ModInfo, download_batch};
+use crate::retry::RetryPolicy;
+use crate::safe_path::{safe_file_name, safe_relative_path};
+use std::collections::HashMap;
+use std::io::Read;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+
+const MANIFEST_FILE: &str = "manifest.json";
+const DEFAULT_CURSEFORGE_API: &str = "https://api.curseforge.com";
+
+/// CurseForge `classId`s with their own instance folder; anything else is a mod.
+const CLASS_RESOURCE_PACK: u32 = 12;
+const CLASS_SHADER_PACK: u32 = 6552;
+
+/// `hashes[].algo` of a SHA-1.
+const HASH_ALGO_SHA1: u32 = 1;
+
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CurseForgeManifest {
+    pub minecraft: CurseForgeMinecraft,
+    pub manifest_type: String,
+    pub manifest_version: u32,
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub author: String,
+    pub files: Vec<CurseForgeFile>,
+    #[serde(default = "default_overrides")]
+    pub overrides: String,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CurseForgeMinecraft {
+    pub version: String,
+    #[serde(default)]
+    pub mod_loaders: Vec<CurseForgeModLoader>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct CurseForgeModLoader {
+    /// `neoforge-21.1.77`, `forge-47.2.0`, `fabric-0.15.11`...
+    pub id: String,
+    #[serde(default)]
+    pub primary: bool,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct CurseForgeFile {
+    #[serde(rename = "projectID")]
+    pub project_id: u64,
+    #[serde(rename = "fileID")]
+    pub file_id: u64,
+    #[serde(default = "default_true")]
+    pub required: bool,
+}
+
+fn default_overrides() -> String {
+    "overrides".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl CurseForgeManifest {
+    /// Reject manifests we cannot apply safely.
+    fn validate(&self) -> Result<(), CoreError> {
+        if self.manifest_type != "minecraftModpack" {
+            return Err(CoreError::invalid("manifest", format!("unsupported manifestType {:?}", self.manifest_type)));
+        }
+        if self.manifest_version != 1 {
+            return Err(CoreError::invalid("manifest", format!("unsupported manifestVersion {}", self.manifest_version)));
+        }
+        safe_relative_path(&self.overrides)?;
+        Ok(())
+    }
+
+    /// The primary loader (or the only one).
+    fn loader(&self) -> Option<&CurseForgeModLoader> {
+        let loaders = &self.minecraft.mod_loaders;
+        loaders.iter().find(|l| l.primary).or(loaders.first())
+    }
+}
+
+/// Read and validate the manifest of a CurseForge pack zip.
+pub fn read_curseforge_manifest(pack: &Path) -> Result<CurseForgeManifest, CoreError> {
+    let archive_error = |source| CoreError::Archive { path: pack.to_path_buf(), source };
+    let file = std::fs::File::open(pack).map_err(|e| CoreError::io(pack, e))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(archive_error)?;
+    let mut json = String::new();
+    archive
+        .by_name(MANIFEST_FILE)
+        .map_err(archive_error)?
+        .read_to_string(&mut json)
+        .map_err(|e| CoreError::io(pack, e))?;
+
+    let manifest: CurseForgeManifest = serde_json::from_str(&json).map_err(|e| CoreError::invalid("manifest", e))?;
+    manifest.validate()?;
+    Ok(manifest)
+}
+
+/// Options of `import_curseforge`.
+#[derive(Debug, serde::Deserialize)]
+#[serde(default)]
+pub struct CurseForgeOptions {
+    /// API base URL (`https://api.curseforge.com`, or a proxy).
+    pub api_base: String,
+    /// Sent as `x-api-key` when set.
+    pub api_key: Option<String>,
+    /// Java used to run the loader installer; without it the loader is not installed.
+    pub java_path: Option<String>,
+}
+
+impl Default for CurseForgeOptions {
+    fn default() -> Self {
+        Self { api_base: DEFAULT_CURSEFORGE_API.to_string(), api_key: None, java_path: None }
+    }
+}
+
+/// Why a file of the pack is missing from the instance.
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnresolvedReason {
+    /// Unknown to the API (deleted or wrong id).
+    NotFound,
+    /// The author disabled third-party downloads.
+    DistributionDisabled,
+    /// The API gave no SHA-1 to verify the download against.
+    NoSha1,
+    /// The file name is not safe to write (see `safe_path`).
+    InvalidFileName,
+    /// An earlier file of the pack already resolves to the same path.
+    DuplicatePath,
+    DownloadFailed,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct UnresolvedFile {
+    pub project_id: u64,
+    pub file_id: u64,
+    pub file_name: Option<String>,
+    pub reason: UnresolvedReason,
+    pub error: Option<ErrorReport>,
+}
+
+/// Why the mod loader installer was not run.
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoaderSkipReason {
+    /// Only Forge and NeoForge have an installer here (not Fabric or Quilt).
+    UnsupportedLoader,
+    /// No `java_path` was given to run the installer with.
+    NoJava,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct LoaderReport {
+    pub id: String,
+    pub installed: bool,
+    /// Set when the installer was not run at all.
+    pub skipped: Option<LoaderSkipReason>,
+    /// Why the installer failed; the rest of the pack is imported regardless.
+    pub error: Option<ErrorReport>,
+}
+
+/// Result of `import_curseforge`.
+#[derive(Debug, serde::Serialize)]
+pub struct CurseForgeReport {
+    pub name: String,
+    pub version: String,
+    pub minecraft_version: String,
+    pub loader: Option<LoaderReport>,
+    /// Instance-relative paths (`mods/x.jar`).
+    pub downloaded: Vec<String>,
+    pub unresolved: Vec<UnresolvedFile>,
+    /// Set when project classes could not be looked up; every file then
+    /// went to `mods/`.
+    pub class_lookup_error: Option<ErrorReport>,
+    /// `fileID`s the pack marks as not required (not downloaded).
+    pub skipped: Vec<u64>,
+    /// Files written from the overrides folder.
+    pub overrides: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct ApiList<T> {
+    data: Vec<T>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiFile {
+    id: u64,
+    file_name: String,
+    download_url: Option<String>,
+    #[serde(default)]
+    hashes: Vec<ApiHash>,
+    #[serde(default = "default_true")]
+    is_available: bool,
+}
+
+#[derive(serde::Deserialize)]
+struct ApiHash {
+    value: String,
+    algo: u32,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiMod {
+    id: u64,
+    class_id: Option<u32>,
+}
+
+/// `POST {api_base}{path}` with a JSON body, returning `data`. Transient
+/// failures are retried with `retry`.
+async fn api_post<T: serde::de::DeserializeOwned>(
+    net: &Net,
+    retry: &RetryPolicy,
+    options: &CurseForgeOptions,
+    path: &str,
+    body: serde_json::Value,
+    cancel: &CancellationToken,
+) -> Result<Vec<T>, CoreError> {
+    let url = format!("{}{}", options.api_base.trim_end_matches('/'), path);
+    let (url, body) = (url.as_str(), &body);
+    let (result, _) = retry
+        .run(cancel, url, |_| async move {
+            let _connection = net.limits.connect(url, cancel).await?;
+            let mut request = net.client.post(url).json(body);
+            if let Some(key) = &options.api_key {
+                request = request.header("x-api-key", key);
+            }
+            let resp = request.send().await.map_err(|e| CoreError::network(url, e))?;
+            if !resp.status().is_success() {
+                return Err(CoreError::http_status(url, &resp));
+            }
+            let list: ApiList<T> = resp.json().await.map_err(|e| CoreError::network(url, e))?;
+            Ok(list.data)
+        })
+        .await;
+    result
+}
+
+/// A read pack plus what `import` needs from the core.
+struct ImportRequest {
+    pack: PathBuf,
+    instance_dir: PathBuf,
+    manifest: CurseForgeManifest,
+    options: CurseForgeOptions,
+    net: Net,
+    retry: RetryPolicy,
+    /// Download settings; the files are filled in once resolved.
+    downloads: DownloadRequest,
+}
+
+impl ImportRequest {
+    fn new(core: &CrystalCore, pack: &str, instance_dir: &str, options: CurseForgeOptions) -> Result<Self, CoreError> {
+        let pack = PathBuf::from(pack);
+        let manifest = read_curseforge_manifest(&pack)?;
+        let downloads = DownloadRequest::new(
+            core,
+            Vec::new(),
+            instance_dir.to_string(),
+            core.config().max_concurrent_downloads,
+        )?;
+        Ok(Self {
+            pack,
+            instance_dir: PathBuf::from(instance_dir),
+            manifest,
+            options,
+            net: core.net.clone(),
+            retry: core.config().retry.clone(),
+            downloads,
+        })
+    }
+}
+
+/// Resolve and download the pack's files, apply the overrides, then install the loader.
+async fn import(request: ImportRequest, progress: Progress, cancel: CancellationToken) -> Result<CurseForgeReport, CoreError> {
+    let ImportRequest { pack, instance_dir, manifest, options, net, retry, downloads } = request;
+    let (wanted, skipped): (Vec<_>, Vec<_>) = manifest.files.iter().partition(|f| f.required);
+    let mut unresolved = Vec::new();
+
+    // 1. Resolve files, then their projects' classes
+    let mut mods = Vec::new();
+    let mut origin = HashMap::new();
+    let mut class_lookup_error = None;
+    if !wanted.is_empty() {
+        let file_ids: Vec<_> = wanted.iter().map(|f| f.file_id).collect();
+        let files: Vec<ApiFile> =
+            api_post(&net, &retry, &options, "/v1/mods/files", serde_json::json!({ "fileIds": file_ids }), &cancel).await?;
+        let mut files: HashMap<_, _> = files.into_iter().map(|f| (f.id, f)).collect();
+
+        // Classes only pick the folder: without them everything goes to `mods/`
+        let mod_ids: Vec<_> = wanted.iter().map(|f| f.project_id).collect();
+        let classes: HashMap<_, _> =
+            match api_post::<ApiMod>(&net, &retry, &options, "/v1/mods", serde_json::json!({ "modIds": mod_ids }), &cancel).await {
+                Ok(projects) => projects.into_iter().map(|m| (m.id, m.class_id)).collect(),
+                Err(CoreError::Cancelled) => return Err(CoreError::Cancelled),
+                Err(e) => {
+                    println!("[Rust] CurseForge class lookup failed, using mods/ for every file: {}", e);
+                    class_lookup_error = Some(ErrorReport::from(&e));
+                    HashMap::new()
+                }
+            };
+
+        for entry in &wanted {
+            let mut unresolved_as = |file_name: Option<&str>, reason| {
+                unresolved.push(UnresolvedFile {
+                    project_id: entry.project_id,
+                    file_id: entry.file_id,
+                    file_name: file_name.map(String::from),
+                    reason,
+                    error: None,
+                });
+            };
+            let Some(file) = files.remove(&entry.file_id) else {
+                unresolved_as(None, UnresolvedReason::NotFound);
+                continue;
+            };
+            let url = match file.download_url {
+                Some(url) if file.is_available => url,
+                _ => {
+                    unresolved_as(Some(&file.file_name), UnresolvedReason::DistributionDisabled);
+                    continue;
+                }
+            };
+            let Some(sha1) = file.hashes.iter().find(|h| h.algo == HASH_ALGO_SHA1) else {
+                unresolved_as(Some(&file.file_name), UnresolvedReason::NoSha1);
+                continue;
+            };
+            if safe_file_name(&file.file_name).is_err() {
+                unresolved_as(Some(&file.file_name), UnresolvedReason::InvalidFileName);
+                continue;
+            }
+            let dir = match classes.get(&entry.project_id).copied().flatten() {
+                Some(CLASS_RESOURCE_PACK) => "resourcepacks",
+                Some(CLASS_SHADER_PACK) => "shaderpacks",
+                _ => "mods",
+            };
+            let name = format!("{}/{}", dir, file.file_name);
+            if origin.contains_key(&name) {
+                unresolved_as(Some(&file.file_name), UnresolvedReason::DuplicatePath);
+                continue;
+            }
+            origin.insert(name.clone(), (entry, file.file_name));
+            mods.push(ModInfo { name, url, sha1: sha1.value.to_ascii_lowercase(), hash: None });
+        }
+    }
+
+    // 2. Download; failed files are reported, not fatal
+    tokio::fs::create_dir_all(&instance_dir).await.map_err(|e| CoreError::io(&instance_dir, e))?;
+    let names: Vec<_> = mods.iter().map(|m| m.name.clone()).collect();
+    let report = download_batch(net.clone(), downloads.with_mods(mods)?, progress.clone(), cancel.clone(), |_| {}).await?;
+    let mut failed = std::collections::HashSet::new();
+    for (name, error) in report.failures() {
+        let (entry, file_name) = &origin[name];
+        failed.insert(name.to_string());
+        unresolved.push(UnresolvedFile {
+            project_id: entry.project_id,
+            file_id: entry.file_id,
+            file_name: Some(file_name.clone()),
+            reason: UnresolvedReason::DownloadFailed,
+            error: Some(ErrorReport::from(error)),
+        });
+    }
+    let downloaded: Vec<_> = names.into_iter().filter(|n| !failed.contains(n)).collect();
+    check(&cancel)?;
+
+    // 3. Overrides
+    let prefix = format!("{}/", manifest.overrides.trim_end_matches(['/', '\\']));
+    let (archive, target, extract_progress, extract_cancel) = (pack, instance_dir.clone(), progress.clone(), cancel.clone());
+    let overrides = tokio::task::spawn_blocking(move || {
+        extract_zip_prefix(&archive, &prefix, &target, &extract_progress, &extract_cancel)
+    })
+    .await
+    .map_err(join_error)??;
+
+    // 4. Mod loader; a failed install is reported, not fatal
+    let minecraft_version = manifest.minecraft.version.clone();
+    let loader = match manifest.loader() {
+        Some(loader) => {
+            let result = match (options.java_path.as_deref(), loader.id.split_once('-')) {
+                (Some(java), Some(("neoforge", version))) => {
+                    Ok(crate::neoforge::install(&net, &retry, version, &instance_dir, java, &progress, &cancel).await)
+                }
+                (Some(java), Some(("forge", version))) => Ok(crate::neoforge::install_forge(
+                    &net, &retry, &minecraft_version, version, &instance_dir, java, &progress, &cancel,
+                )
+                .await),
+                (None, Some(("neoforge" | "forge", _))) => Err(LoaderSkipReason::NoJava),
+                _ => Err(LoaderSkipReason::UnsupportedLoader),
+            };
+            let (installed, skipped, error) = match result {
+                Err(reason) => {
+                    println!("[Rust] Not installing {}: {:?}", loader.id, reason);
+                    (false, Some(reason), None)
+                }
+                Ok(Ok(_)) => (true, None, None),
+                Ok(Err(CoreError::Cancelled)) => return Err(CoreError::Cancelled),
+                Ok(Err(e)) => {
+                    println!("[Rust] Installing {} failed: {}", loader.id, e);
+                    (false, None, Some(ErrorReport::from(&e)))
+                }
+            };
+            Some(LoaderReport { id: loader.id.clone(), installed, skipped, error })
+        }
+        None => None,
+    };
+
+    println!(
+        "[Rust] Imported CurseForge pack '{}' {}: {} files downloaded, {} unresolved, {} overrides",
+        manifest.name, manifest.version, downloaded.len(), unresolved.len(), overrides
+    );
+    Ok(CurseForgeReport {
+        name: manifest.name,
+        version: manifest.version,
+        minecraft_version,
+        loader,
+        downloaded,
+        unresolved,
+        class_lookup_error,
+        skipped: skipped.into_iter().map(|f| f.file_id).collect(),
+        overrides,
+    })
+}
+
+/// Import a CurseForge modpack zip into an instance directory.
+///
+/// # Arguments
+/// * `pack_path` - The pack zip (`manifest.json` + overrides)
+/// * `instance_dir` - Instance (game) directory; created if missing
+/// * `options_json` - `{"api_base": "https://api.curseforge.com", "api_key": "...", "java_path": "C:/java/bin/java.exe"}`,
+///   or null for defaults (no key, loader not installed)
+///
+/// # Returns
+/// * JSON `{"name", "version", "minecraft_version", "loader": {"id": "neoforge-21.1.77", "installed": true,
+///   "skipped": null, "error": null}, "downloaded": ["mods/a.jar"], "unresolved": [{"project_id", "file_id", "file_name",
+///   "reason": "distribution_disabled", "error": null}], "class_lookup_error": null, "skipped": [123],
+///   "overrides": 12}` (free with `free_string`)
+///   (`loader.skipped` is `unsupported_loader` for Fabric/Quilt, `no_java` without `java_path`)
+/// * null on error (see `crystal_last_error`); unresolved or failed files, a failed class lookup
+///   and a failed loader install are not errors
+#[unsafe(no_mangle)]
+pub extern "C" fn import_curseforge(
+    core: *const CrystalCore,
+    pack_path: *const c_char,
+    instance_dir: *const c_char,
+    options_json: *const c_char,
+) -> *mut c_char {
+    ffi_string(|| {
+        let core = core_arg(core)?;
+        let request = ImportRequest::new(
+            core,
+            str_arg(pack_path, "pack_path")?,
+            str_arg(instance_dir, "instance_dir")?,
+            options_arg(options_json)?,
+        )?;
+
+        let report = core.runtime.block_on(import(request, Progress::for_core(core, None), CancellationToken::new()))?;
+        Ok(to_json(&report)?.to_string())
+    })
+}
+
+/// Background variant of `import_curseforge`.
+///
+/// # Returns
+/// * Job id for `crystal_job_poll` (result: the import report)
+/// * negated `ErrorCode` on invalid arguments or an invalid pack
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_start_import_curseforge(
+    core: *const CrystalCore,
+    pack_path: *const c_char,
+    instance_dir: *const c_char,
+    options_json: *const c_char,
+) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        let request = ImportRequest::new(
+            core,
+            str_arg(pack_path, "pack_path")?,
+            str_arg(instance_dir, "instance_dir")?,
+            options_arg(options_json)?,
+        )?;
+
+        Ok(core.spawn_job("import_curseforge", |progress, cancel| async move {
+            to_json(&import(request, progress, cancel).await?)
+        }))
+    })
+}
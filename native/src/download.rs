@@ -0,0 +1,808 @@
+//! Streaming HTTP downloads shared by every module that fetches files.
+//!
+//! The body is written chunk by chunk to `<name>.part` next to the target
+//! while it is hashed, so memory stays bounded whatever the file size. The
+//! target only appears (atomic rename) once the hash has been checked: a
+//! failed, cancelled or crashed download never leaves a truncated jar behind.
+//!
+//! Next to the `.part` file a small `<name>.part.json` sidecar records where
+//! the bytes came from (URL, ETag / Last-Modified, expected hash) and how many
+//! of them are on disk. When a download is interrupted by the network or by
+//! cancellation both files are kept, and the next attempt (a retry, or the
+//! next launch) asks only for the missing bytes with a `Range` request. If
+//! the server ignores the range or the file changed upstream, the download
+//! restarts from zero.
+//!
+//! Large files can also be fetched as N ranged segments over parallel
+//! connections (`download_file_segmented`): the `.part` file is preallocated,
+//! each segment writes at its own offset and records its progress in the
+//! sidecar, and the whole file is hashed once every segment is complete.
+
+use crate::cancel::{CancellationToken, check, run_cancellable};
+use crate::context::CrystalCore;
+use crate::error::CoreError;
+use crate::ffi::{core_arg, join_error, str_arg};
+use crate::hashing::{ExpectedHash, HashAlgorithm};
+use crate::jobs::ffi_job;
+use crate::net::Net;
+use crate::progress::{ItemProgress, Phase, Progress};
+use reqwest::StatusCode;
+use reqwest::header::{CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use sha1::{Digest, Sha1};
+use std::io::SeekFrom;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufWriter};
+
+/// Write buffer in front of the temporary file.
+const WRITE_BUFFER_SIZE: usize = 256 * 1024;
+
+/// Bytes written between two sidecar checkpoints.
+const CHECKPOINT_INTERVAL: u64 = 8 * 1024 * 1024;
+
+/// Temporary file a download of `dest` is streamed into.
+pub fn part_path(dest: &Path) -> PathBuf {
+    with_suffix(dest, ".part")
+}
+
+/// Sidecar describing the bytes already in `part_path(dest)`.
+pub fn part_meta_path(dest: &Path) -> PathBuf {
+    with_suffix(dest, ".part.json")
+}
+
+/// Suffixes of the temporary files written next to a destination while it is
+/// downloaded, verified or linked from the store.
+pub(crate) const TEMP_SUFFIXES: [&str; 4] = [".part", ".part.json", ".unverified", ".link"];
+
+fn with_suffix(dest: &Path, suffix: &str) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    dest.with_file_name(name)
+}
+
+/// Contents of the `.part.json` sidecar.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+struct PartMeta {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    expected_sha1: Option<String>,
+    /// Bytes of the `.part` file known to be flushed; anything past this
+    /// offset is discarded on resume.
+    bytes_written: u64,
+    /// Ranges of a segmented download (`bytes_written` is unused then).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    segments: Option<Vec<Segment>>,
+}
+
+/// One ranged segment of a segmented download: bytes `start..end` of the
+/// file, of which the first `done` are flushed to the `.part` file.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
+struct Segment {
+    start: u64,
+    end: u64,
+    done: u64,
+}
+
+impl Segment {
+    fn remaining(&self) -> u64 {
+        self.end - self.start - self.done
+    }
+}
+
+impl PartMeta {
+    /// Value for `If-Range`: a strong ETag, else Last-Modified.
+    fn validator(&self) -> Option<&str> {
+        self.etag
+            .as_deref()
+            .filter(|etag| !etag.starts_with("W/"))
+            .or(self.last_modified.as_deref())
+    }
+
+    /// Without a validator or an expected hash we could silently splice two
+    /// different files together, so such downloads always restart.
+    fn can_resume(&self) -> bool {
+        self.bytes_written > 0 && (self.validator().is_some() || self.expected_sha1.is_some())
+    }
+}
+
+/// Download `url` to `dest`.
+///
+/// When `expected_sha1` is given the file is only moved into place if its
+/// SHA-1 matches; otherwise `CoreError::HashMismatch` is returned. Any
+/// existing `dest` is replaced atomically.
+///
+/// A leftover `.part` from an earlier attempt for the same URL and hash is
+/// resumed. On network errors, other transient failures (see
+/// `CoreError::is_transient`) and cancellation the partial file is kept for
+/// the next attempt; on any other error it is removed.
+pub async fn download_file(
+    net: &Net,
+    url: &str,
+    dest: &Path,
+    expected_sha1: Option<&str>,
+    item: &ItemProgress,
+    cancel: &CancellationToken,
+) -> Result<(), CoreError> {
+    let part = part_path(dest);
+    let meta_path = part_meta_path(dest);
+
+    let result = async {
+        // 1. Stream to the temporary file (resuming if possible), hashing as we go
+        let hash = stream_to_file(net, url, &part, &meta_path, expected_sha1, item, cancel).await?;
+
+        // 2. Verify and move into place
+        commit(&part, &meta_path, dest, hash, expected_sha1, item).await
+    }
+    .await;
+
+    discard_unless_resumable(&result, &part, &meta_path).await;
+    result
+}
+
+/// Download `url` to `dest`, verified against a digest that may not be a
+/// SHA-1 (SHA-256, SHA-512, murmur2). The file is downloaded next to `dest`
+/// and only moved into place once it has been hashed and matches.
+pub async fn download_file_with_hash(
+    net: &Net,
+    url: &str,
+    dest: &Path,
+    expected: &ExpectedHash,
+    item: &ItemProgress,
+    cancel: &CancellationToken,
+) -> Result<(), CoreError> {
+    if expected.algorithm == HashAlgorithm::Sha1 {
+        return download_file(net, url, dest, Some(&expected.value), item, cancel).await;
+    }
+
+    // 1. Download unverified
+    let unverified = with_suffix(dest, ".unverified");
+    download_file(net, url, &unverified, None, item, cancel).await?;
+
+    // 2. Verify (off the runtime: this reads the whole file again)
+    item.set_phase(Phase::Verifying);
+    let (path, expected, token) = (unverified.clone(), expected.clone(), cancel.clone());
+    let verified = tokio::task::spawn_blocking(move || {
+        let scratch = Progress::default().start_item(path.display().to_string(), Phase::Verifying, None);
+        expected.verify_file(&path, &scratch, &token)
+    })
+    .await
+    .map_err(join_error)
+    .and_then(|r| r);
+    if let Err(e) = verified {
+        let _ = tokio::fs::remove_file(&unverified).await;
+        return Err(match e {
+            CoreError::HashMismatch { expected, actual, .. } => {
+                CoreError::HashMismatch { path: dest.to_path_buf(), expected, actual }
+            }
+            e => e,
+        });
+    }
+
+    // 3. Move into place
+    item.set_phase(Phase::Writing);
+    tokio::fs::rename(&unverified, dest).await.map_err(|e| CoreError::io(dest, e))
+}
+
+/// Check `hash` against `expected_sha1`, then atomically move `part` to `dest`.
+async fn commit(
+    part: &Path,
+    meta_path: &Path,
+    dest: &Path,
+    hash: String,
+    expected_sha1: Option<&str>,
+    item: &ItemProgress,
+) -> Result<(), CoreError> {
+    item.set_phase(Phase::Verifying);
+    if let Some(expected) = expected_sha1
+        && !hash.eq_ignore_ascii_case(expected)
+    {
+        return Err(CoreError::HashMismatch {
+            path: dest.to_path_buf(),
+            expected: expected.to_string(),
+            actual: hash,
+        });
+    }
+
+    item.set_phase(Phase::Writing);
+    tokio::fs::rename(part, dest).await.map_err(|e| CoreError::io(dest, e))?;
+    let _ = tokio::fs::remove_file(meta_path).await;
+    Ok(())
+}
+
+/// Keep the partial file after errors a later attempt can recover from
+/// (network, other transient failures, cancellation); remove it otherwise.
+async fn discard_unless_resumable(result: &Result<(), CoreError>, part: &Path, meta_path: &Path) {
+    match result {
+        Ok(()) | Err(CoreError::Cancelled | CoreError::Network { .. }) => {}
+        Err(e) if e.is_transient() => {}
+        Err(_) => {
+            let _ = tokio::fs::remove_file(part).await;
+            let _ = tokio::fs::remove_file(meta_path).await;
+        }
+    }
+}
+
+/// Whether a sidecar was written for this URL and expected hash.
+fn same_source(meta: &PartMeta, url: &str, expected_sha1: Option<&str>) -> bool {
+    let same_hash = match (&meta.expected_sha1, expected_sha1) {
+        (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+        (None, None) => true,
+        _ => false,
+    };
+    meta.url == url && same_hash
+}
+
+/// Sidecar of a previous attempt, if it matches this download and its
+/// `.part` file still holds the recorded bytes.
+async fn load_resume(part: &Path, meta_path: &Path, url: &str, expected_sha1: Option<&str>) -> Option<PartMeta> {
+    let json = tokio::fs::read(meta_path).await.ok()?;
+    let meta: PartMeta = serde_json::from_slice(&json).ok()?;
+    let len = tokio::fs::metadata(part).await.ok()?.len();
+
+    (same_source(&meta, url, expected_sha1) && meta.segments.is_none() && len >= meta.bytes_written && meta.can_resume())
+        .then_some(meta)
+}
+
+async fn save_meta(meta_path: &Path, meta: &PartMeta) -> Result<(), CoreError> {
+    let json = serde_json::to_vec(meta).map_err(|e| CoreError::Internal(e.to_string()))?;
+    tokio::fs::write(meta_path, json).await.map_err(|e| CoreError::io(meta_path, e))
+}
+
+/// Send the GET, asking for the bytes after `resume.bytes_written` if set.
+async fn send(
+    net: &Net,
+    url: &str,
+    resume: Option<&PartMeta>,
+    cancel: &CancellationToken,
+) -> Result<reqwest::Response, CoreError> {
+    let mut request = net.client.get(url);
+    if let Some(meta) = resume {
+        request = request.header(RANGE, format!("bytes={}-", meta.bytes_written));
+        if let Some(validator) = meta.validator() {
+            request = request.header(IF_RANGE, validator);
+        }
+    }
+    run_cancellable(cancel, async { request.send().await.map_err(|e| CoreError::network(url, e)) }).await
+}
+
+/// `(start, total)` from a `Content-Range: bytes start-end/total` header.
+fn content_range(response: &reqwest::Response) -> Option<(u64, Option<u64>)> {
+    let value = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _) = range.split_once('-')?;
+    Some((start.trim().parse().ok()?, total.trim().parse().ok()))
+}
+
+fn header_string(response: &reqwest::Response, name: reqwest::header::HeaderName) -> Option<String> {
+    response.headers().get(name)?.to_str().ok().map(str::to_string)
+}
+
+/// Stream the body of `url` into `part` and return the hex SHA-1 of the
+/// whole file. The file is flushed and synced to disk before returning.
+async fn stream_to_file(
+    net: &Net,
+    url: &str,
+    part: &Path,
+    meta_path: &Path,
+    expected_sha1: Option<&str>,
+    item: &ItemProgress,
+    cancel: &CancellationToken,
+) -> Result<String, CoreError> {
+    // 1. Request (ranged when a usable partial file exists)
+    let _connection = net.limits.connect(url, cancel).await?;
+    let mut resume = load_resume(part, meta_path, url, expected_sha1).await;
+    let (mut response, resumed_from) = loop {
+        let response = send(net, url, resume.as_ref(), cancel).await?;
+        match resume.take() {
+            Some(meta)
+                if response.status() == StatusCode::PARTIAL_CONTENT
+                    && content_range(&response).map(|(start, _)| start) == Some(meta.bytes_written) =>
+            {
+                break (response, Some(meta.bytes_written));
+            }
+            // Odd range answer: ask again for the whole file.
+            Some(_)
+                if response.status() == StatusCode::PARTIAL_CONTENT
+                    || response.status() == StatusCode::RANGE_NOT_SATISFIABLE => continue,
+            // 200 OK: the range was ignored or the validator no longer matches.
+            _ => break (response, None),
+        }
+    };
+
+    if !response.status().is_success() {
+        return Err(CoreError::http_status(url, &response));
+    }
+
+    let offset = resumed_from.unwrap_or(0);
+    let total = match resumed_from {
+        Some(_) => content_range(&response)
+            .and_then(|(_, total)| total)
+            .or(response.content_length().map(|len| len + offset)),
+        None => response.content_length(),
+    };
+    if let Some(total) = total {
+        item.set_total(total);
+    }
+
+    // 2. Open the partial file, re-hashing the bytes we keep
+    let mut hasher = Sha1::new();
+    let file = if resumed_from.is_some() {
+        println!("[Rust] Resuming {} at byte {}", url, offset);
+        item.set_phase(Phase::Hashing);
+        let mut file = tokio::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(part)
+            .await
+            .map_err(|e| CoreError::io(part, e))?;
+        file.set_len(offset).await.map_err(|e| CoreError::io(part, e))?;
+        hash_prefix(&mut file, part, &mut hasher, item, cancel).await?;
+        file.seek(SeekFrom::End(0)).await.map_err(|e| CoreError::io(part, e))?;
+        item.set_phase(Phase::Downloading);
+        file
+    } else {
+        tokio::fs::File::create(part).await.map_err(|e| CoreError::io(part, e))?
+    };
+
+    let mut meta = PartMeta {
+        url: url.to_string(),
+        etag: header_string(&response, ETAG),
+        last_modified: header_string(&response, LAST_MODIFIED),
+        expected_sha1: expected_sha1.map(str::to_string),
+        bytes_written: offset,
+        segments: None,
+    };
+    save_meta(meta_path, &meta).await?;
+
+    // 3. Stream the body, checkpointing the sidecar as we go
+    let mut writer = BufWriter::with_capacity(WRITE_BUFFER_SIZE, file);
+    let mut written = offset;
+    loop {
+        // Only the network read (and the bandwidth wait) is raced against
+        // `cancel`, so the file is always dropped here (and closed) before
+        // the caller touches it.
+        let chunk = match run_cancellable(cancel, async {
+            let chunk = response.chunk().await.map_err(|e| CoreError::network(url, e))?;
+            if let Some(chunk) = &chunk {
+                net.limits.consume(chunk.len() as u64, cancel).await?;
+            }
+            Ok(chunk)
+        })
+        .await
+        {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                // Keep what we have for the next attempt.
+                if writer.flush().await.is_ok() {
+                    meta.bytes_written = written;
+                    let _ = save_meta(meta_path, &meta).await;
+                }
+                return Err(e);
+            }
+        };
+
+        hasher.update(&chunk);
+        writer.write_all(&chunk).await.map_err(|e| CoreError::io(part, e))?;
+        written += chunk.len() as u64;
+        item.advance(chunk.len() as u64);
+
+        if written - meta.bytes_written >= CHECKPOINT_INTERVAL {
+            writer.flush().await.map_err(|e| CoreError::io(part, e))?;
+            meta.bytes_written = written;
+            save_meta(meta_path, &meta).await?;
+        }
+    }
+
+    writer.flush().await.map_err(|e| CoreError::io(part, e))?;
+    writer.get_ref().sync_all().await.map_err(|e| CoreError::io(part, e))?;
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Files smaller than this per connection are not worth splitting.
+const MIN_SEGMENT_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Size and validators of a file whose server honours `Range`.
+struct RangeSupport {
+    size: u64,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+/// Download `url` to `dest` over up to `connections` parallel ranged
+/// requests, with the same verification, atomic rename and resume rules as
+/// `download_file`.
+///
+/// Falls back to a single stream when the server does not honour `Range`,
+/// the file is too small to be worth splitting, or the server stops
+/// honouring ranges midway (e.g. the file changed upstream).
+pub async fn download_file_segmented(
+    net: &Net,
+    url: &str,
+    dest: &Path,
+    expected_sha1: Option<&str>,
+    connections: usize,
+    item: &ItemProgress,
+    cancel: &CancellationToken,
+) -> Result<(), CoreError> {
+    // 1. Probe for range support
+    let remote = match probe_ranges(net, url, cancel).await? {
+        Some(remote) if connections > 1 && remote.size >= 2 * MIN_SEGMENT_SIZE => remote,
+        _ => return download_file(net, url, dest, expected_sha1, item, cancel).await,
+    };
+    let connections = connections.min((remote.size / MIN_SEGMENT_SIZE) as usize);
+
+    let part = part_path(dest);
+    let meta_path = part_meta_path(dest);
+
+    let result = async {
+        // 2. Fetch every segment into the preallocated file
+        if !fetch_segments(net, url, &part, &meta_path, &remote, expected_sha1, connections, item, cancel).await? {
+            println!("[Rust] {} stopped honouring ranges, restarting as a single stream", url);
+            let _ = tokio::fs::remove_file(&meta_path).await;
+            item.restart(Phase::Downloading);
+            return download_file(net, url, dest, expected_sha1, item, cancel).await;
+        }
+
+        // 3. Hash the whole file, verify and move into place
+        item.set_phase(Phase::Verifying);
+        let hash_path = part.clone();
+        let hash = tokio::task::spawn_blocking(move || crate::hashing::sha1_file(&hash_path))
+            .await
+            .map_err(crate::ffi::join_error)??;
+        commit(&part, &meta_path, dest, hash, expected_sha1, item).await
+    }
+    .await;
+
+    discard_unless_resumable(&result, &part, &meta_path).await;
+    result
+}
+
+/// Ask for the first byte: a `206` with a `Content-Range` total means the
+/// server can serve segments.
+async fn probe_ranges(
+    net: &Net,
+    url: &str,
+    cancel: &CancellationToken,
+) -> Result<Option<RangeSupport>, CoreError> {
+    let _connection = net.limits.connect(url, cancel).await?;
+    let response = run_cancellable(cancel, async {
+        net.client.get(url).header(RANGE, "bytes=0-0").send().await.map_err(|e| CoreError::network(url, e))
+    })
+    .await?;
+
+    if !response.status().is_success() {
+        return Err(CoreError::http_status(url, &response));
+    }
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return Ok(None);
+    }
+    Ok(content_range(&response).and_then(|(_, total)| total).map(|size| RangeSupport {
+        size,
+        etag: header_string(&response, ETAG),
+        last_modified: header_string(&response, LAST_MODIFIED),
+    }))
+}
+
+/// Segmented sidecar of a previous attempt for the same remote file.
+async fn load_segments(
+    part: &Path,
+    meta_path: &Path,
+    url: &str,
+    expected_sha1: Option<&str>,
+    remote: &RangeSupport,
+) -> Option<PartMeta> {
+    let json = tokio::fs::read(meta_path).await.ok()?;
+    let meta: PartMeta = serde_json::from_slice(&json).ok()?;
+    let len = tokio::fs::metadata(part).await.ok()?.len();
+
+    let covers_file = meta.segments.as_ref()?.iter().map(|s| s.end - s.start).sum::<u64>() == remote.size;
+    // Same rule as `can_resume`: two missing validators prove nothing
+    let trusted = meta.validator().is_some() || meta.expected_sha1.is_some();
+    let unchanged = meta.etag == remote.etag && meta.last_modified == remote.last_modified;
+    (same_source(&meta, url, expected_sha1) && len == remote.size && covers_file && trusted && unchanged).then_some(meta)
+}
+
+/// Split `size` bytes into `count` contiguous segments.
+fn split_segments(size: u64, count: usize) -> Vec<Segment> {
+    let count = count.max(1) as u64;
+    let step = size.div_ceil(count);
+    (0..count)
+        .map(|i| Segment { start: i * step, end: ((i + 1) * step).min(size), done: 0 })
+        .filter(|s| s.start < s.end)
+        .collect()
+}
+
+/// Fetch every unfinished segment concurrently. Returns `false` if the
+/// server answered a segment with the whole file instead of the range.
+#[allow(clippy::too_many_arguments)]
+async fn fetch_segments(
+    net: &Net,
+    url: &str,
+    part: &Path,
+    meta_path: &Path,
+    remote: &RangeSupport,
+    expected_sha1: Option<&str>,
+    connections: usize,
+    item: &ItemProgress,
+    cancel: &CancellationToken,
+) -> Result<bool, CoreError> {
+    // 1. Resume the matching segmented state, or preallocate a fresh file
+    let meta = match load_segments(part, meta_path, url, expected_sha1, remote).await {
+        Some(meta) => meta,
+        None => {
+            let file = tokio::fs::File::create(part).await.map_err(|e| CoreError::io(part, e))?;
+            file.set_len(remote.size).await.map_err(|e| CoreError::io(part, e))?;
+            PartMeta {
+                url: url.to_string(),
+                etag: remote.etag.clone(),
+                last_modified: remote.last_modified.clone(),
+                expected_sha1: expected_sha1.map(str::to_string),
+                bytes_written: 0,
+                segments: Some(split_segments(remote.size, connections)),
+            }
+        }
+    };
+    save_meta(meta_path, &meta).await?;
+
+    let segments = meta.segments.clone().unwrap_or_default();
+    item.set_total(remote.size);
+    let resumed: u64 = segments.iter().map(|s| s.done).sum();
+    if resumed > 0 {
+        println!("[Rust] Resuming {} segments of {} ({} bytes done)", segments.len(), url, resumed);
+        item.advance(resumed);
+    }
+
+    // 2. One request per segment; the first failure stops the others
+    let validator = meta.validator().map(str::to_string);
+    let state = tokio::sync::Mutex::new(meta);
+    let stop = cancel.child_token();
+    let results = futures::future::join_all(
+        segments
+            .into_iter()
+            .enumerate()
+            .filter(|(_, segment)| segment.remaining() > 0)
+            .map(|(index, segment)| {
+                let (state, stop, validator) = (&state, &stop, validator.as_deref());
+                async move {
+                    let result = fetch_segment(net, url, part, meta_path, state, index, segment, validator, item, stop).await;
+                    if !matches!(result, Ok(true)) {
+                        stop.cancel();
+                    }
+                    result
+                }
+            }),
+    )
+    .await;
+
+    // 3. A real error wins over the cancellations it caused
+    let mut ranges_honoured = true;
+    for result in results {
+        match result {
+            Ok(honoured) => ranges_honoured &= honoured,
+            Err(CoreError::Cancelled) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    check(cancel)?;
+    Ok(ranges_honoured)
+}
+
+/// Stream one segment into its slot of the `.part` file, checkpointing its
+/// progress in the shared sidecar.
+#[allow(clippy::too_many_arguments)]
+async fn fetch_segment(
+    net: &Net,
+    url: &str,
+    part: &Path,
+    meta_path: &Path,
+    state: &tokio::sync::Mutex<PartMeta>,
+    index: usize,
+    segment: Segment,
+    validator: Option<&str>,
+    item: &ItemProgress,
+    cancel: &CancellationToken,
+) -> Result<bool, CoreError> {
+    // 1. Ranged request for the missing bytes of this segment
+    let _connection = net.limits.connect(url, cancel).await?;
+    let from = segment.start + segment.done;
+    let mut request = net.client.get(url).header(RANGE, format!("bytes={}-{}", from, segment.end - 1));
+    if let Some(validator) = validator {
+        request = request.header(IF_RANGE, validator);
+    }
+    let mut response = run_cancellable(cancel, async { request.send().await.map_err(|e| CoreError::network(url, e)) }).await?;
+
+    if !response.status().is_success() {
+        return Err(CoreError::http_status(url, &response));
+    }
+    if response.status() != StatusCode::PARTIAL_CONTENT || content_range(&response).map(|(start, _)| start) != Some(from) {
+        return Ok(false);
+    }
+
+    // 2. Write at the segment's offset
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(part)
+        .await
+        .map_err(|e| CoreError::io(part, e))?;
+    file.seek(SeekFrom::Start(from)).await.map_err(|e| CoreError::io(part, e))?;
+    let mut writer = BufWriter::with_capacity(WRITE_BUFFER_SIZE, file);
+
+    let checkpoint = |done: u64| async move {
+        let mut meta = state.lock().await;
+        if let Some(segments) = meta.segments.as_mut() {
+            segments[index].done = done;
+        }
+        save_meta(meta_path, &meta).await
+    };
+
+    let mut done = segment.done;
+    let mut flushed = done;
+    loop {
+        let chunk = match run_cancellable(cancel, async {
+            let chunk = response.chunk().await.map_err(|e| CoreError::network(url, e))?;
+            if let Some(chunk) = &chunk {
+                net.limits.consume(chunk.len() as u64, cancel).await?;
+            }
+            Ok(chunk)
+        })
+        .await
+        {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                // Keep what we have for the next attempt.
+                if writer.flush().await.is_ok() {
+                    let _ = checkpoint(done).await;
+                }
+                return Err(e);
+            }
+        };
+
+        writer.write_all(&chunk).await.map_err(|e| CoreError::io(part, e))?;
+        done += chunk.len() as u64;
+        item.advance(chunk.len() as u64);
+
+        if done - flushed >= CHECKPOINT_INTERVAL {
+            writer.flush().await.map_err(|e| CoreError::io(part, e))?;
+            checkpoint(done).await?;
+            flushed = done;
+        }
+    }
+
+    writer.flush().await.map_err(|e| CoreError::io(part, e))?;
+    writer.get_ref().sync_all().await.map_err(|e| CoreError::io(part, e))?;
+    checkpoint(done).await?;
+
+    if done != segment.end - segment.start {
+        return Err(CoreError::Internal(format!(
+            "segment {} of {} ended after {} of {} bytes",
+            index, url, done, segment.end - segment.start
+        )));
+    }
+    Ok(true)
+}
+
+/// Feed the first bytes of an already truncated `file` to `hasher`.
+async fn hash_prefix(
+    file: &mut tokio::fs::File,
+    path: &Path,
+    hasher: &mut Sha1,
+    item: &ItemProgress,
+    cancel: &CancellationToken,
+) -> Result<(), CoreError> {
+    let mut buffer = vec![0; WRITE_BUFFER_SIZE];
+    loop {
+        check(cancel)?;
+        let count = file.read(&mut buffer).await.map_err(|e| CoreError::io(path, e))?;
+        if count == 0 { break; }
+        hasher.update(&buffer[..count]);
+        item.advance(count as u64);
+    }
+    Ok(())
+}
+
+/// Download one file as a background job, split into up to `connections`
+/// parallel ranged segments when the server supports it (client jars,
+/// modpack zips, Java runtimes, launcher updates).
+///
+/// # Arguments
+/// * `core` - Handle from `crystal_core_new`
+/// * `url` - File to download
+/// * `dest_path` - Final path; parent directories are created
+/// * `sha1` - Expected hex SHA-1, or null to skip verification
+/// * `connections` - Parallel connections (1 = single stream)
+///
+/// # Returns
+/// * Job id for `crystal_job_poll` (result: `null`)
+/// * negated `ErrorCode` on invalid arguments
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_start_download_file(
+    core: *const CrystalCore,
+    url: *const c_char,
+    dest_path: *const c_char,
+    sha1: *const c_char,
+    connections: i32,
+) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        let url = str_arg(url, "url")?.to_string();
+        let dest = PathBuf::from(str_arg(dest_path, "dest_path")?);
+        let sha1 = if sha1.is_null() { None } else { Some(str_arg(sha1, "sha1")?.to_string()) };
+        let connections = connections.max(1) as usize;
+        let net = core.net.clone();
+        let retry = core.config().retry.clone();
+
+        Ok(core.spawn_job("download_file", |progress, cancel| async move {
+            if let Some(parent) = dest.parent() {
+                tokio::fs::create_dir_all(parent).await.map_err(|e| CoreError::io(parent, e))?;
+            }
+
+            progress.set_items_total(1);
+            let name = dest.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| url.clone());
+            let item = progress.start_item(name, Phase::Downloading, None);
+            let (result, _) = retry
+                .run(&cancel, &url, |attempt| {
+                    if attempt > 1 {
+                        item.restart(Phase::Downloading);
+                    }
+                    download_file_segmented(&net, &url, &dest, sha1.as_deref(), connections, &item, &cancel)
+                })
+                .await;
+            item.finish(result)?;
+            Ok(serde_json::Value::Null)
+        }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A half-done two-segment `.part` of a 10-byte file, with its sidecar.
+    async fn segmented_part(name: &str, etag: Option<&str>, expected_sha1: Option<&str>) -> (PathBuf, PathBuf) {
+        let dest = std::env::temp_dir().join(format!("crystal-segments-{}-{}.bin", name, std::process::id()));
+        let (part, meta_path) = (part_path(&dest), part_meta_path(&dest));
+        let meta = PartMeta {
+            url: "https://cdn.example/a.bin".to_string(),
+            etag: etag.map(String::from),
+            last_modified: None,
+            expected_sha1: expected_sha1.map(String::from),
+            bytes_written: 0,
+            segments: Some(vec![Segment { start: 0, end: 5, done: 5 }, Segment { start: 5, end: 10, done: 2 }]),
+        };
+        tokio::fs::write(&part, [0u8; 10]).await.unwrap();
+        tokio::fs::write(&meta_path, serde_json::to_vec(&meta).unwrap()).await.unwrap();
+        (part, meta_path)
+    }
+
+    async fn load(part: &Path, meta_path: &Path, expected_sha1: Option<&str>, etag: Option<&str>) -> bool {
+        let remote = RangeSupport { size: 10, etag: etag.map(String::from), last_modified: None };
+        let loaded = load_segments(part, meta_path, "https://cdn.example/a.bin", expected_sha1, &remote).await;
+        loaded.is_some()
+    }
+
+    #[tokio::test]
+    async fn segments_resume_only_with_a_validator_or_hash() {
+        let sha1 = "a9993e364706816aba3e25717850c26c9cd0d89d";
+
+        let (part, meta) = segmented_part("etag", Some("\"v1\""), None).await;
+        assert!(load(&part, &meta, None, Some("\"v1\"")).await);
+        assert!(!load(&part, &meta, None, Some("\"v2\"")).await);
+
+        let (part, meta) = segmented_part("sha1", None, Some(sha1)).await;
+        assert!(load(&part, &meta, Some(sha1), None).await);
+
+        // Nothing ties the bytes on disk to what the server sends now
+        let (part, meta) = segmented_part("none", None, None).await;
+        assert!(!load(&part, &meta, None, None).await);
+
+        for name in ["etag", "sha1", "none"] {
+            let dest = std::env::temp_dir().join(format!("crystal-segments-{}-{}.bin", name, std::process::id()));
+            let _ = std::fs::remove_file(part_path(&dest));
+            let _ = std::fs::remove_file(part_meta_path(&dest));
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use std::cell::RefCell;
+use std::error::Error as StdError;
+use std::path::PathBuf;
+
+/// Stable error codes shared with the host.
+///
+/// Failing exports return the negated value (e.g. `-20` for `Network`), and the
+/// full report is available from `crystal_last_error`. Never renumber these.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i32)]
+pub enum ErrorCode {
+    InvalidArgument = 1,
+    InvalidHandle = 2,
+    Cancelled = 3,
+    UnsafePath = 4,
+    Io = 10,
+    NotFound = 11,
+    Network = 20,
+    HttpStatus = 21,
+    HashMismatch = 30,
+    Signature = 31,
+    Archive = 40,
+    ProcessFailed = 50,
+    ProcessSpawn = 51,
+    Storage = 60,
+    Database = 70,
+    Incomplete = 80,
+    Panic = 98,
+    Internal = 99,
+}
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CoreError {
+    #[error("invalid argument `{name}`: {reason}")]
+    InvalidArgument { name: &'static str, reason: String },
+
+    #[error("invalid or null core handle")]
+    InvalidHandle,
+
+    #[error("operation cancelled")]
+    Cancelled,
+
+    #[error(transparent)]
+    UnsafePath(#[from] crate::safe_path::UnsafePath),
+
+    #[error("I/O error on {}", path.display())]
+    Io { path: PathBuf, #[source] source: std::io::Error },
+
+    #[error("request to {url} failed")]
+    Network { url: String, #[source] source: reqwest::Error },
+
+    #[error("{url} returned HTTP {status}")]
+    HttpStatus { url: String, status: u16, retry_after: Option<std::time::Duration> },
+
+    #[error("hash mismatch for {}: expected {expected}, got {actual}", path.display())]
+    HashMismatch { path: PathBuf, expected: String, actual: String },
+
+    #[error("manifest signature rejected: {reason}")]
+    Signature { reason: String },
+
+    #[error("invalid archive {}", path.display())]
+    Archive { path: PathBuf, #[source] source: zip::result::ZipError },
+
+    #[error("{program} exited with {status}: {stderr}")]
+    ProcessFailed { program: String, status: String, stderr: String },
+
+    #[error("failed to start {program}")]
+    ProcessSpawn { program: String, #[source] source: std::io::Error },
+
+    #[error("storage operation on {key} failed")]
+    Storage { key: String, #[source] source: BoxError },
+
+    #[error("native panic at {location}: {message}")]
+    Panic { message: String, location: String },
+
+    #[error("database error")]
+    Database(#[from] rusqlite::Error),
+
+    #[error("{} of {total} files failed: {}", failed.len(), failed.join(", "))]
+    Incomplete { failed: Vec<String>, total: usize, #[source] first: Box<CoreError> },
+
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl CoreError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            CoreError::InvalidArgument { .. } => ErrorCode::InvalidArgument,
+            CoreError::InvalidHandle => ErrorCode::InvalidHandle,
+            CoreError::Cancelled => ErrorCode::Cancelled,
+            CoreError::UnsafePath(_) => ErrorCode::UnsafePath,
+            CoreError::Io { source, .. } if source.kind() == std::io::ErrorKind::NotFound => ErrorCode::NotFound,
+            CoreError::Io { .. } => ErrorCode::Io,
+            CoreError::Network { .. } => ErrorCode::Network,
+            CoreError::HttpStatus { .. } => ErrorCode::HttpStatus,
+            CoreError::HashMismatch { .. } => ErrorCode::HashMismatch,
+            CoreError::Signature { .. } => ErrorCode::Signature,
+            CoreError::Archive { .. } => ErrorCode::Archive,
+            CoreError::ProcessFailed { .. } => ErrorCode::ProcessFailed,
+            CoreError::ProcessSpawn { .. } => ErrorCode::ProcessSpawn,
+            CoreError::Storage { .. } => ErrorCode::Storage,
+            CoreError::Panic { .. } => ErrorCode::Panic,
+            CoreError::Database(_) => ErrorCode::Database,
+            CoreError::Incomplete { .. } => ErrorCode::Incomplete,
+            CoreError::Internal(_) => ErrorCode::Internal,
+        }
+    }
+
+    /// Filesystem path involved in the failure, if any.
+    pub fn path(&self) -> Option<String> {
+        match self {
+            CoreError::Io { path, .. }
+            | CoreError::HashMismatch { path, .. }
+            | CoreError::Archive { path, .. } => Some(path.display().to_string()),
+            CoreError::ProcessSpawn { program, .. } => Some(program.clone()),
+            CoreError::UnsafePath(e) => Some(e.path.clone()),
+            _ => None,
+        }
+    }
+
+    /// URL (or object key) involved in the failure, if any.
+    pub fn url(&self) -> Option<String> {
+        match self {
+            CoreError::Network { url, .. } | CoreError::HttpStatus { url, .. } => Some(url.clone()),
+            CoreError::Storage { key, .. } => Some(key.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether retrying the same request may succeed: timeouts, connection
+    /// failures/resets and HTTP 408, 429 and 5xx.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            CoreError::Network { source, .. } => {
+                source.is_timeout() || source.is_connect() || source.is_request() || source.is_body()
+            }
+            CoreError::HttpStatus { status, .. } => matches!(status, 408 | 429 | 500..=599),
+            _ => false,
+        }
+    }
+
+    /// Delay requested by the server through `Retry-After`.
+    pub fn retry_after(&self) -> Option<std::time::Duration> {
+        match self {
+            CoreError::HttpStatus { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Value returned by `i32` exports for this error.
+    pub fn status(&self) -> i32 {
+        -(self.code() as i32)
+    }
+
+    // Constructors for the common cases, so call sites stay short.
+
+    pub fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        CoreError::Io { path: path.into(), source }
+    }
+
+    pub fn network(url: impl Into<String>, source: reqwest::Error) -> Self {
+        CoreError::Network { url: url.into(), source }
+    }
+
+    /// Non-success `response`, keeping its `Retry-After` (seconds or an
+    /// HTTP date) if any.
+    pub fn http_status(url: impl Into<String>, response: &reqwest::Response) -> Self {
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_retry_after);
+        CoreError::HttpStatus { url: url.into(), status: response.status().as_u16(), retry_after }
+    }
+
+    pub fn storage(key: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        CoreError::Storage { key: key.into(), source: source.into() }
+    }
+
+    pub fn invalid(name: &'static str, reason: impl std::fmt::Display) -> Self {
+        CoreError::InvalidArgument { name, reason: reason.to_string() }
+    }
+}
+
+/// `Retry-After` value: delay-seconds, or an HTTP date (a date in the past
+/// means "retry now").
+fn parse_retry_after(value: &str) -> Option<std::time::Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse() {
+        return Some(std::time::Duration::from_secs(secs));
+    }
+    let at = httpdate::parse_http_date(value).ok()?;
+    Some(at.duration_since(std::time::SystemTime::now()).unwrap_or_default())
+}
+
+/// JSON shape returned by `crystal_last_error`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ErrorReport {
+    pub code: ErrorCode,
+    pub status: i32,
+    pub message: String,
+    pub path: Option<String>,
+    pub url: Option<String>,
+    pub causes: Vec<String>,
+}
+
+impl From<&CoreError> for ErrorReport {
+    fn from(err: &CoreError) -> Self {
+        let mut causes = Vec::new();
+        let mut source = err.source();
+        while let Some(cause) = source {
+            causes.push(cause.to_string());
+            source = cause.source();
+        }
+
+        Self {
+            code: err.code(),
+            status: err.status(),
+            message: err.to_string(),
+            path: err.path(),
+            url: err.url(),
+            causes,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<ErrorReport>> = const { RefCell::new(None) };
+}
+
+/// Record `err` as the calling thread's last error.
+pub fn set_last_error(err: &CoreError) {
+    let report = ErrorReport::from(err);
+    println!("[Rust] Error {:?}: {} {:?}", report.code, report.message, report.causes);
+    LAST_ERROR.with(|slot| *slot.borrow_mut() = Some(report));
+}
+
+pub fn clear_last_error() {
+    LAST_ERROR.with(|slot| *slot.borrow_mut() = None);
+}
+
+pub fn last_error() -> Option<ErrorReport> {
+    LAST_ERROR.with(|slot| slot.borrow().clone())
+}
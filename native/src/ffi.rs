@@ -0,0 +1,195 @@
+//! Helpers shared by every `extern "C"` export: argument decoding, panic
+//! containment and translation of `CoreError` into status codes / last-error
+//! reports.
+//!
+//! Every export must go through one of the `ffi_*` runners so that a panic is
+//! turned into `ErrorCode::Panic` instead of unwinding into the host (which
+//! aborts the whole Flutter process).
+
+use crate::context::CrystalCore;
+use crate::error::{CoreError, clear_last_error, last_error, set_last_error};
+use futures::FutureExt;
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::future::Future;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+thread_local! {
+    // Location of the most recent panic on this thread, filled by the hook.
+    static PANIC_LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+static PANIC_HOOK: Once = Once::new();
+
+/// Install (once) a panic hook that remembers where each panic happened,
+/// then defers to the previous hook so the message still reaches stderr.
+fn install_panic_hook() {
+    PANIC_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let location = info
+                .location()
+                .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()))
+                .unwrap_or_else(|| "<unknown>".to_string());
+            PANIC_LOCATION.with(|slot| *slot.borrow_mut() = Some(location));
+            previous(info);
+        }));
+    });
+}
+
+/// Build a `CoreError::Panic` from a caught payload. Must run on the thread
+/// that panicked so the hook's location is still available.
+fn panic_error(payload: Box<dyn Any + Send>) -> CoreError {
+    let message = payload_message(payload);
+    let location = PANIC_LOCATION
+        .with(|slot| slot.borrow_mut().take())
+        .unwrap_or_else(|| "<unknown>".to_string());
+
+    CoreError::Panic { message, location }
+}
+
+/// Run `f`, converting a panic into `CoreError::Panic`.
+fn guard<T>(f: impl FnOnce() -> Result<T, CoreError>) -> Result<T, CoreError> {
+    install_panic_hook();
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| Err(panic_error(payload)))
+}
+
+/// Async counterpart of `guard` for spawned tasks: the panic is caught on the
+/// worker thread that raised it, so its location is preserved.
+pub(crate) async fn guard_task<T>(fut: impl Future<Output = Result<T, CoreError>>) -> Result<T, CoreError> {
+    install_panic_hook();
+    AssertUnwindSafe(fut).catch_unwind().await.unwrap_or_else(|payload| Err(panic_error(payload)))
+}
+
+/// Map a `JoinError` (task aborted or panicked outside `guard_task`).
+pub(crate) fn join_error(e: tokio::task::JoinError) -> CoreError {
+    if e.is_panic() {
+        CoreError::Panic { message: payload_message(e.into_panic()), location: "<tokio task>".to_string() }
+    } else {
+        CoreError::Internal(format!("task failed: {}", e))
+    }
+}
+
+fn payload_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "non-string panic payload".to_string()
+    }
+}
+
+/// Decode a required UTF-8 C string argument.
+pub(crate) fn str_arg<'a>(ptr: *const c_char, name: &'static str) -> Result<&'a str, CoreError> {
+    if ptr.is_null() {
+        return Err(CoreError::invalid(name, "null pointer"));
+    }
+    unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .map_err(|e| CoreError::invalid(name, e))
+}
+
+/// Decode a required JSON C string argument.
+pub(crate) fn json_arg<T: serde::de::DeserializeOwned>(ptr: *const c_char, name: &'static str) -> Result<T, CoreError> {
+    let json = str_arg(ptr, name)?;
+    serde_json::from_str(json).map_err(|e| CoreError::invalid(name, e))
+}
+
+/// Decode an optional JSON `options_json` argument; null means defaults.
+pub(crate) fn options_arg<T: serde::de::DeserializeOwned + Default>(ptr: *const c_char) -> Result<T, CoreError> {
+    if ptr.is_null() {
+        return Ok(T::default());
+    }
+    json_arg(ptr, "options_json")
+}
+
+/// Serialize a result for the host (job results, JSON strings).
+pub(crate) fn to_json<T: serde::Serialize>(value: &T) -> Result<serde_json::Value, CoreError> {
+    serde_json::to_value(value).map_err(|e| CoreError::Internal(e.to_string()))
+}
+
+/// Borrow the context behind an FFI handle.
+pub(crate) fn core_arg<'a>(core: *const CrystalCore) -> Result<&'a CrystalCore, CoreError> {
+    unsafe { core.as_ref() }.ok_or(CoreError::InvalidHandle)
+}
+
+/// Run an export returning a status code: `ok` on success, the negated
+/// `ErrorCode` on failure (with the report stored for `crystal_last_error`).
+pub(crate) fn ffi_status(ok: i32, f: impl FnOnce() -> Result<(), CoreError>) -> i32 {
+    clear_last_error();
+    match guard(f) {
+        Ok(()) => ok,
+        Err(e) => {
+            set_last_error(&e);
+            e.status()
+        }
+    }
+}
+
+/// Run an export returning an owned string (free with `free_string`).
+/// Returns null on failure.
+pub(crate) fn ffi_string(f: impl FnOnce() -> Result<String, CoreError>) -> *mut c_char {
+    clear_last_error();
+    match guard(|| f().and_then(into_c_string)) {
+        Ok(ptr) => ptr,
+        Err(e) => {
+            set_last_error(&e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Run an export returning a heap object handed to the host as a raw pointer.
+/// Returns null on failure.
+pub(crate) fn ffi_box<T>(f: impl FnOnce() -> Result<T, CoreError>) -> *mut T {
+    clear_last_error();
+    match guard(f) {
+        Ok(value) => Box::into_raw(Box::new(value)),
+        Err(e) => {
+            set_last_error(&e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Run an export with no return value (destructors and the like).
+/// Leaves the previous last error untouched unless `f` panics.
+pub(crate) fn ffi_void(f: impl FnOnce()) {
+    if let Err(e) = guard(|| {
+        f();
+        Ok(())
+    }) {
+        set_last_error(&e);
+    }
+}
+
+pub(crate) fn into_c_string(s: String) -> Result<*mut c_char, CoreError> {
+    CString::new(s)
+        .map(CString::into_raw)
+        .map_err(|e| CoreError::Internal(format!("string contains NUL: {}", e)))
+}
+
+/// Last error recorded on the calling thread, as a JSON object:
+/// `{"code": "NETWORK", "status": -20, "message": "...", "path": null, "url": "...", "causes": [...]}`
+///
+/// Panics are reported with code `PANIC` and the payload/location in `message`.
+///
+/// # Returns
+/// * JSON string (free with `free_string`)
+/// * null if the last call on this thread succeeded
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_last_error() -> *mut c_char {
+    let report = match last_error() {
+        Some(report) => report,
+        None => return std::ptr::null_mut(),
+    };
+    guard(|| {
+        let json = serde_json::to_string(&report).map_err(|e| CoreError::Internal(e.to_string()))?;
+        into_c_string(json)
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
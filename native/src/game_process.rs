@@ -0,0 +1,527 @@
+//! Game process supervision.
+//!
+//! `launch_game` spawns Java with an argv from `build_launch_command` and
+//! returns a game id. A task on the core runtime pumps stdout/stderr into a
+//! ring buffer (read with `crystal_game_read_log`) and a log file, and tracks
+//! the state:
+//!
+//! * `starting` - spawned, the game window is not up yet
+//! * `running` - a log line matched one of the ready patterns
+//! * `exited` - exit code 0, or stopped through `crystal_game_terminate` / `crystal_game_kill`
+//! * `crashed` - any other exit, or a crash report was written
+//!
+//! Every transition is also pushed as a `game_state` event on the core's
+//! event queue, so the host can minimise or close when the game is up.
+
+use crate::cancel::CancellationToken;
+use crate::context::CrystalCore;
+use crate::error::CoreError;
+use crate::ffi::{core_arg, ffi_status, ffi_string, json_arg};
+use crate::jobs::ffi_job;
+use crate::progress::{Event, EventQueue};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::Write;
+use std::os::raw::c_char;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::watch;
+
+/// Printed by Minecraft when it writes a crash report, followed by the path.
+const CRASH_REPORT_MARKER: &str = "#@!@# Game crashed! Crash report saved to: #@!@# ";
+
+/// How long to keep reading output after the process exited (children may
+/// still hold the pipes).
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How to run the game, as accepted by `launch_game`. The JSON returned by
+/// `build_launch_command` is a valid spec on its own.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct LaunchSpec {
+    /// Program first.
+    pub argv: Vec<String>,
+    pub working_dir: String,
+    /// Extra environment variables.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Defaults to `<working_dir>/logs/launcher-output.log` (truncated per launch).
+    #[serde(default)]
+    pub log_path: Option<String>,
+    /// Lines kept in memory for `crystal_game_read_log`.
+    #[serde(default = "default_log_lines")]
+    pub log_lines: usize,
+    /// Substrings of a log line meaning the game window is up.
+    #[serde(default = "default_ready_patterns")]
+    pub ready_patterns: Vec<String>,
+    /// Per-launch `natives-*` directory, deleted once the game has exited or crashed.
+    #[serde(default)]
+    pub temporary_natives_dir: Option<String>,
+}
+
+fn default_log_lines() -> usize {
+    2000
+}
+
+fn default_ready_patterns() -> Vec<String> {
+    [
+        // 1.13+: printed once the window and GL context exist
+        "Backend library: LWJGL version",
+        // 1.6 - 1.12
+        "LWJGL Version: ",
+        // Fallbacks printed a little later during startup
+        "Sound engine started",
+        "OpenAL initialized",
+    ]
+    .map(str::to_string)
+    .to_vec()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameState {
+    Starting,
+    Running,
+    Exited,
+    Crashed,
+}
+
+impl GameState {
+    fn is_finished(self) -> bool {
+        matches!(self, GameState::Exited | GameState::Crashed)
+    }
+}
+
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct LogLine {
+    seq: u64,
+    stream: LogStream,
+    line: String,
+}
+
+struct GameStatus {
+    exit_code: Option<i32>,
+    crash_report: Option<String>,
+    lines: VecDeque<LogLine>,
+    next_seq: u64,
+}
+
+struct GameProcess {
+    id: u64,
+    program: String,
+    pid: Option<u32>,
+    log_path: PathBuf,
+    started: Instant,
+    ready_patterns: Vec<String>,
+    log_lines: usize,
+    state: watch::Sender<GameState>,
+    status: Mutex<GameStatus>,
+    log: Mutex<std::io::LineWriter<std::fs::File>>,
+    /// Set by terminate/kill so the exit is not reported as a crash.
+    stop_requested: AtomicBool,
+    temporary_natives_dir: Option<PathBuf>,
+    kill: CancellationToken,
+    events: Arc<EventQueue>,
+}
+
+/// JSON shape returned by `crystal_game_poll`.
+#[derive(serde::Serialize)]
+struct GameSnapshot<'a> {
+    id: u64,
+    pid: Option<u32>,
+    state: GameState,
+    exit_code: Option<i32>,
+    crash_report: Option<&'a str>,
+    log_path: String,
+    lines: u64,
+    uptime_secs: f64,
+}
+
+impl GameProcess {
+    fn status(&self) -> std::sync::MutexGuard<'_, GameStatus> {
+        self.status.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set_state(&self, state: GameState) {
+        let changed = self.state.send_if_modified(|current| {
+            // Terminal states are final; `running` only follows `starting`.
+            let allowed = !current.is_finished() && *current != state;
+            if allowed {
+                *current = state;
+            }
+            allowed
+        });
+        if changed {
+            let exit_code = self.status().exit_code;
+            println!("[Rust] Game {} is now {:?}", self.id, state);
+            self.events.push(Event::GameState { game_id: self.id, state, exit_code });
+        }
+    }
+
+    fn record(&self, stream: LogStream, line: String) {
+        let _ = writeln!(self.log.lock().unwrap_or_else(|e| e.into_inner()), "{}", line);
+        let ready = *self.state.borrow() == GameState::Starting && self.ready_patterns.iter().any(|p| line.contains(p.as_str()));
+        let crash_report = line.split_once(CRASH_REPORT_MARKER).map(|(_, path)| path.trim().to_string());
+        {
+            let mut status = self.status();
+            if crash_report.is_some() {
+                status.crash_report = crash_report;
+            }
+            let seq = status.next_seq;
+            status.next_seq += 1;
+            if status.lines.len() >= self.log_lines {
+                status.lines.pop_front();
+            }
+            status.lines.push_back(LogLine { seq, stream, line });
+        }
+        if ready {
+            self.set_state(GameState::Running);
+        }
+    }
+
+    fn finish(&self, exit: std::io::Result<std::process::ExitStatus>) {
+        let code = exit.as_ref().ok().and_then(|s| s.code());
+        let crashed = {
+            let mut status = self.status();
+            status.exit_code = code;
+            status.crash_report.is_some()
+                || !(code == Some(0) || self.stop_requested.load(Ordering::Relaxed))
+        };
+        let _ = self.log.lock().unwrap_or_else(|e| e.into_inner()).flush();
+        if let Some(dir) = &self.temporary_natives_dir
+            && let Err(e) = std::fs::remove_dir_all(dir)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            println!("[Rust] Could not remove natives {}: {}", dir.display(), e);
+        }
+        self.set_state(if crashed { GameState::Crashed } else { GameState::Exited });
+    }
+
+    fn snapshot(&self) -> Result<String, CoreError> {
+        let status = self.status();
+        let snapshot = GameSnapshot {
+            id: self.id,
+            pid: self.pid,
+            state: *self.state.borrow(),
+            exit_code: status.exit_code,
+            crash_report: status.crash_report.as_deref(),
+            log_path: self.log_path.display().to_string(),
+            lines: status.next_seq,
+            uptime_secs: self.started.elapsed().as_secs_f64(),
+        };
+        serde_json::to_string(&snapshot).map_err(|e| CoreError::Internal(e.to_string()))
+    }
+
+    /// Lines with `seq >= after`, at most `max`.
+    fn read_log(&self, after: u64, max: usize) -> Result<String, CoreError> {
+        let status = self.status();
+        let lines: Vec<_> = status.lines.iter().filter(|l| l.seq >= after).take(max).collect();
+        let next_seq = lines.last().map_or(after.min(status.next_seq), |l| l.seq + 1);
+        // Lines that scrolled out of the ring buffer before being read.
+        let dropped = status.lines.front().map_or(0, |first| first.seq.saturating_sub(after));
+        serde_json::to_string(&serde_json::json!({ "lines": lines, "next_seq": next_seq, "dropped": dropped }))
+            .map_err(|e| CoreError::Internal(e.to_string()))
+    }
+
+    /// Ask the game to close: `WM_CLOSE` on Windows, `SIGTERM` elsewhere.
+    fn terminate(&self) -> Result<(), CoreError> {
+        if self.state.borrow().is_finished() {
+            return Ok(());
+        }
+        let Some(pid) = self.pid else { return self.force_kill() };
+        self.stop_requested.store(true, Ordering::Relaxed);
+
+        #[cfg(windows)]
+        let mut command = {
+            use std::os::windows::process::CommandExt;
+            const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+            let mut command = std::process::Command::new("taskkill");
+            command.args(["/PID", &pid.to_string()]).creation_flags(CREATE_NO_WINDOW);
+            command
+        };
+        #[cfg(not(windows))]
+        let mut command = {
+            let mut command = std::process::Command::new("kill");
+            command.args(["-TERM", &pid.to_string()]);
+            command
+        };
+        let program = command.get_program().to_string_lossy().into_owned();
+        let out = command.output().map_err(|e| CoreError::ProcessSpawn { program: program.clone(), source: e })?;
+        if !out.status.success() && !self.state.borrow().is_finished() {
+            return Err(CoreError::ProcessFailed {
+                program,
+                status: out.status.to_string(),
+                stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Kill the process immediately.
+    fn force_kill(&self) -> Result<(), CoreError> {
+        self.stop_requested.store(true, Ordering::Relaxed);
+        self.kill.cancel();
+        Ok(())
+    }
+}
+
+/// Read `reader` line by line into `game` (lossily: output is not always UTF-8).
+async fn pump(reader: impl AsyncRead + Unpin, stream: LogStream, game: Arc<GameProcess>) {
+    let mut reader = BufReader::new(reader);
+    let mut buffer = Vec::new();
+    loop {
+        buffer.clear();
+        match reader.read_until(b'\n', &mut buffer).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {
+                let line = String::from_utf8_lossy(&buffer).trim_end_matches(['\r', '\n']).to_string();
+                game.record(stream, line);
+            }
+        }
+    }
+}
+
+/// Games owned by a `CrystalCore`.
+#[derive(Default)]
+pub struct GameRegistry {
+    next_id: AtomicU64,
+    games: Mutex<HashMap<u64, Arc<GameProcess>>>,
+}
+
+impl GameRegistry {
+    fn games(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Arc<GameProcess>>> {
+        self.games.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn get(&self, id: u64) -> Result<Arc<GameProcess>, CoreError> {
+        self.games()
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| CoreError::invalid("game_id", format!("unknown game {}", id)))
+    }
+}
+
+impl CrystalCore {
+    /// Spawn the game described by `spec` and start supervising it.
+    pub fn launch_game(&self, spec: LaunchSpec) -> Result<u64, CoreError> {
+        // 1. Command; the per-launch natives are only ever a `natives-*`
+        //    directory, since the spec comes from the host
+        let temporary_natives_dir = spec
+            .temporary_natives_dir
+            .as_ref()
+            .map(PathBuf::from)
+            .filter(|dir| dir.file_name().is_some_and(|n| n.to_string_lossy().starts_with("natives-")));
+        let Some((program, args)) = spec.argv.split_first() else {
+            return Err(CoreError::invalid("argv", "empty"));
+        };
+        let mut command = tokio::process::Command::new(program);
+        command
+            .args(args)
+            .current_dir(&spec.working_dir)
+            .envs(&spec.env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        #[cfg(windows)]
+        {
+            const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+            command.creation_flags(CREATE_NO_WINDOW);
+        }
+
+        // 2. Spawn (inside the runtime, which reaps the child)
+        let mut child = {
+            let _runtime = self.runtime.enter();
+            command.spawn().map_err(|e| {
+                if let Some(dir) = &temporary_natives_dir {
+                    let _ = std::fs::remove_dir_all(dir);
+                }
+                CoreError::ProcessSpawn { program: program.clone(), source: e }
+            })?
+        };
+
+        // 3. Log file (only once the game is started, so a failed launch keeps the previous log)
+        let log_path = spec.log_path.as_ref().map_or_else(
+            || PathBuf::from(&spec.working_dir).join("logs").join("launcher-output.log"),
+            PathBuf::from,
+        );
+        let log = log_path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::File::create(&log_path));
+        let log = match log {
+            Ok(log) => log,
+            Err(e) => {
+                let _ = child.start_kill();
+                return Err(CoreError::io(&log_path, e));
+            }
+        };
+
+        let id = self.games.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let game = Arc::new(GameProcess {
+            id,
+            program: program.clone(),
+            pid: child.id(),
+            log_path,
+            started: Instant::now(),
+            ready_patterns: spec.ready_patterns,
+            log_lines: spec.log_lines.max(1),
+            state: watch::Sender::new(GameState::Starting),
+            status: Mutex::new(GameStatus { exit_code: None, crash_report: None, lines: VecDeque::new(), next_seq: 0 }),
+            log: Mutex::new(std::io::LineWriter::new(log)),
+            stop_requested: AtomicBool::new(false),
+            temporary_natives_dir,
+            kill: CancellationToken::new(),
+            events: self.events.clone(),
+        });
+        println!("[Rust] Launched game {} ({}) with PID {:?}", id, program, game.pid);
+        self.events.push(Event::GameState { game_id: id, state: GameState::Starting, exit_code: None });
+
+        // 4. Supervise
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        let task_game = game.clone();
+        self.runtime.spawn(async move {
+            let pumps = [
+                stdout.map(|out| tokio::spawn(pump(out, LogStream::Stdout, task_game.clone()))),
+                stderr.map(|err| tokio::spawn(pump(err, LogStream::Stderr, task_game.clone()))),
+            ];
+            let exit = tokio::select! {
+                exit = child.wait() => exit,
+                _ = task_game.kill.cancelled() => {
+                    let _ = child.start_kill();
+                    child.wait().await
+                }
+            };
+            for pump in pumps.into_iter().flatten() {
+                let _ = tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, pump).await;
+            }
+            task_game.finish(exit);
+        });
+
+        self.games.games().insert(id, game);
+        Ok(id)
+    }
+}
+
+/// Launch the game and supervise it.
+///
+/// # Arguments
+/// * `launch_spec_json` - `{"argv": ["java", ...], "working_dir", "env": {},
+///   "log_path": null, "log_lines": 2000, "ready_patterns": [...], "temporary_natives_dir": null}`;
+///   the result of `build_launch_command` can be passed as is
+///
+/// # Returns
+/// * Game id for the `crystal_game_*` exports
+/// * negated `ErrorCode` on error (`PROCESS_SPAWN` (-51) if Java could not be started)
+#[unsafe(no_mangle)]
+pub extern "C" fn launch_game(core: *const CrystalCore, launch_spec_json: *const c_char) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        core.launch_game(json_arg(launch_spec_json, "launch_spec_json")?)
+    })
+}
+
+/// Current state of a game as JSON:
+/// `{"id": 1, "pid": 1234, "state": "running", "exit_code": null, "crash_report": null,
+///   "log_path": "...", "lines": 812, "uptime_secs": 14.2}`
+///
+/// `state` is one of `starting`, `running`, `exited`, `crashed`.
+///
+/// # Returns
+/// * JSON string (free with `free_string`)
+/// * null if the game id is unknown
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_game_poll(core: *const CrystalCore, game_id: u64) -> *mut c_char {
+    ffi_string(|| core_arg(core)?.games.get(game_id)?.snapshot())
+}
+
+/// Output lines still in the ring buffer, starting at `after_seq`.
+///
+/// # Returns
+/// * JSON `{"lines": [{"seq": 0, "stream": "stdout", "line": "..."}], "next_seq": 1,
+///   "dropped": 0}`; pass `next_seq` to the next call. `dropped` counts lines that
+///   left the buffer before being read (they are still in the log file).
+/// * null if the game id is unknown
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_game_read_log(core: *const CrystalCore, game_id: u64, after_seq: u64, max_lines: u32) -> *mut c_char {
+    ffi_string(|| core_arg(core)?.games.get(game_id)?.read_log(after_seq, max_lines as usize))
+}
+
+/// Block until the game is up (`running`), for at most `timeout_ms`.
+///
+/// # Returns
+/// * 1 once the game is running
+/// * 0 on timeout
+/// * negated `ErrorCode` on error; `PROCESS_FAILED` (-50) if the game ended first
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_game_wait_ready(core: *const CrystalCore, game_id: u64, timeout_ms: u64) -> i32 {
+    let mut ready = 0;
+    let status = ffi_status(0, || {
+        let core = core_arg(core)?;
+        let game = core.games.get(game_id)?;
+        let mut state = game.state.subscribe();
+        let reached = core.runtime.block_on(async {
+            let wait = state.wait_for(|s| *s != GameState::Starting);
+            tokio::time::timeout(Duration::from_millis(timeout_ms), wait).await.map(|s| s.map(|s| *s))
+        });
+        let reached = match reached {
+            Ok(Ok(state)) => state,
+            Ok(Err(_)) => return Err(CoreError::Internal("game state channel closed".into())),
+            Err(_) => return Ok(()),
+        };
+        if reached == GameState::Running {
+            ready = 1;
+            return Ok(());
+        }
+        let status = game.status();
+        let tail: Vec<_> = status.lines.iter().rev().take(20).rev().map(|l| l.line.as_str()).collect();
+        Err(CoreError::ProcessFailed {
+            program: game.program.clone(),
+            status: status.exit_code.map_or("a signal".to_string(), |c| format!("code {}", c)),
+            stderr: tail.join("\n"),
+        })
+    });
+    if status == 0 { ready } else { status }
+}
+
+/// Ask the game to close cleanly (`WM_CLOSE` on Windows, `SIGTERM` elsewhere).
+/// The state becomes `exited` once it is gone.
+///
+/// # Returns
+/// * 1 on success (also if the game already ended)
+/// * negated `ErrorCode` on error
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_game_terminate(core: *const CrystalCore, game_id: u64) -> i32 {
+    ffi_status(1, || core_arg(core)?.games.get(game_id)?.terminate())
+}
+
+/// Kill the game immediately. The state becomes `exited` once it is gone.
+///
+/// # Returns
+/// * 1 on success
+/// * negated `ErrorCode` on error
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_game_kill(core: *const CrystalCore, game_id: u64) -> i32 {
+    ffi_status(1, || core_arg(core)?.games.get(game_id)?.force_kill())
+}
+
+/// Forget a game. A running game is not stopped.
+///
+/// # Returns
+/// * 1 on success
+/// * negated `ErrorCode` on error
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_game_free(core: *const CrystalCore, game_id: u64) -> i32 {
+    ffi_status(1, || {
+        core_arg(core)?.games.games().remove(&game_id).map(|_| ()).ok_or_else(|| CoreError::invalid("game_id", format!("unknown game {}", game_id)))
+    })
+}
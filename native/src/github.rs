@@ -0,0 +1,163 @@
+use crate::cancel::{CancellationToken, run_cancellable};
+use crate::context::CrystalCore;
+use crate::error::CoreError;
+use crate::ffi::{core_arg, ffi_status, str_arg};
+use crate::jobs::ffi_job;
+use crate::net::{Net, reported_file_stream, track_upload};
+use crate::progress::{Phase, Progress};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+#[derive(serde::Deserialize)]
+struct GithubAsset {
+    id: u64,
+    name: String,
+}
+
+#[derive(serde::Deserialize)]
+struct GithubRelease {
+    id: u64,
+    assets: Vec<GithubAsset>,
+}
+
+/// Upload `file_path` as an asset of the release tagged `tag`, replacing any
+/// existing asset with the same name.
+pub async fn upload_release_asset(
+    net: &Net,
+    repo: &str,
+    tag: &str,
+    file_path: &Path,
+    token: &str,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> Result<(), CoreError> {
+    let client = &net.client;
+
+    // 1. Get Release ID and Assets from Tag
+    let release_url = format!("https://api.github.com/repos/{}/releases/tags/{}", repo, tag);
+    let release: GithubRelease = {
+        let _connection = net.limits.connect(&release_url, cancel).await?;
+        let resp = client.get(&release_url)
+            .header("Authorization", format!("token {}", token))
+            .header("Accept", "application/vnd.github.v3+json")
+            .send()
+            .await
+            .map_err(|e| CoreError::network(&release_url, e))?;
+
+        if !resp.status().is_success() {
+            return Err(CoreError::http_status(release_url, &resp));
+        }
+
+        resp.json().await.map_err(|e| CoreError::network(&release_url, e))?
+    };
+
+    let file_name = file_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown.jar");
+
+    // 2. Clobber logic: Delete if exists
+    if let Some(asset) = release.assets.iter().find(|a| a.name == file_name) {
+        println!("[Rust] Asset '{}' already exists (ID: {}). Deleting for clobber...", file_name, asset.id);
+        let delete_url = format!("https://api.github.com/repos/{}/releases/assets/{}", repo, asset.id);
+        let _connection = net.limits.connect(&delete_url, cancel).await?;
+        let delete_resp = client.delete(&delete_url)
+            .header("Authorization", format!("token {}", token))
+            .header("Accept", "application/vnd.github.v3+json")
+            .send()
+            .await;
+
+        if let Ok(resp) = delete_resp
+            && !resp.status().is_success()
+            && resp.status().as_u16() != 404
+        {
+            println!("[Rust] Warning: Failed to delete existing asset: {}", resp.status());
+        }
+    }
+
+    // 3. Upload Asset
+    let upload_base = format!("https://uploads.github.com/repos/{}/releases/{}/assets", repo, release.id);
+    let upload_url = format!("{}?name={}", upload_base, file_name);
+
+    let file_size = tokio::fs::metadata(file_path).await.map_err(|e| CoreError::io(file_path, e))?.len();
+    progress.set_items_total(1);
+    let item = progress.start_item(file_name, Phase::Uploading, Some(file_size));
+    progress.add_bytes_total(file_size);
+
+    let result = async {
+        // Streamed through the shared bandwidth limiter
+        let (sent_tx, sent_rx) = mpsc::unbounded_channel();
+        let body = reported_file_stream(file_path.to_path_buf(), net.limits.clone(), cancel.clone(), sent_tx);
+        let _connection = net.limits.connect(&upload_url, cancel).await?;
+        let upload = client.post(&upload_url)
+            .header("Authorization", format!("token {}", token))
+            .header("Content-Type", "application/octet-stream")
+            .header("Content-Length", file_size)
+            .body(reqwest::Body::wrap_stream(body))
+            .send();
+        let resp = track_upload(&item, sent_rx, upload)
+            .await
+            .map_err(|e| CoreError::network(&upload_url, e))?;
+
+        if !resp.status().is_success() {
+            return Err(CoreError::http_status(upload_url.clone(), &resp));
+        }
+        Ok(())
+    }
+    .await;
+
+    item.finish(result)
+}
+
+/// Upload a file to a GitHub release, replacing an existing asset of the same name.
+///
+/// # Returns
+/// * 1 on success
+/// * negated `ErrorCode` on error (see `crystal_last_error`)
+#[unsafe(no_mangle)]
+pub extern "C" fn upload_to_github(
+    core: *const CrystalCore,
+    repo_ptr: *const c_char,
+    tag_ptr: *const c_char,
+    file_path_ptr: *const c_char,
+    token_ptr: *const c_char
+) -> i32 {
+    ffi_status(1, || {
+        let core = core_arg(core)?;
+        let repo = str_arg(repo_ptr, "repo")?;
+        let tag = str_arg(tag_ptr, "tag")?;
+        let file_path = str_arg(file_path_ptr, "file_path")?;
+        let token = str_arg(token_ptr, "token")?;
+
+        core.runtime.block_on(upload_release_asset(&core.net, repo, tag, Path::new(file_path), token, &Progress::for_core(core, None), &CancellationToken::new()))
+    })
+}
+
+/// Background variant of `upload_to_github`.
+///
+/// # Returns
+/// * Job id for `crystal_job_poll` (result: `null`)
+/// * negated `ErrorCode` on invalid arguments
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_start_upload_to_github(
+    core: *const CrystalCore,
+    repo_ptr: *const c_char,
+    tag_ptr: *const c_char,
+    file_path_ptr: *const c_char,
+    token_ptr: *const c_char
+) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        let repo = str_arg(repo_ptr, "repo")?.to_string();
+        let tag = str_arg(tag_ptr, "tag")?.to_string();
+        let file_path = PathBuf::from(str_arg(file_path_ptr, "file_path")?);
+        let token = str_arg(token_ptr, "token")?.to_string();
+        let net = core.net.clone();
+
+        Ok(core.spawn_job("upload_to_github", |progress, cancel| async move {
+            run_cancellable(&cancel, upload_release_asset(&net, &repo, &tag, &file_path, &token, &progress, &cancel)).await?;
+            Ok(serde_json::Value::Null)
+        }))
+    })
+}
@@ -0,0 +1,236 @@
+//! Persistent cache of file digests, so rescanning an unchanged `mods/`
+//! folder costs one `stat` per file instead of reading every jar.
+//!
+//! Entries are keyed by canonical path and only trusted while the file's
+//! size, modification time and (on Unix) inode still match what was hashed.
+//! Anything else counts as a modification and the file is rehashed.
+
+use crate::context::{CrystalCore, SharedDb, lock_db};
+use crate::error::CoreError;
+use crate::ffi::{core_arg, ffi_status, ffi_string, str_arg};
+use crate::hashing::{FileHashes, HashAlgorithm};
+use rusqlite::OptionalExtension;
+use std::os::raw::c_char;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Files modified this recently are hashed but not cached: on filesystems
+/// with coarse timestamps a second write could keep the same mtime.
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/// Identity of a file's contents as far as the cache is concerned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileKey {
+    pub path: String,
+    pub size: u64,
+    pub mtime_ns: i64,
+    pub inode: Option<u64>,
+}
+
+impl FileKey {
+    /// `stat` `path`. Fails like opening the file would (`NotFound`...).
+    pub fn of(path: &Path) -> Result<Self, CoreError> {
+        let canonical = std::fs::canonicalize(path).map_err(|e| CoreError::io(path, e))?;
+        let meta = std::fs::metadata(&canonical).map_err(|e| CoreError::io(path, e))?;
+        let mtime = meta.modified().map_err(|e| CoreError::io(path, e))?;
+        Ok(Self {
+            path: canonical.display().to_string(),
+            size: meta.len(),
+            mtime_ns: mtime_ns(mtime),
+            inode: inode(&meta),
+        })
+    }
+
+    fn is_racy(&self) -> bool {
+        mtime_ns(SystemTime::now()).saturating_sub(self.mtime_ns) < RACY_WINDOW.as_nanos() as i64
+    }
+}
+
+fn mtime_ns(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_nanos() as i64,
+        Err(e) => -(e.duration().as_nanos() as i64),
+    }
+}
+
+#[cfg(unix)]
+fn inode(meta: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(meta.ino())
+}
+
+#[cfg(not(unix))]
+fn inode(_meta: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// Result of `crystal_hash_cache_stats`. Hits and misses count files looked
+/// up since the core was created.
+#[derive(Debug, serde::Serialize)]
+pub struct HashCacheStats {
+    pub entries: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Digest cache stored in the core database.
+pub struct HashCache {
+    db: SharedDb,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl HashCache {
+    pub fn open(db: SharedDb) -> Result<Self, CoreError> {
+        lock_db(&db).execute_batch(
+            "CREATE TABLE IF NOT EXISTS hash_cache (
+                path TEXT PRIMARY KEY,
+                size INTEGER NOT NULL,
+                mtime_ns INTEGER NOT NULL,
+                inode INTEGER,
+                sha1 TEXT,
+                sha256 TEXT,
+                sha512 TEXT,
+                murmur2 INTEGER
+            );",
+        )?;
+        Ok(Self { db, hits: AtomicU64::new(0), misses: AtomicU64::new(0) })
+    }
+
+    /// Cached digests of `key`, if the entry still describes the same file.
+    /// Any subset of the algorithms may be present.
+    pub fn lookup(&self, key: &FileKey) -> Result<Option<FileHashes>, CoreError> {
+        let db = lock_db(&self.db);
+        let row = db
+            .query_row(
+                "SELECT sha1, sha256, sha512, murmur2 FROM hash_cache
+                 WHERE path = ?1 AND size = ?2 AND mtime_ns = ?3 AND inode IS ?4",
+                (&key.path, key.size as i64, key.mtime_ns, key.inode.map(|i| i as i64)),
+                |row| {
+                    Ok(FileHashes {
+                        size: key.size,
+                        sha1: row.get(0)?,
+                        sha256: row.get(1)?,
+                        sha512: row.get(2)?,
+                        murmur2: row.get::<_, Option<i64>>(3)?.map(|m| m as u32),
+                    })
+                },
+            )
+            .optional()?;
+        Ok(row)
+    }
+
+    /// Record `hashes` for `key`, replacing any entry for an older version of
+    /// the file. Skipped for files modified within `RACY_WINDOW`.
+    pub fn store(&self, key: &FileKey, hashes: &FileHashes) -> Result<(), CoreError> {
+        if key.is_racy() {
+            return Ok(());
+        }
+        lock_db(&self.db).execute(
+            "INSERT OR REPLACE INTO hash_cache (path, size, mtime_ns, inode, sha1, sha256, sha512, murmur2)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            (
+                &key.path,
+                key.size as i64,
+                key.mtime_ns,
+                key.inode.map(|i| i as i64),
+                &hashes.sha1,
+                &hashes.sha256,
+                &hashes.sha512,
+                hashes.murmur2.map(|m| m as i64),
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// Digests of `path` for `algorithms`: served from the cache when the file
+    /// is unchanged, otherwise `hash` is called with the algorithms the cache
+    /// lacks and the merged result is stored. Returns the digests and whether
+    /// the file was read.
+    pub fn get_or_hash(
+        &self,
+        path: &Path,
+        algorithms: &[HashAlgorithm],
+        hash: impl FnOnce(&[HashAlgorithm]) -> Result<FileHashes, CoreError>,
+    ) -> Result<(FileHashes, bool), CoreError> {
+        let key = FileKey::of(path)?;
+        let mut hashes = self.lookup(&key)?.unwrap_or(FileHashes { size: key.size, ..Default::default() });
+
+        let missing: Vec<_> = algorithms.iter().copied().filter(|a| !hashes.has(*a)).collect();
+        if missing.is_empty() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok((hashes.only(algorithms), false));
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let fresh = hash(&missing)?;
+        // The file changed while we read it: don't cache a mix of versions.
+        if fresh.size != key.size {
+            return Ok((fresh.only(algorithms), true));
+        }
+        hashes.merge(fresh);
+        self.store(&key, &hashes)?;
+        Ok((hashes.only(algorithms), true))
+    }
+
+    /// Drop the entry for `path`, or for everything below it if it is a
+    /// directory. `None` clears the whole cache. Returns the entries removed.
+    pub fn invalidate(&self, path: Option<&Path>) -> Result<u64, CoreError> {
+        let db = lock_db(&self.db);
+        let removed = match path {
+            None => db.execute("DELETE FROM hash_cache", [])?,
+            Some(path) => {
+                let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()).display().to_string();
+                let prefix = format!("{}{}", path.trim_end_matches(std::path::MAIN_SEPARATOR), std::path::MAIN_SEPARATOR);
+                db.execute(
+                    "DELETE FROM hash_cache WHERE path = ?1 OR substr(path, 1, length(?2)) = ?2",
+                    (&path, &prefix),
+                )?
+            }
+        };
+        Ok(removed as u64)
+    }
+
+    pub fn stats(&self) -> Result<HashCacheStats, CoreError> {
+        let entries: i64 = lock_db(&self.db).query_row("SELECT COUNT(*) FROM hash_cache", [], |row| row.get(0))?;
+        Ok(HashCacheStats {
+            entries: entries as u64,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Forget cached digests.
+///
+/// # Arguments
+/// * `path` - File or directory whose entries to drop, or null to clear the cache
+///
+/// # Returns
+/// * Number of entries removed
+/// * negated `ErrorCode` on error
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_hash_cache_invalidate(core: *const CrystalCore, path: *const c_char) -> i64 {
+    let mut removed = 0;
+    let status = ffi_status(0, || {
+        let core = core_arg(core)?;
+        let path = if path.is_null() { None } else { Some(Path::new(str_arg(path, "path")?)) };
+        removed = core.hash_cache.invalidate(path)?;
+        Ok(())
+    });
+    if status == 0 { removed as i64 } else { status as i64 }
+}
+
+/// Hash cache statistics.
+///
+/// # Returns
+/// * JSON `{"entries": 412, "hits": 398, "misses": 14}` (free with `free_string`)
+/// * null on error (see `crystal_last_error`)
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_hash_cache_stats(core: *const CrystalCore) -> *mut c_char {
+    ffi_string(|| {
+        let stats = core_arg(core)?.hash_cache.stats()?;
+        serde_json::to_string(&stats).map_err(|e| CoreError::Internal(e.to_string()))
+    })
+}
@@ -0,0 +1,493 @@
+//! File hashing: the SHA-1 used everywhere for verification, plus batch
+//! hashing with the digests mod platforms identify files by (SHA-512 for
+//! Modrinth, the MurmurHash2 fingerprint for CurseForge).
+
+use crate::cancel::{CancellationToken, check};
+use crate::context::CrystalCore;
+use crate::error::{CoreError, ErrorReport};
+use crate::ffi::{core_arg, ffi_string, join_error, json_arg, str_arg};
+use crate::hash_cache::HashCache;
+use crate::jobs::ffi_job;
+use crate::progress::{ItemProgress, Phase, Progress};
+use futures::StreamExt;
+use sha1::{Digest, Sha1};
+use sha2::{Sha256, Sha512};
+use std::io::{Read, Seek};
+use std::os::raw::c_char;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Read size for hashing; large enough that syscalls don't dominate.
+const HASH_BUFFER_SIZE: usize = 256 * 1024;
+
+/// Algorithms accepted by `hash_files_batch`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+    /// CurseForge fingerprint: MurmurHash2 (seed 1) over the file with
+    /// whitespace bytes (tab, LF, CR, space) removed.
+    Murmur2,
+}
+
+/// Digests of one file; only the requested ones are set.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct FileHashes {
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha512: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub murmur2: Option<u32>,
+}
+
+impl FileHashes {
+    pub fn has(&self, algorithm: HashAlgorithm) -> bool {
+        match algorithm {
+            HashAlgorithm::Sha1 => self.sha1.is_some(),
+            HashAlgorithm::Sha256 => self.sha256.is_some(),
+            HashAlgorithm::Sha512 => self.sha512.is_some(),
+            HashAlgorithm::Murmur2 => self.murmur2.is_some(),
+        }
+    }
+
+    /// Keep only the digests of `algorithms`.
+    pub fn only(self, algorithms: &[HashAlgorithm]) -> Self {
+        let keep = |algorithm| algorithms.contains(&algorithm);
+        Self {
+            size: self.size,
+            sha1: self.sha1.filter(|_| keep(HashAlgorithm::Sha1)),
+            sha256: self.sha256.filter(|_| keep(HashAlgorithm::Sha256)),
+            sha512: self.sha512.filter(|_| keep(HashAlgorithm::Sha512)),
+            murmur2: self.murmur2.filter(|_| keep(HashAlgorithm::Murmur2)),
+        }
+    }
+
+    /// Take every digest `other` has.
+    pub fn merge(&mut self, other: FileHashes) {
+        self.size = other.size;
+        self.sha1 = other.sha1.or(self.sha1.take());
+        self.sha256 = other.sha256.or(self.sha256.take());
+        self.sha512 = other.sha512.or(self.sha512.take());
+        self.murmur2 = other.murmur2.or(self.murmur2.take());
+    }
+}
+
+/// A digest a file must match: `{"algorithm": "sha512", "value": "<hex>"}`;
+/// `murmur2` values are decimal, as packwiz and CurseForge write them.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ExpectedHash {
+    pub algorithm: HashAlgorithm,
+    pub value: String,
+}
+
+impl ExpectedHash {
+    /// The digest of `hashes` in this algorithm, formatted like `value`.
+    pub fn actual(&self, hashes: &FileHashes) -> Option<String> {
+        match self.algorithm {
+            HashAlgorithm::Sha1 => hashes.sha1.clone(),
+            HashAlgorithm::Sha256 => hashes.sha256.clone(),
+            HashAlgorithm::Sha512 => hashes.sha512.clone(),
+            HashAlgorithm::Murmur2 => hashes.murmur2.map(|h| h.to_string()),
+        }
+    }
+
+    pub fn matches(&self, hashes: &FileHashes) -> bool {
+        self.actual(hashes).is_some_and(|actual| actual.eq_ignore_ascii_case(self.value.trim()))
+    }
+
+    /// Hash `path` and fail with `CoreError::HashMismatch` unless it matches.
+    pub fn verify_file(&self, path: &Path, item: &ItemProgress, cancel: &CancellationToken) -> Result<(), CoreError> {
+        let hashes = hash_file(path, &[self.algorithm], item, cancel)?;
+        if self.matches(&hashes) {
+            return Ok(());
+        }
+        Err(CoreError::HashMismatch {
+            path: path.to_path_buf(),
+            expected: self.value.clone(),
+            actual: self.actual(&hashes).unwrap_or_default(),
+        })
+    }
+
+    /// Fail with `CoreError::HashMismatch` (naming `what`) unless `data`,
+    /// a small file already in memory, matches.
+    pub fn verify_bytes(&self, data: &[u8], what: &str) -> Result<(), CoreError> {
+        let actual = hash_bytes(data, self.algorithm);
+        if actual.eq_ignore_ascii_case(self.value.trim()) {
+            return Ok(());
+        }
+        Err(CoreError::HashMismatch { path: what.into(), expected: self.value.clone(), actual })
+    }
+}
+
+/// Whether `s` is exactly `len` hex digits (a hash as written in a manifest).
+pub(crate) fn is_hex(s: &str, len: usize) -> bool {
+    s.len() == len && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Digest of in-memory `data`, formatted like `ExpectedHash::value`.
+pub fn hash_bytes(data: &[u8], algorithm: HashAlgorithm) -> String {
+    match algorithm {
+        HashAlgorithm::Sha1 => hex::encode(Sha1::digest(data)),
+        HashAlgorithm::Sha256 => hex::encode(Sha256::digest(data)),
+        HashAlgorithm::Sha512 => hex::encode(Sha512::digest(data)),
+        HashAlgorithm::Murmur2 => {
+            let len = data.iter().filter(|b| !is_murmur_whitespace(**b)).count() as u32;
+            let mut murmur = Murmur2::new(len);
+            murmur.update(data);
+            murmur.finish().to_string()
+        }
+    }
+}
+
+/// Hex SHA-1 of a file on disk.
+pub fn sha1_file(path: &Path) -> Result<String, CoreError> {
+    let mut file = std::fs::File::open(path).map_err(|e| CoreError::io(path, e))?;
+    let mut hasher = Sha1::new();
+    for_each_chunk(&mut file, path, |chunk| {
+        hasher.update(chunk);
+        Ok(())
+    })?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Compute every digest in `algorithms` for `path`, reading it once (twice
+/// with `Murmur2`, whose seed depends on the stripped length). `item`
+/// advances by the bytes hashed; `cancel` is checked between chunks.
+pub fn hash_file(
+    path: &Path,
+    algorithms: &[HashAlgorithm],
+    item: &ItemProgress,
+    cancel: &CancellationToken,
+) -> Result<FileHashes, CoreError> {
+    let mut file = std::fs::File::open(path).map_err(|e| CoreError::io(path, e))?;
+    let wants = |algorithm| algorithms.contains(&algorithm);
+
+    let mut murmur = None;
+    if wants(HashAlgorithm::Murmur2) {
+        let mut len = 0u32;
+        for_each_chunk(&mut file, path, |chunk| {
+            check(cancel)?;
+            len = len.wrapping_add(chunk.iter().filter(|b| !is_murmur_whitespace(**b)).count() as u32);
+            Ok(())
+        })?;
+        file.rewind().map_err(|e| CoreError::io(path, e))?;
+        murmur = Some(Murmur2::new(len));
+    }
+    let mut sha1 = wants(HashAlgorithm::Sha1).then(Sha1::new);
+    let mut sha256 = wants(HashAlgorithm::Sha256).then(Sha256::new);
+    let mut sha512 = wants(HashAlgorithm::Sha512).then(Sha512::new);
+
+    let mut size = 0;
+    for_each_chunk(&mut file, path, |chunk| {
+        check(cancel)?;
+        if let Some(h) = &mut sha1 { h.update(chunk); }
+        if let Some(h) = &mut sha256 { h.update(chunk); }
+        if let Some(h) = &mut sha512 { h.update(chunk); }
+        if let Some(h) = &mut murmur { h.update(chunk); }
+        size += chunk.len() as u64;
+        item.advance(chunk.len() as u64);
+        Ok(())
+    })?;
+
+    Ok(FileHashes {
+        size,
+        sha1: sha1.map(|h| hex::encode(h.finalize())),
+        sha256: sha256.map(|h| hex::encode(h.finalize())),
+        sha512: sha512.map(|h| hex::encode(h.finalize())),
+        murmur2: murmur.map(Murmur2::finish),
+    })
+}
+
+fn for_each_chunk(
+    file: &mut std::fs::File,
+    path: &Path,
+    mut f: impl FnMut(&[u8]) -> Result<(), CoreError>,
+) -> Result<(), CoreError> {
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
+    loop {
+        let count = file.read(&mut buffer).map_err(|e| CoreError::io(path, e))?;
+        if count == 0 {
+            return Ok(());
+        }
+        f(&buffer[..count])?;
+    }
+}
+
+fn is_murmur_whitespace(b: u8) -> bool {
+    matches!(b, 9 | 10 | 13 | 32)
+}
+
+/// Streaming MurmurHash2 (32-bit) as CurseForge computes fingerprints.
+/// The length in the seed is the whitespace-stripped length.
+struct Murmur2 {
+    hash: u32,
+    tail: [u8; 4],
+    tail_len: usize,
+}
+
+impl Murmur2 {
+    const SEED: u32 = 1;
+    const M: u32 = 0x5bd1_e995;
+
+    fn new(len: u32) -> Self {
+        Self { hash: Self::SEED ^ len, tail: [0; 4], tail_len: 0 }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &b in data.iter().filter(|b| !is_murmur_whitespace(**b)) {
+            self.tail[self.tail_len] = b;
+            self.tail_len += 1;
+            if self.tail_len == 4 {
+                let mut k = u32::from_le_bytes(self.tail);
+                k = k.wrapping_mul(Self::M);
+                k ^= k >> 24;
+                k = k.wrapping_mul(Self::M);
+                self.hash = self.hash.wrapping_mul(Self::M) ^ k;
+                self.tail_len = 0;
+            }
+        }
+    }
+
+    fn finish(self) -> u32 {
+        let mut h = self.hash;
+        let t = self.tail;
+        if self.tail_len > 0 {
+            if self.tail_len >= 3 { h ^= (t[2] as u32) << 16; }
+            if self.tail_len >= 2 { h ^= (t[1] as u32) << 8; }
+            h ^= t[0] as u32;
+            h = h.wrapping_mul(Self::M);
+        }
+        h ^= h >> 13;
+        h = h.wrapping_mul(Self::M);
+        h ^ (h >> 15)
+    }
+}
+
+/// JSON shape of one entry of a batch hashing result.
+#[derive(serde::Serialize)]
+struct HashResult {
+    path: String,
+    ok: bool,
+    #[serde(flatten)]
+    hashes: Option<FileHashes>,
+    error: Option<ErrorReport>,
+}
+
+/// Hash `paths` in parallel, one blocking task per file and at most one per
+/// CPU at a time. Unchanged files are answered from `cache` without being
+/// read. A file that cannot be read fails on its own; the others are still
+/// hashed. Results come back in input order.
+pub async fn hash_files(
+    paths: Vec<String>,
+    algorithms: Vec<HashAlgorithm>,
+    cache: Arc<HashCache>,
+    progress: Progress,
+    cancel: CancellationToken,
+) -> Result<Vec<(String, Result<FileHashes, CoreError>)>, CoreError> {
+    // 1. Size the batch up front
+    progress.set_items_total(paths.len() as u64);
+    for path in &paths {
+        if let Ok(meta) = std::fs::metadata(path) {
+            progress.add_bytes_total(meta.len());
+        }
+    }
+
+    // 2. Hash
+    let parallelism = std::thread::available_parallelism().map_or(4, |n| n.get());
+    let algorithms = Arc::new(algorithms);
+    let files = futures::stream::iter(paths)
+        .map(|path| {
+            let (algorithms, cache, progress, cancel) =
+                (algorithms.clone(), cache.clone(), progress.clone(), cancel.clone());
+            async move {
+                let task_path = path.clone();
+                let result = tokio::task::spawn_blocking(move || {
+                    let size = std::fs::metadata(&task_path).map(|m| m.len()).ok();
+                    let item = progress.start_item(task_path.as_str(), Phase::Hashing, size);
+                    let result = cache
+                        .get_or_hash(Path::new(&task_path), &algorithms, |missing| {
+                            hash_file(Path::new(&task_path), missing, &item, &cancel)
+                        })
+                        .map(|(hashes, read)| {
+                            if !read {
+                                item.advance(hashes.size);
+                            }
+                            hashes
+                        });
+                    item.finish(result)
+                })
+                .await
+                .map_err(join_error)
+                .and_then(|r| r);
+                (path, result)
+            }
+        })
+        .buffered(parallelism)
+        .collect()
+        .await;
+    check(&cancel)?;
+    Ok(files)
+}
+
+/// Result of `hash_files_batch`:
+/// `{"files": [{"path", "ok", "size", "sha1", ..., "error"}, ...], "failed": 1}`
+fn hash_report(files: Vec<(String, Result<FileHashes, CoreError>)>) -> serde_json::Value {
+    let files: Vec<HashResult> = files
+        .into_iter()
+        .map(|(path, result)| HashResult {
+            path,
+            ok: result.is_ok(),
+            error: result.as_ref().err().map(ErrorReport::from),
+            hashes: result.ok(),
+        })
+        .collect();
+    let failed = files.iter().filter(|f| !f.ok).count();
+    serde_json::json!({ "files": files, "failed": failed })
+}
+
+/// Hex SHA-1 of a file.
+///
+/// # Returns
+/// * Hash string (free with `free_string`)
+/// * null on error (see `crystal_last_error`)
+#[unsafe(no_mangle)]
+pub extern "C" fn calculate_sha1(core: *const CrystalCore, path_ptr: *const c_char) -> *mut c_char {
+    ffi_string(|| {
+        core_arg(core)?;
+        let path = str_arg(path_ptr, "path")?;
+        sha1_file(Path::new(path))
+    })
+}
+
+/// Hash many files in parallel with several algorithms at once. Digests of
+/// files unchanged since they were last hashed come from the hash cache.
+///
+/// # Arguments
+/// * `paths_json` - JSON array of file paths
+/// * `algorithms_json` - JSON array out of `"sha1"`, `"sha256"`, `"sha512"`, `"murmur2"`
+///
+/// # Returns
+/// * JSON (free with `free_string`), files in input order, hex digests and the
+///   CurseForge fingerprint as a number:
+///   `{"files": [{"path": "a.jar", "ok": true, "size": 1024, "sha1": "...", "murmur2": 3608199863, "error": null}], "failed": 0}`
+/// * null on invalid arguments (see `crystal_last_error`); unreadable files
+///   are reported per entry
+#[unsafe(no_mangle)]
+pub extern "C" fn hash_files_batch(
+    core: *const CrystalCore,
+    paths_json: *const c_char,
+    algorithms_json: *const c_char,
+) -> *mut c_char {
+    ffi_string(|| {
+        let core = core_arg(core)?;
+        let paths: Vec<String> = json_arg(paths_json, "paths_json")?;
+        let algorithms: Vec<HashAlgorithm> = json_arg(algorithms_json, "algorithms_json")?;
+
+        let report = core.runtime.block_on(hash_files(
+            paths,
+            algorithms,
+            core.hash_cache.clone(),
+            Progress::for_core(core, None),
+            CancellationToken::new(),
+        ))?;
+        serde_json::to_string(&hash_report(report)).map_err(|e| CoreError::Internal(e.to_string()))
+    })
+}
+
+/// Background variant of `hash_files_batch`.
+///
+/// # Returns
+/// * Job id for `crystal_job_poll` (result: the `hash_files_batch` JSON)
+/// * negated `ErrorCode` on invalid arguments
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_start_hash_files(
+    core: *const CrystalCore,
+    paths_json: *const c_char,
+    algorithms_json: *const c_char,
+) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        let paths: Vec<String> = json_arg(paths_json, "paths_json")?;
+        let algorithms: Vec<HashAlgorithm> = json_arg(algorithms_json, "algorithms_json")?;
+
+        let cache = core.hash_cache.clone();
+        Ok(core.spawn_job("hash_files", |progress, cancel| async move {
+            Ok(hash_report(hash_files(paths, algorithms, cache, progress, cancel).await?))
+        }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha_digests_match_the_published_vectors() {
+        // FIPS 180 test vectors for "abc"
+        assert_eq!(hash_bytes(b"abc", HashAlgorithm::Sha1), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(
+            hash_bytes(b"abc", HashAlgorithm::Sha256),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hash_bytes(b"abc", HashAlgorithm::Sha512),
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        );
+    }
+
+    #[test]
+    fn murmur2_matches_the_curseforge_fingerprint() {
+        // Reference MurmurHash2 (seed 1) over the bytes left once tab, LF,
+        // CR and space are stripped, which is how CurseForge fingerprints files
+        assert_eq!(hash_bytes(b"", HashAlgorithm::Murmur2), "1540447798");
+        assert_eq!(hash_bytes(b"abc", HashAlgorithm::Murmur2), "1621425345");
+        assert_eq!(hash_bytes(b"abcd", HashAlgorithm::Murmur2), "3376380438");
+        assert_eq!(hash_bytes(b"Hello World", HashAlgorithm::Murmur2), "1756117720");
+        assert_eq!(
+            hash_bytes(b"The quick brown fox\tjumps over\r\nthe lazy dog\n", HashAlgorithm::Murmur2),
+            "3751777527"
+        );
+    }
+
+    #[test]
+    fn murmur2_is_independent_of_chunking() {
+        let data = b"a b\tcd\r\nefg hij\nklmno pq";
+        let len = data.iter().filter(|b| !is_murmur_whitespace(**b)).count() as u32;
+        let expected = hash_bytes(data, HashAlgorithm::Murmur2);
+        for split in 0..=data.len() {
+            let mut murmur = Murmur2::new(len);
+            murmur.update(&data[..split]);
+            murmur.update(&data[split..]);
+            assert_eq!(murmur.finish().to_string(), expected, "split at {}", split);
+        }
+    }
+
+    #[test]
+    fn streamed_file_hashes_match_one_shot_digests() {
+        // Spans several read buffers, with whitespace around the boundaries
+        // and a length that leaves a Murmur2 tail
+        let mut data: Vec<u8> = (0..HASH_BUFFER_SIZE * 2 + 3).map(|i| b"ab c\nd\te\r"[i % 9]).collect();
+        data[HASH_BUFFER_SIZE - 1] = b' ';
+        data[HASH_BUFFER_SIZE] = b'\n';
+
+        let path = std::env::temp_dir().join(format!("crystal-hashing-{}.bin", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+        let all = [HashAlgorithm::Sha1, HashAlgorithm::Sha256, HashAlgorithm::Sha512, HashAlgorithm::Murmur2];
+        let item = Progress::default().start_item("test", Phase::Hashing, None);
+        let hashes = hash_file(&path, &all, &item, &CancellationToken::new()).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(hashes.size, data.len() as u64);
+        for algorithm in all {
+            let expected = ExpectedHash { algorithm, value: hash_bytes(&data, algorithm) };
+            assert_eq!(expected.actual(&hashes), Some(expected.value.clone()), "{:?}", algorithm);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Background jobs for long-running operations.
+//!
+//! `crystal_job_start_<op>` exports spawn the operation on the core runtime and
+//! return a job id immediately; the host then drives its UI from a timer with
+//! `crystal_job_poll` instead of blocking a Dart isolate.
+
+use crate::cancel::CancellationToken;
+use crate::context::CrystalCore;
+use crate::error::{CoreError, ErrorReport};
+use crate::ffi::{core_arg, ffi_status, ffi_string, guard_task};
+use crate::progress::{Event, EventQueue, Progress, ProgressSnapshot};
+use std::collections::HashMap;
+use std::future::Future;
+use std::os::raw::c_char;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+struct JobStatus {
+    state: JobState,
+    result: Option<serde_json::Value>,
+    error: Option<ErrorReport>,
+}
+
+struct Job {
+    id: u64,
+    kind: &'static str,
+    progress: Progress,
+    status: Mutex<JobStatus>,
+    cancel: CancellationToken,
+    events: Arc<EventQueue>,
+}
+
+impl Job {
+    fn status(&self) -> std::sync::MutexGuard<'_, JobStatus> {
+        self.status.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Move to a terminal state unless one was already reached.
+    fn finish(&self, state: JobState, result: Option<serde_json::Value>, error: Option<ErrorReport>) {
+        let mut status = self.status();
+        if status.state == JobState::Running {
+            *status = JobStatus { state, result, error };
+            self.events.push(Event::JobFinished { job_id: self.id, state });
+        }
+    }
+}
+
+/// JSON shape returned by `crystal_job_poll`.
+#[derive(serde::Serialize)]
+struct JobSnapshot<'a> {
+    id: u64,
+    kind: &'a str,
+    state: JobState,
+    #[serde(flatten)]
+    progress: ProgressSnapshot,
+    result: Option<&'a serde_json::Value>,
+    error: Option<&'a ErrorReport>,
+}
+
+/// Jobs owned by a `CrystalCore`.
+#[derive(Default)]
+pub struct JobRegistry {
+    next_id: AtomicU64,
+    jobs: Mutex<HashMap<u64, Arc<Job>>>,
+}
+
+impl JobRegistry {
+    fn jobs(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Arc<Job>>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn get(&self, id: u64) -> Result<Arc<Job>, CoreError> {
+        self.jobs()
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| CoreError::invalid("job_id", format!("unknown job {}", id)))
+    }
+
+    fn spawn<F, Fut>(&self, runtime: &tokio::runtime::Runtime, events: &Arc<EventQueue>, kind: &'static str, op: F) -> u64
+    where
+        F: FnOnce(Progress, CancellationToken) -> Fut,
+        Fut: Future<Output = Result<serde_json::Value, CoreError>> + Send + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let progress = Progress::with_sink(events.clone(), Some(id));
+        let job = Arc::new(Job {
+            id,
+            kind,
+            progress: progress.clone(),
+            status: Mutex::new(JobStatus { state: JobState::Running, result: None, error: None }),
+            cancel: CancellationToken::new(),
+            events: events.clone(),
+        });
+
+        let fut = op(progress, job.cancel.clone());
+        let task_job = job.clone();
+        runtime.spawn(async move {
+            match guard_task(fut).await {
+                Ok(result) => task_job.finish(JobState::Completed, Some(result), None),
+                Err(CoreError::Cancelled) => {
+                    println!("[Rust] Job {} ({}) cancelled", id, task_job.kind);
+                    task_job.finish(JobState::Cancelled, None, None);
+                }
+                Err(e) => {
+                    println!("[Rust] Job {} ({}) failed: {}", id, task_job.kind, e);
+                    task_job.finish(JobState::Failed, None, Some(ErrorReport::from(&e)));
+                }
+            }
+        });
+
+        self.jobs().insert(id, job);
+        id
+    }
+
+    fn poll(&self, id: u64) -> Result<String, CoreError> {
+        let job = self.get(id)?;
+        let status = job.status();
+        let snapshot = JobSnapshot {
+            id,
+            kind: job.kind,
+            state: status.state,
+            progress: job.progress.snapshot(),
+            result: status.result.as_ref(),
+            error: status.error.as_ref(),
+        };
+        serde_json::to_string(&snapshot).map_err(|e| CoreError::Internal(e.to_string()))
+    }
+
+    /// Signal the job's token. The job reaches `cancelled` once the operation
+    /// has stopped and cleaned up its partial files (downloads keep their
+    /// `.part` file so the next attempt can resume).
+    fn cancel(&self, id: u64) -> Result<(), CoreError> {
+        self.get(id)?.cancel.cancel();
+        Ok(())
+    }
+
+    fn free(&self, id: u64) -> Result<(), CoreError> {
+        self.cancel(id)?;
+        self.jobs().remove(&id);
+        Ok(())
+    }
+}
+
+impl CrystalCore {
+    /// Spawn `op` on the core runtime and register it as a job.
+    ///
+    /// `op` receives the job's `Progress` (publishing on this core's event
+    /// queue) and cancellation token, and resolves to the JSON `result`
+    /// reported by `crystal_job_poll` once the job completes.
+    pub fn spawn_job<F, Fut>(&self, kind: &'static str, op: F) -> u64
+    where
+        F: FnOnce(Progress, CancellationToken) -> Fut,
+        Fut: Future<Output = Result<serde_json::Value, CoreError>> + Send + 'static,
+    {
+        self.jobs.spawn(&self.runtime, &self.events, kind, op)
+    }
+}
+
+/// Start-export helper: returns the job id, or the negated `ErrorCode` if the
+/// arguments were rejected before anything was spawned.
+pub(crate) fn ffi_job(f: impl FnOnce() -> Result<u64, CoreError>) -> i64 {
+    let mut id = 0;
+    let status = ffi_status(0, || {
+        id = f()?;
+        Ok(())
+    });
+    if status == 0 { id as i64 } else { status as i64 }
+}
+
+/// Current state of a job as JSON:
+/// `{"id": 1, "kind": "download_mods", "state": "running", "bytes_done": 0, "bytes_total": 0,
+///   "items_done": 3, "items_total": 40, "current_item": "sodium.jar", "result": null, "error": null}`
+///
+/// `state` is one of `running`, `completed`, `failed`, `cancelled`; `error` uses
+/// the same shape as `crystal_last_error`.
+///
+/// # Returns
+/// * JSON string (free with `free_string`)
+/// * null if the job id is unknown
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_poll(core: *const CrystalCore, job_id: u64) -> *mut c_char {
+    ffi_string(|| core_arg(core)?.jobs.poll(job_id))
+}
+
+/// Request cancellation of a running job. Finished jobs are left untouched.
+///
+/// Cancellation is cooperative: keep polling until `state` becomes `cancelled`.
+///
+/// # Returns
+/// * 1 on success
+/// * negated `ErrorCode` on error
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_cancel(core: *const CrystalCore, job_id: u64) -> i32 {
+    ffi_status(1, || core_arg(core)?.jobs.cancel(job_id))
+}
+
+/// Forget a job, cancelling it first if it is still running.
+///
+/// # Returns
+/// * 1 on success
+/// * negated `ErrorCode` on error
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_free(core: *const CrystalCore, job_id: u64) -> i32 {
+    ffi_status(1, || core_arg(core)?.jobs.free(job_id))
+}
@@ -0,0 +1,556 @@
+//! Launch command building: merged version JSON + instance settings -> argv.
+//!
+//! Arguments are evaluated like the official launcher does: `arguments.jvm`
+//! and `arguments.game` entries with `rules` are kept only when the rules
+//! allow them for the environment and its features (`is_demo_user`,
+//! `has_custom_resolution`, `is_quick_play_*`). Versions that only have
+//! `minecraftArguments` (up to 1.12) get the launcher's legacy JVM arguments.
+//! `${placeholders}` are substituted last; unknown ones are left as they are.
+
+use crate::cancel::CancellationToken;
+use crate::context::CrystalCore;
+use crate::error::CoreError;
+use crate::ffi::{core_arg, ffi_string, json_arg};
+use crate::libraries::{ResolvedLibrary, extract_natives, launch_natives_dir, resolve_libraries};
+use crate::progress::Progress;
+use crate::rules::{Environment, rules_allow};
+use crate::safe_path::safe_file_name;
+use crate::vanilla::AssetIndex;
+use crate::version::{Argument, ArgumentValue, VersionJson, read_version};
+use std::collections::HashMap;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+
+/// JVM arguments the official launcher uses for versions without `arguments`.
+const LEGACY_JVM_ARGUMENTS: &str = r#"[
+    {"rules": [{"action": "allow", "os": {"name": "osx"}}], "value": "-XstartOnFirstThread"},
+    {"rules": [{"action": "allow", "os": {"name": "windows"}}],
+     "value": "-XX:HeapDumpPath=MojangTricksIntelDriversForPerformance_javaw.exe_minecraft.exe.heapdump"},
+    {"rules": [{"action": "allow", "os": {"name": "windows", "version": "^10\\."}}],
+     "value": ["-Dos.name=Windows 10", "-Dos.version=10.0"]},
+    {"rules": [{"action": "allow", "os": {"arch": "x86"}}], "value": "-Xss1M"},
+    "-Djava.library.path=${natives_directory}",
+    "-Dminecraft.launcher.brand=${launcher_name}",
+    "-Dminecraft.launcher.version=${launcher_version}",
+    "-cp",
+    "${classpath}"
+]"#;
+
+/// What to launch and as whom, as accepted by `build_launch_command`.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct LaunchInstance {
+    pub game_dir: String,
+    /// Installed version to launch (e.g. `1.21.1`, `neoforge-21.1.219`).
+    pub version_id: String,
+    #[serde(default = "default_java_path")]
+    pub java_path: String,
+    /// Defaults to `<game_dir>/libraries`.
+    #[serde(default)]
+    pub libraries_dir: Option<String>,
+    /// Defaults to `<game_dir>/assets`.
+    #[serde(default)]
+    pub assets_dir: Option<String>,
+    /// Already extracted natives; `None` extracts them into a fresh
+    /// `versions/<id>/natives-<millis>`, deleted by `launch_game` when the
+    /// game ends.
+    #[serde(default)]
+    pub natives_dir: Option<String>,
+    pub auth: LaunchAuth,
+    #[serde(default)]
+    pub min_memory_mb: Option<u32>,
+    #[serde(default)]
+    pub max_memory_mb: Option<u32>,
+    /// Extra JVM arguments, after the version's.
+    #[serde(default)]
+    pub jvm_args: Vec<String>,
+    /// Extra game arguments, after the version's.
+    #[serde(default)]
+    pub game_args: Vec<String>,
+    #[serde(default)]
+    pub resolution: Option<Resolution>,
+    #[serde(default)]
+    pub fullscreen: bool,
+    #[serde(default)]
+    pub demo: bool,
+    #[serde(default)]
+    pub quick_play: Option<QuickPlay>,
+    #[serde(default = "default_launcher_name")]
+    pub launcher_name: String,
+    #[serde(default = "default_launcher_version")]
+    pub launcher_version: String,
+    /// Defaults to this machine; the features are set from the fields above.
+    #[serde(default)]
+    pub environment: Option<Environment>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct LaunchAuth {
+    pub player_name: String,
+    #[serde(default = "default_uuid")]
+    pub uuid: String,
+    #[serde(default)]
+    pub access_token: String,
+    #[serde(default = "default_user_type")]
+    pub user_type: String,
+    #[serde(default)]
+    pub xuid: String,
+    #[serde(default)]
+    pub client_id: String,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Join a world, server or realm straight from the launcher:
+/// `{"singleplayer": "World"}`, `{"multiplayer": "mc.example.com:25565"}`
+/// or `{"realms": "1234"}`.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum QuickPlay {
+    Singleplayer(String),
+    Multiplayer(String),
+    Realms(String),
+}
+
+fn default_java_path() -> String {
+    "java".to_string()
+}
+
+fn default_uuid() -> String {
+    "00000000000000000000000000000000".to_string()
+}
+
+fn default_user_type() -> String {
+    "msa".to_string()
+}
+
+fn default_launcher_name() -> String {
+    "CrystalTides".to_string()
+}
+
+fn default_launcher_version() -> String {
+    env!("CARGO_PKG_VERSION").to_string()
+}
+
+/// Result of `build_launch_command`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct LaunchCommand {
+    /// Java first, then JVM arguments, the main class and game arguments.
+    pub argv: Vec<String>,
+    pub working_dir: String,
+    pub natives_dir: String,
+    pub main_class: String,
+    /// `natives_dir` when it was extracted for this launch only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temporary_natives_dir: Option<String>,
+}
+
+/// Evaluate `arguments` for `env` into plain strings (not yet substituted).
+fn evaluate(arguments: &[Argument], env: &Environment) -> Vec<String> {
+    let mut out = Vec::new();
+    for argument in arguments {
+        match argument {
+            Argument::Plain(value) => out.push(value.clone()),
+            Argument::Conditional { rules, value } if rules_allow(Some(rules), env) => match value {
+                ArgumentValue::One(value) => out.push(value.clone()),
+                ArgumentValue::Many(values) => out.extend(values.iter().cloned()),
+            },
+            Argument::Conditional { .. } => {}
+        }
+    }
+    out
+}
+
+/// Replace every `${name}` known to `vars`.
+fn substitute(arg: &str, vars: &HashMap<&str, String>) -> String {
+    let mut out = String::with_capacity(arg.len());
+    let mut rest = arg;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find('}') {
+            Some(end) => {
+                match vars.get(&after[..end]) {
+                    Some(value) => out.push_str(value),
+                    None => out.push_str(&rest[start..start + 2 + end + 1]),
+                }
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Whether the version's game arguments handle quick play themselves (1.20+).
+fn supports_quick_play(version: &VersionJson) -> bool {
+    version.arguments.iter().flat_map(|a| &a.game).any(|argument| match argument {
+        Argument::Conditional { rules, .. } => rules
+            .iter()
+            .filter_map(|rule| rule.features.as_ref())
+            .any(|features| features.keys().any(|k| k.starts_with("is_quick_play"))),
+        Argument::Plain(_) => false,
+    })
+}
+
+/// The jar to put on the classpath: `versions/<id>/<id>.jar` of the launched
+/// version (or of its `jar`). Like the official launcher, an inheriting
+/// version without its own jar gets a copy of its root's, so that loaders
+/// filtering `${version_name}.jar` out of the game layer still find it.
+fn client_jar(game_dir: &Path, version: &VersionJson, chain: &[String]) -> Result<PathBuf, CoreError> {
+    let id = version.jar.as_deref().unwrap_or(&version.id);
+    safe_file_name(id)?;
+    let jar = game_dir.join("versions").join(id).join(format!("{}.jar", id));
+    if version.jar.is_none()
+        && !jar.exists()
+        && let Some(root) = chain.last().filter(|root| *root != id)
+    {
+        let source = game_dir.join("versions").join(root).join(format!("{}.jar", root));
+        if source.is_file() {
+            std::fs::copy(&source, &jar).map_err(|e| CoreError::io(&jar, e))?;
+        }
+    }
+    Ok(jar)
+}
+
+/// Where `${game_assets}` points: the by-name copy for legacy asset indexes.
+fn game_assets(game_dir: &Path, assets_dir: &Path, index_id: &str) -> PathBuf {
+    let index = std::fs::read(assets_dir.join("indexes").join(format!("{}.json", index_id)))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<AssetIndex>(&bytes).ok());
+    match index {
+        Some(index) if index.map_to_resources => game_dir.join("resources"),
+        Some(index) if index.is_virtual => assets_dir.join("virtual").join(index_id),
+        _ => assets_dir.to_path_buf(),
+    }
+}
+
+/// Build the full command line for `instance`, extracting natives first
+/// unless `natives_dir` is given.
+pub fn launch_command(
+    instance: &LaunchInstance,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> Result<LaunchCommand, CoreError> {
+    let game_dir = Path::new(&instance.game_dir);
+    let libraries_dir = instance.libraries_dir.as_ref().map_or_else(|| game_dir.join("libraries"), PathBuf::from);
+    let assets_dir = instance.assets_dir.as_ref().map_or_else(|| game_dir.join("assets"), PathBuf::from);
+
+    // 1. Version chain and environment
+    let (version, chain) = read_version(game_dir, &instance.version_id)?;
+    let main_class = version
+        .main_class
+        .clone()
+        .ok_or_else(|| CoreError::invalid("version", format!("{} has no mainClass", instance.version_id)))?;
+
+    let mut env = instance.environment.clone().unwrap_or_else(Environment::current);
+    env.features.insert("is_demo_user".into(), instance.demo);
+    env.features.insert("has_custom_resolution".into(), instance.resolution.is_some());
+    for (feature, enabled) in [
+        ("is_quick_play_singleplayer", matches!(instance.quick_play, Some(QuickPlay::Singleplayer(_)))),
+        ("is_quick_play_multiplayer", matches!(instance.quick_play, Some(QuickPlay::Multiplayer(_)))),
+        ("is_quick_play_realms", matches!(instance.quick_play, Some(QuickPlay::Realms(_)))),
+    ] {
+        env.features.insert(feature.into(), enabled);
+    }
+
+    // 2. Libraries, natives and classpath
+    let libraries = resolve_libraries(&version.libraries, &env)?;
+    let (natives_dir, temporary) = match &instance.natives_dir {
+        Some(dir) => (PathBuf::from(dir), false),
+        None => {
+            let dir = launch_natives_dir(game_dir, &instance.version_id);
+            extract_natives(&libraries, &libraries_dir, &dir, progress, cancel)?;
+            (dir, true)
+        }
+    };
+    let separator = if env.os_name == "windows" { ";" } else { ":" };
+    let mut classpath: Vec<String> = libraries
+        .iter()
+        .filter(|l| !l.natives)
+        .map(|l: &ResolvedLibrary| libraries_dir.join(&l.path).display().to_string())
+        .collect();
+    classpath.push(client_jar(game_dir, &version, &chain)?.display().to_string());
+
+    // 3. Placeholders
+    let index_id = version.asset_index_id().unwrap_or("legacy").to_string();
+    let auth = &instance.auth;
+    let (width, height) = instance.resolution.map_or((String::new(), String::new()), |r| (r.width.to_string(), r.height.to_string()));
+    let quick_play = |kind: fn(&QuickPlay) -> Option<&String>| instance.quick_play.as_ref().and_then(kind).cloned().unwrap_or_default();
+    let vars: HashMap<&str, String> = HashMap::from([
+        ("auth_player_name", auth.player_name.clone()),
+        ("auth_uuid", auth.uuid.clone()),
+        ("auth_access_token", auth.access_token.clone()),
+        ("auth_session", format!("token:{}:{}", auth.access_token, auth.uuid)),
+        ("auth_xuid", auth.xuid.clone()),
+        ("clientid", auth.client_id.clone()),
+        ("user_type", auth.user_type.clone()),
+        ("user_properties", "{}".to_string()),
+        ("version_name", instance.version_id.clone()),
+        ("version_type", version.version_type.clone().unwrap_or_else(|| "release".to_string())),
+        ("game_directory", game_dir.display().to_string()),
+        ("assets_root", assets_dir.display().to_string()),
+        ("game_assets", game_assets(game_dir, &assets_dir, &index_id).display().to_string()),
+        ("assets_index_name", index_id.clone()),
+        ("launcher_name", instance.launcher_name.clone()),
+        ("launcher_version", instance.launcher_version.clone()),
+        ("natives_directory", natives_dir.display().to_string()),
+        ("library_directory", libraries_dir.display().to_string()),
+        ("classpath", classpath.join(separator)),
+        ("classpath_separator", separator.to_string()),
+        ("resolution_width", width.clone()),
+        ("resolution_height", height.clone()),
+        ("quickPlaySingleplayer", quick_play(|q| match q { QuickPlay::Singleplayer(v) => Some(v), _ => None })),
+        ("quickPlayMultiplayer", quick_play(|q| match q { QuickPlay::Multiplayer(v) => Some(v), _ => None })),
+        ("quickPlayRealms", quick_play(|q| match q { QuickPlay::Realms(v) => Some(v), _ => None })),
+    ]);
+
+    // 4. JVM arguments
+    let mut jvm = Vec::new();
+    if let Some(min) = instance.min_memory_mb {
+        jvm.push(format!("-Xms{}M", min));
+    }
+    if let Some(max) = instance.max_memory_mb {
+        jvm.push(format!("-Xmx{}M", max));
+    }
+    match version.arguments.as_ref().filter(|a| !a.jvm.is_empty()) {
+        Some(arguments) => jvm.extend(evaluate(&arguments.jvm, &env)),
+        None => {
+            let legacy: Vec<Argument> =
+                serde_json::from_str(LEGACY_JVM_ARGUMENTS).map_err(|e| CoreError::Internal(e.to_string()))?;
+            jvm.extend(evaluate(&legacy, &env));
+        }
+    }
+    jvm.extend(instance.jvm_args.iter().cloned());
+
+    // 5. Game arguments
+    let mut game = match version.arguments.as_ref().filter(|a| !a.game.is_empty()) {
+        Some(arguments) => evaluate(&arguments.game, &env),
+        None => {
+            let legacy = version.minecraft_arguments.as_deref().unwrap_or_default();
+            let mut game: Vec<String> = legacy.split_whitespace().map(str::to_string).collect();
+            if instance.demo {
+                game.push("--demo".to_string());
+            }
+            if instance.resolution.is_some() {
+                game.extend(["--width".to_string(), width, "--height".to_string(), height]);
+            }
+            game
+        }
+    };
+    if let Some(QuickPlay::Multiplayer(address)) = &instance.quick_play
+        && !supports_quick_play(&version)
+    {
+        // Before 1.20: join through --server/--port.
+        let (host, port) = address.rsplit_once(':').unwrap_or((address, "25565"));
+        game.extend(["--server".to_string(), host.to_string(), "--port".to_string(), port.to_string()]);
+    }
+    if instance.fullscreen {
+        game.push("--fullscreen".to_string());
+    }
+    game.extend(instance.game_args.iter().cloned());
+
+    // 6. Substitute and assemble
+    let mut argv = vec![instance.java_path.clone()];
+    argv.extend(jvm.iter().map(|a| substitute(a, &vars)));
+    argv.push(main_class.clone());
+    argv.extend(game.iter().map(|a| substitute(a, &vars)));
+
+    Ok(LaunchCommand {
+        argv,
+        working_dir: instance.game_dir.clone(),
+        natives_dir: natives_dir.display().to_string(),
+        main_class,
+        temporary_natives_dir: temporary.then(|| natives_dir.display().to_string()),
+    })
+}
+
+/// Build the command line to launch an installed version.
+///
+/// Follows the version's `inheritsFrom` chain (JVM and game arguments of
+/// parents included), evaluates argument rules and features, resolves the
+/// classpath and extracts natives (unless `natives_dir` is given), then
+/// substitutes `${...}` placeholders.
+///
+/// # Arguments
+/// * `instance_json` - `{"game_dir", "version_id", "java_path": "java",
+///   "auth": {"player_name", "uuid", "access_token", "user_type": "msa", "xuid", "client_id"},
+///   "min_memory_mb": 2048, "max_memory_mb": 4096, "jvm_args": [], "game_args": [],
+///   "resolution": {"width": 854, "height": 480}, "fullscreen": false, "demo": false,
+///   "quick_play": {"multiplayer": "mc.example.com:25565"}, "natives_dir": null,
+///   "libraries_dir": null, "assets_dir": null, "launcher_name", "launcher_version",
+///   "environment": null}`; only `game_dir`, `version_id` and `auth.player_name` are required
+///
+/// # Returns
+/// * JSON `{"argv": ["java", ...], "working_dir", "natives_dir", "main_class",
+///   "temporary_natives_dir"}` (free with `free_string`); `temporary_natives_dir`
+///   is only present when the natives were extracted for this launch
+///   (`launch_game` deletes it once the game ends)
+/// * null on error (see `crystal_last_error`)
+#[unsafe(no_mangle)]
+pub extern "C" fn build_launch_command(core: *const CrystalCore, instance_json: *const c_char) -> *mut c_char {
+    ffi_string(|| {
+        let core = core_arg(core)?;
+        let instance: LaunchInstance = json_arg(instance_json, "instance_json")?;
+        let command = launch_command(&instance, &Progress::for_core(core, None), &CancellationToken::new())?;
+        serde_json::to_string(&command).map_err(|e| CoreError::Internal(e.to_string()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn fixture(id: &str) -> &'static str {
+        match id {
+            "1.8" => include_str!("../tests/fixtures/versions/1.8.json"),
+            "1.21" => include_str!("../tests/fixtures/versions/1.21.json"),
+            "neoforge-21.1.219" => include_str!("../tests/fixtures/versions/neoforge-21.1.219.json"),
+            other => panic!("no fixture {}", other),
+        }
+    }
+
+    fn version(id: &str) -> VersionJson {
+        serde_json::from_str(fixture(id)).unwrap()
+    }
+
+    fn env(features: &[(&str, bool)]) -> Environment {
+        Environment {
+            os_name: "linux".to_string(),
+            os_version: "6.8.0".to_string(),
+            arch: "amd64".to_string(),
+            features: features.iter().map(|(k, v)| (k.to_string(), *v)).collect::<BTreeMap<_, _>>(),
+        }
+    }
+
+    /// A game dir holding `ids` under `versions/`.
+    fn game_dir(name: &str, ids: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("crystal-launch-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for id in ids {
+            let path = crate::version::version_json_path(&dir, id).unwrap();
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, fixture(id)).unwrap();
+        }
+        dir
+    }
+
+    fn instance(game_dir: &Path, version_id: &str) -> LaunchInstance {
+        serde_json::from_value(serde_json::json!({
+            "game_dir": game_dir,
+            "version_id": version_id,
+            "natives_dir": game_dir.join("natives"),
+            "auth": {"player_name": "Steve"},
+            "environment": env(&[]),
+        }))
+        .unwrap()
+    }
+
+    fn has_pair(argv: &[String], flag: &str, value: &str) -> bool {
+        argv.windows(2).any(|w| w[0] == flag && w[1] == value)
+    }
+
+    #[test]
+    fn substitute_leaves_unknown_and_unterminated_placeholders() {
+        let vars = HashMap::from([("a", "1".to_string()), ("natives_directory", "/n".to_string())]);
+
+        assert_eq!(substitute("-Djava.library.path=${natives_directory}", &vars), "-Djava.library.path=/n");
+        assert_eq!(substitute("${a}${a}-${a}", &vars), "11-1");
+        assert_eq!(substitute("${x} and ${a}", &vars), "${x} and 1");
+        assert_eq!(substitute("${a} then ${a", &vars), "1 then ${a");
+        assert_eq!(substitute("no placeholders {}", &vars), "no placeholders {}");
+    }
+
+    #[test]
+    fn evaluate_gates_on_features() {
+        let version = version("1.21");
+        let game = &version.arguments.as_ref().unwrap().game;
+
+        let plain = evaluate(game, &env(&[]));
+        assert!(!plain.iter().any(|a| a == "--demo" || a == "--width"));
+        assert!(plain.iter().all(|a| !a.starts_with("--quickPlay")));
+
+        let demo = evaluate(game, &env(&[("is_demo_user", true)]));
+        assert_eq!(demo.len(), plain.len() + 1);
+        assert_eq!(demo.last().map(String::as_str), Some("--demo"));
+
+        let sized = evaluate(game, &env(&[("has_custom_resolution", true), ("is_demo_user", false)]));
+        assert_eq!(sized[plain.len()..], ["--width", "${resolution_width}", "--height", "${resolution_height}"]);
+    }
+
+    #[test]
+    fn evaluate_applies_os_rules() {
+        let version = version("1.21");
+        let jvm = &version.arguments.as_ref().unwrap().jvm;
+
+        let mut osx = env(&[]);
+        osx.os_name = "osx".to_string();
+        assert_eq!(evaluate(jvm, &osx)[0], "-XstartOnFirstThread");
+        assert!(!evaluate(jvm, &env(&[])).iter().any(|a| a == "-XstartOnFirstThread" || a == "-Xss1M"));
+    }
+
+    #[test]
+    fn quick_play_support_is_detected() {
+        assert!(supports_quick_play(&version("1.21")));
+        assert!(!supports_quick_play(&version("1.8")));
+    }
+
+    #[test]
+    fn legacy_arguments_get_demo_and_resolution() {
+        let dir = game_dir("legacy", &["1.8"]);
+        let mut instance = instance(&dir, "1.8");
+        instance.demo = true;
+        instance.resolution = Some(Resolution { width: 854, height: 480 });
+        instance.quick_play = Some(QuickPlay::Multiplayer("mc.example.com".to_string()));
+
+        let command = launch_command(&instance, &Progress::default(), &CancellationToken::new()).unwrap();
+        let argv = &command.argv;
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // Legacy JVM arguments, substituted
+        let natives = dir.join("natives").display().to_string();
+        assert!(argv.contains(&format!("-Djava.library.path={}", natives)));
+        assert!(!argv.iter().any(|a| a == "-Xss1M" || a == "-XstartOnFirstThread"));
+        assert!(command.temporary_natives_dir.is_none());
+
+        // minecraftArguments, then --demo, the resolution and --server
+        let main = argv.iter().position(|a| a == "net.minecraft.client.main.Main").unwrap();
+        assert!(has_pair(&argv[main..], "--username", "Steve"));
+        assert!(has_pair(&argv[main..], "--version", "1.8"));
+        assert!(argv.contains(&"--demo".to_string()));
+        assert!(has_pair(argv, "--width", "854") && has_pair(argv, "--height", "480"));
+        assert!(has_pair(argv, "--server", "mc.example.com") && has_pair(argv, "--port", "25565"));
+    }
+
+    #[test]
+    fn loader_command_keeps_vanilla_arguments() {
+        let dir = game_dir("neoforge", &["1.21", "neoforge-21.1.219"]);
+        let mut instance = instance(&dir, "neoforge-21.1.219");
+        instance.max_memory_mb = Some(4096);
+        instance.quick_play = Some(QuickPlay::Multiplayer("mc.example.com:25566".to_string()));
+
+        let command = launch_command(&instance, &Progress::default(), &CancellationToken::new()).unwrap();
+        let argv = &command.argv;
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let main = argv.iter().position(|a| a == "cpw.mods.bootstraplauncher.BootstrapLauncher").unwrap();
+        let (jvm, game) = argv.split_at(main);
+        assert_eq!(jvm[1], "-Xmx4096M");
+        assert!(jvm.contains(&format!("-DlibraryDirectory={}", dir.join("libraries").display())));
+        let classpath = &jvm[jvm.iter().position(|a| a == "-cp").unwrap() + 1];
+        assert!(classpath.contains("loader-4.0.42.jar") && classpath.contains("guava-32.1.2-jre.jar"));
+        assert!(classpath.ends_with("neoforge-21.1.219.jar"));
+        assert!(has_pair(game, "--version", "neoforge-21.1.219"));
+        assert!(has_pair(game, "--launchTarget", "forgeclient"));
+        assert!(has_pair(game, "--quickPlayMultiplayer", "mc.example.com:25566"));
+        assert!(!game.iter().any(|a| a == "--server" || a == "--demo" || a == "--width"));
+    }
+}
@@ -0,0 +1,518 @@
+//! Version JSON `libraries`: rule evaluation, natives classifier selection
+//! and native library extraction.
+//!
+//! Resolution is a pure function of the parsed libraries and an
+//! `Environment`, so any version JSON can be resolved for any OS/arch.
+//!
+//! Natives are what the official launcher extracts: libraries with a
+//! `natives` map (LWJGL 2, and LWJGL 3 up to 1.18), whose classifier jar is
+//! unpacked into the natives directory minus `extract.exclude`. From 1.19 on
+//! natives ship as plain `:natives-<os>` classpath libraries that LWJGL
+//! loads itself, so those are resolved like any other library.
+
+use crate::archive::extract_zip_excluding;
+use crate::cancel::{CancellationToken, check};
+use crate::context::CrystalCore;
+use crate::error::CoreError;
+use crate::ffi::{core_arg, ffi_string, join_error, str_arg};
+use crate::jobs::ffi_job;
+use crate::progress::Progress;
+use crate::rules::{Environment, Rule, rules_allow};
+use crate::safe_path::{safe_join, safe_relative_path};
+use crate::version::read_version;
+use std::collections::{BTreeMap, HashSet};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+
+/// Repository for libraries that only give a maven `name`.
+const DEFAULT_LIBRARY_REPO: &str = "https://libraries.minecraft.net/";
+
+/// One entry of a version JSON `libraries` array.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub(crate) struct Library {
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) downloads: Option<LibraryDownloads>,
+    #[serde(default)]
+    pub(crate) rules: Option<Vec<Rule>>,
+    /// OS name -> classifier (`natives-windows`, `natives-windows-${arch}`).
+    #[serde(default)]
+    pub(crate) natives: BTreeMap<String, String>,
+    #[serde(default)]
+    pub(crate) extract: Option<Extract>,
+    /// Maven repository for libraries without `downloads` (Fabric, older Forge).
+    #[serde(default)]
+    pub(crate) url: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+pub(crate) struct LibraryDownloads {
+    pub(crate) artifact: Option<Artifact>,
+    #[serde(default)]
+    pub(crate) classifiers: BTreeMap<String, Artifact>,
+}
+
+/// A downloadable file with its SHA-1 (`path` only for libraries).
+#[derive(Clone, Debug, serde::Deserialize)]
+pub(crate) struct Artifact {
+    #[serde(default)]
+    pub(crate) path: Option<String>,
+    #[serde(default)]
+    pub(crate) url: String,
+    #[serde(default)]
+    pub(crate) sha1: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+pub(crate) struct Extract {
+    #[serde(default)]
+    pub(crate) exclude: Vec<String>,
+}
+
+/// A library file that applies to the environment.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct ResolvedLibrary {
+    /// Maven coordinates from the version JSON.
+    pub name: String,
+    /// Path under `libraries/`.
+    pub path: String,
+    /// `None` for files generated locally (e.g. by a loader installer).
+    pub url: Option<String>,
+    pub sha1: Option<String>,
+    /// A natives jar to extract rather than put on the classpath.
+    pub natives: bool,
+    /// Entry prefixes not to extract (natives only).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+/// `group:artifact:version[:classifier][@ext]` -> `group/path/artifact/version/artifact-version[-classifier].ext`.
+pub(crate) fn maven_path(name: &str) -> Option<String> {
+    let (coords, ext) = name.split_once('@').unwrap_or((name, "jar"));
+    let mut parts = coords.split(':');
+    let (group, artifact, version) = (parts.next()?, parts.next()?, parts.next()?);
+    let classifier = parts.next().map(|c| format!("-{}", c)).unwrap_or_default();
+    if parts.next().is_some() || group.is_empty() || artifact.is_empty() || version.is_empty() {
+        return None;
+    }
+    Some(format!("{}/{}/{}/{}-{}{}.{}", group.replace('.', "/"), artifact, version, artifact, version, classifier, ext))
+}
+
+/// `group:artifact[:classifier]`: two entries with the same key are the same
+/// library in different versions.
+fn library_key(name: &str) -> String {
+    let coords = name.split('@').next().unwrap_or(name);
+    let parts: Vec<_> = coords.split(':').collect();
+    match parts.as_slice() {
+        [group, artifact, _, classifier, ..] => format!("{}:{}:{}", group, artifact, classifier),
+        [group, artifact, ..] => format!("{}:{}", group, artifact),
+        _ => coords.to_string(),
+    }
+}
+
+impl Library {
+    /// The natives classifier for `env`, with `${arch}` substituted.
+    pub(crate) fn natives_classifier(&self, env: &Environment) -> Option<String> {
+        self.natives.get(&env.os_name).map(|c| c.replace("${arch}", env.arch_bits()))
+    }
+
+    fn repo(&self) -> String {
+        let repo = self.url.as_deref().unwrap_or(DEFAULT_LIBRARY_REPO);
+        format!("{}/", repo.trim_end_matches('/'))
+    }
+
+    /// The files of this library that apply to `env`.
+    fn resolve(&self, env: &Environment) -> Result<Vec<ResolvedLibrary>, CoreError> {
+        if !rules_allow(self.rules.as_deref(), env) {
+            return Ok(Vec::new());
+        }
+        let invalid = || CoreError::invalid("library", format!("cannot resolve {}", self.name));
+        let entry = |artifact: Option<&Artifact>, name: &str, natives: bool| -> Result<ResolvedLibrary, CoreError> {
+            let path = match artifact.and_then(|a| a.path.clone()) {
+                Some(path) => path,
+                None => maven_path(name).ok_or_else(invalid)?,
+            };
+            safe_relative_path(&path)?;
+            let url = match artifact {
+                Some(a) => (!a.url.is_empty()).then(|| a.url.clone()),
+                None => Some(format!("{}{}", self.repo(), path)),
+            };
+            Ok(ResolvedLibrary {
+                name: name.to_string(),
+                path,
+                url,
+                sha1: artifact.and_then(|a| a.sha1.clone()).filter(|s| !s.is_empty()),
+                natives,
+                exclude: match natives {
+                    true => self.extract.as_ref().map(|e| e.exclude.clone()).unwrap_or_default(),
+                    false => Vec::new(),
+                },
+            })
+        };
+
+        let mut files = Vec::new();
+        let downloads = self.downloads.as_ref();
+        let artifact = downloads.and_then(|d| d.artifact.as_ref());
+        // A natives-only library (LWJGL 2 `*-platform`) has no main jar.
+        if artifact.is_some() || (downloads.is_none() && self.natives.is_empty()) {
+            files.push(entry(artifact, &self.name, false)?);
+        }
+        if let Some(classifier) = self.natives_classifier(env) {
+            let artifact = downloads.and_then(|d| d.classifiers.get(&classifier));
+            files.push(entry(artifact, &format!("{}:{}", self.name, classifier), true)?);
+        }
+        Ok(files)
+    }
+}
+
+/// Resolve `libraries` (child version first when merged from an
+/// `inheritsFrom` chain) for `env`. When a library appears more than once,
+/// the first occurrence wins.
+pub(crate) fn resolve_libraries(libraries: &[Library], env: &Environment) -> Result<Vec<ResolvedLibrary>, CoreError> {
+    let mut seen = HashSet::new();
+    let mut resolved = Vec::new();
+    for library in libraries {
+        for file in library.resolve(env)? {
+            if seen.insert(library_key(&file.name)) {
+                resolved.push(file);
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+/// Extract every natives jar of `libraries` from `libraries_dir` into
+/// `natives_dir`. Returns the number of files written.
+pub(crate) fn extract_natives(
+    libraries: &[ResolvedLibrary],
+    libraries_dir: &Path,
+    natives_dir: &Path,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> Result<u64, CoreError> {
+    std::fs::create_dir_all(natives_dir).map_err(|e| CoreError::io(natives_dir, e))?;
+    let mut written = 0;
+    for library in libraries.iter().filter(|l| l.natives) {
+        check(cancel)?;
+        let jar = safe_join(libraries_dir, &library.path)?;
+        written += extract_zip_excluding(&jar, &library.exclude, natives_dir, progress, cancel)?;
+    }
+    Ok(written)
+}
+
+/// A fresh natives directory for one launch: `versions/<id>/natives-<millis>`.
+pub(crate) fn launch_natives_dir(game_dir: &Path, version_id: &str) -> PathBuf {
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    game_dir.join("versions").join(version_id).join(format!("natives-{}", millis))
+}
+
+fn environment_arg(ptr: *const c_char) -> Result<Environment, CoreError> {
+    if ptr.is_null() {
+        return Ok(Environment::current());
+    }
+    serde_json::from_str(str_arg(ptr, "environment_json")?).map_err(|e| CoreError::invalid("environment_json", e))
+}
+
+/// Resolve the libraries of an installed version (following `inheritsFrom`).
+///
+/// # Arguments
+/// * `game_dir` - Game directory holding `versions/`
+/// * `version_id` - Installed version
+/// * `environment_json` - `{"os_name": "windows", "os_version": "10.0", "arch": "amd64",
+///   "features": {}}` (any field may be omitted), or null for this machine
+///
+/// # Returns
+/// * JSON `[{"name", "path", "url", "sha1", "natives": false, "exclude": [...]}, ...]`
+///   in classpath order (free with `free_string`)
+/// * null on error (see `crystal_last_error`)
+#[unsafe(no_mangle)]
+pub extern "C" fn resolve_version_libraries(
+    core: *const CrystalCore,
+    game_dir: *const c_char,
+    version_id: *const c_char,
+    environment_json: *const c_char,
+) -> *mut c_char {
+    ffi_string(|| {
+        core_arg(core)?;
+        let (version, _) = read_version(Path::new(str_arg(game_dir, "game_dir")?), str_arg(version_id, "version_id")?)?;
+        let resolved = resolve_libraries(&version.libraries, &environment_arg(environment_json)?)?;
+        serde_json::to_string(&resolved).map_err(|e| CoreError::Internal(e.to_string()))
+    })
+}
+
+/// What `extract_version_natives` needs, resolved before any work starts.
+struct NativesRequest {
+    libraries: Vec<ResolvedLibrary>,
+    libraries_dir: PathBuf,
+    natives_dir: PathBuf,
+}
+
+impl NativesRequest {
+    fn new(game_dir: &str, version_id: &str, natives_dir: *const c_char, env: &Environment) -> Result<Self, CoreError> {
+        let game_dir = Path::new(game_dir);
+        let libraries = resolve_libraries(&read_version(game_dir, version_id)?.0.libraries, env)?;
+        let natives_dir = match natives_dir.is_null() {
+            true => launch_natives_dir(game_dir, version_id),
+            false => PathBuf::from(str_arg(natives_dir, "natives_dir")?),
+        };
+        Ok(Self { libraries, libraries_dir: game_dir.join("libraries"), natives_dir })
+    }
+
+    fn run(self, progress: &Progress, cancel: &CancellationToken) -> Result<String, CoreError> {
+        let written = extract_natives(&self.libraries, &self.libraries_dir, &self.natives_dir, progress, cancel)?;
+        println!("[Rust] Extracted {} native files to {}", written, self.natives_dir.display());
+        Ok(self.natives_dir.display().to_string())
+    }
+}
+
+/// Extract the native libraries of an installed version for one launch.
+///
+/// # Arguments
+/// * `game_dir` - Game directory holding `versions/` and `libraries/`
+/// * `version_id` - Installed version (its `inheritsFrom` chain is followed)
+/// * `natives_dir` - Target directory, or null for a fresh
+///   `versions/<id>/natives-<millis>`
+/// * `environment_json` - See `resolve_version_libraries`, or null for this machine
+///
+/// # Returns
+/// * The natives directory (free with `free_string`)
+/// * null on error (see `crystal_last_error`)
+#[unsafe(no_mangle)]
+pub extern "C" fn extract_version_natives(
+    core: *const CrystalCore,
+    game_dir: *const c_char,
+    version_id: *const c_char,
+    natives_dir: *const c_char,
+    environment_json: *const c_char,
+) -> *mut c_char {
+    ffi_string(|| {
+        let core = core_arg(core)?;
+        let env = environment_arg(environment_json)?;
+        let request = NativesRequest::new(str_arg(game_dir, "game_dir")?, str_arg(version_id, "version_id")?, natives_dir, &env)?;
+        request.run(&Progress::for_core(core, None), &CancellationToken::new())
+    })
+}
+
+/// Background variant of `extract_version_natives`.
+///
+/// # Returns
+/// * Job id for `crystal_job_poll` (result: the natives directory)
+/// * negated `ErrorCode` on invalid arguments or an unreadable version
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_start_extract_version_natives(
+    core: *const CrystalCore,
+    game_dir: *const c_char,
+    version_id: *const c_char,
+    natives_dir: *const c_char,
+    environment_json: *const c_char,
+) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        let env = environment_arg(environment_json)?;
+        let request = NativesRequest::new(str_arg(game_dir, "game_dir")?, str_arg(version_id, "version_id")?, natives_dir, &env)?;
+
+        Ok(core.spawn_job("extract_version_natives", |progress, cancel| async move {
+            let dir = tokio::task::spawn_blocking(move || request.run(&progress, &cancel))
+                .await
+                .map_err(join_error)??;
+            Ok(serde_json::Value::String(dir))
+        }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::version::VersionJson;
+    use std::io::Write;
+
+    /// Trimmed official version JSONs: LWJGL 2 (`*-platform` natives),
+    /// LWJGL 3 with a `natives` map, and LWJGL 3 natives as plain libraries.
+    fn fixture(id: &str) -> Vec<Library> {
+        let json = match id {
+            "1.8" => include_str!("../tests/fixtures/versions/1.8.json"),
+            "1.16" => include_str!("../tests/fixtures/versions/1.16.json"),
+            "1.21" => include_str!("../tests/fixtures/versions/1.21.json"),
+            other => panic!("no fixture {}", other),
+        };
+        serde_json::from_str::<VersionJson>(json).unwrap().libraries
+    }
+
+    fn library<'a>(libraries: &'a [Library], name: &str) -> &'a Library {
+        libraries.iter().find(|l| l.name == name).unwrap()
+    }
+
+    fn env(os_name: &str, os_version: &str, arch: &str) -> Environment {
+        Environment {
+            os_name: os_name.to_string(),
+            os_version: os_version.to_string(),
+            arch: arch.to_string(),
+            features: BTreeMap::new(),
+        }
+    }
+
+    fn names(resolved: &[ResolvedLibrary]) -> Vec<&str> {
+        resolved.iter().map(|l| l.name.as_str()).collect()
+    }
+
+    #[test]
+    fn rules_allow_per_os() {
+        let libraries = fixture("1.8");
+        let not_osx = library(&libraries, "org.lwjgl.lwjgl:lwjgl:2.9.4-nightly-20150209");
+        let not_linux = library(&libraries, "tv.twitch:twitch-platform:6.5");
+        let everywhere = library(&libraries, "com.mojang:netty:1.6");
+
+        let cases = [
+            (env("windows", "10.0", "amd64"), true, true),
+            (env("osx", "10.9.5", "amd64"), false, true),
+            (env("linux", "6.1.0", "amd64"), true, false),
+        ];
+        for (env, expect_not_osx, expect_not_linux) in cases {
+            assert_eq!(rules_allow(not_osx.rules.as_deref(), &env), expect_not_osx, "{}", env.os_name);
+            assert_eq!(rules_allow(not_linux.rules.as_deref(), &env), expect_not_linux, "{}", env.os_name);
+            assert!(rules_allow(everywhere.rules.as_deref(), &env), "{}", env.os_name);
+        }
+    }
+
+    #[test]
+    fn rules_allow_os_version_regex() {
+        let libraries = fixture("1.8");
+        let leopard_only = library(&libraries, "org.lwjgl.lwjgl:lwjgl:2.9.1");
+        let not_leopard = library(&libraries, "org.lwjgl.lwjgl:lwjgl:2.9.2-nightly-20140822");
+
+        let leopard = env("osx", "10.5.8", "x86");
+        let mavericks = env("osx", "10.9.5", "amd64");
+        assert!(rules_allow(leopard_only.rules.as_deref(), &leopard));
+        assert!(!rules_allow(leopard_only.rules.as_deref(), &mavericks));
+        assert!(!rules_allow(not_leopard.rules.as_deref(), &leopard));
+        assert!(rules_allow(not_leopard.rules.as_deref(), &mavericks));
+        assert!(!rules_allow(leopard_only.rules.as_deref(), &env("windows", "10.5.8", "x86")));
+    }
+
+    #[test]
+    fn rules_allow_arch() {
+        let libraries = fixture("1.21");
+        let x86 = library(&libraries, "org.lwjgl:lwjgl:3.3.3:natives-windows-x86");
+
+        assert!(rules_allow(x86.rules.as_deref(), &env("windows", "10.0", "x86")));
+        assert!(!rules_allow(x86.rules.as_deref(), &env("windows", "10.0", "amd64")));
+        assert!(!rules_allow(x86.rules.as_deref(), &env("linux", "6.1.0", "x86")));
+
+        let resolved = resolve_libraries(&libraries, &env("windows", "10.0", "amd64")).unwrap();
+        assert!(names(&resolved).contains(&"org.lwjgl:lwjgl:3.3.3:natives-windows"));
+        assert!(!names(&resolved).contains(&"org.lwjgl:lwjgl:3.3.3:natives-windows-x86"));
+    }
+
+    #[test]
+    fn natives_classifier_substitutes_arch() {
+        let libraries = fixture("1.8");
+        let twitch = library(&libraries, "tv.twitch:twitch-platform:6.5");
+
+        assert_eq!(twitch.natives_classifier(&env("windows", "10.0", "x86")).as_deref(), Some("natives-windows-32"));
+        assert_eq!(twitch.natives_classifier(&env("windows", "10.0", "amd64")).as_deref(), Some("natives-windows-64"));
+        assert_eq!(twitch.natives_classifier(&env("osx", "10.9.5", "amd64")).as_deref(), Some("natives-osx"));
+        assert_eq!(twitch.natives_classifier(&env("linux", "6.1.0", "amd64")), None);
+
+        let resolved = twitch.resolve(&env("windows", "10.0", "x86")).unwrap();
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].path, "tv/twitch/twitch-platform/6.5/twitch-platform-6.5-natives-windows-32.jar");
+        assert!(resolved[0].natives);
+    }
+
+    #[test]
+    fn lwjgl2_platform_is_natives_only() {
+        let libraries = fixture("1.8");
+        let platform = library(&libraries, "org.lwjgl.lwjgl:lwjgl-platform:2.9.4-nightly-20150209");
+
+        let resolved = platform.resolve(&env("linux", "6.1.0", "amd64")).unwrap();
+        assert_eq!(resolved.len(), 1);
+        let natives = &resolved[0];
+        assert!(natives.natives);
+        assert_eq!(natives.name, "org.lwjgl.lwjgl:lwjgl-platform:2.9.4-nightly-20150209:natives-linux");
+        assert_eq!(
+            natives.path,
+            "org/lwjgl/lwjgl/lwjgl-platform/2.9.4-nightly-20150209/lwjgl-platform-2.9.4-nightly-20150209-natives-linux.jar"
+        );
+        assert_eq!(natives.sha1.as_deref(), Some("931074f46c795d2f7b30ed6395df5715cfd7675b"));
+        assert_eq!(natives.exclude, ["META-INF/"]);
+    }
+
+    #[test]
+    fn lwjgl3_natives_map_and_plain_natives() {
+        // 1.16: the natives jar is extracted next to the classpath jar
+        let resolved = resolve_libraries(&fixture("1.16"), &env("osx", "11.2", "amd64")).unwrap();
+        let lwjgl: Vec<_> = resolved.iter().filter(|l| l.name.starts_with("org.lwjgl:lwjgl:")).collect();
+        assert_eq!(lwjgl.len(), 2);
+        assert!(!lwjgl[0].natives);
+        assert_eq!(lwjgl[1].name, "org.lwjgl:lwjgl:3.2.2:natives-macos");
+        assert!(lwjgl[1].natives);
+        assert!(names(&resolved).contains(&"ca.weblite:java-objc-bridge:1.0.0"));
+
+        // 1.21: natives are ordinary classpath libraries
+        let resolved = resolve_libraries(&fixture("1.21"), &env("linux", "6.1.0", "amd64")).unwrap();
+        assert_eq!(
+            names(&resolved),
+            ["com.google.guava:guava:32.1.2-jre", "org.lwjgl:lwjgl:3.3.3", "org.lwjgl:lwjgl:3.3.3:natives-linux"]
+        );
+        assert!(resolved.iter().all(|l| !l.natives));
+    }
+
+    #[test]
+    fn resolve_libraries_dedupes_by_key() {
+        // A child version (loader profile) listed first overrides the parent's guava
+        let child: Library = serde_json::from_value(serde_json::json!({
+            "name": "com.google.guava:guava:33.0.0-jre",
+            "url": "https://maven.example.com/"
+        }))
+        .unwrap();
+        let mut libraries = vec![child];
+        libraries.extend(fixture("1.21"));
+
+        let resolved = resolve_libraries(&libraries, &env("linux", "6.1.0", "amd64")).unwrap();
+        assert_eq!(
+            names(&resolved),
+            ["com.google.guava:guava:33.0.0-jre", "org.lwjgl:lwjgl:3.3.3", "org.lwjgl:lwjgl:3.3.3:natives-linux"]
+        );
+        assert_eq!(
+            resolved[0].url.as_deref(),
+            Some("https://maven.example.com/com/google/guava/guava/33.0.0-jre/guava-33.0.0-jre.jar")
+        );
+        assert_eq!(library_key("org.lwjgl:lwjgl:3.3.3:natives-linux"), "org.lwjgl:lwjgl:natives-linux");
+        assert_eq!(library_key("com.google.guava:guava:33.0.0-jre"), "com.google.guava:guava");
+    }
+
+    #[test]
+    fn extract_natives_honours_exclude() {
+        let root = std::env::temp_dir().join(format!("crystal-natives-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let (libraries_dir, natives_dir) = (root.join("libraries"), root.join("natives"));
+
+        let resolved = resolve_libraries(&fixture("1.8"), &env("linux", "6.1.0", "amd64")).unwrap();
+        let platform = resolved.iter().find(|l| l.natives).unwrap();
+        let jar = libraries_dir.join(&platform.path);
+        std::fs::create_dir_all(jar.parent().unwrap()).unwrap();
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&jar).unwrap());
+        for (name, data) in [
+            ("META-INF/MANIFEST.MF", "Manifest-Version: 1.0\n"),
+            ("META-INF/LWJGL.SF", "signature\n"),
+            ("liblwjgl64.so", "elf"),
+            ("libopenal64.so", "elf"),
+        ] {
+            zip.start_file(name, zip::write::FileOptions::default()).unwrap();
+            zip.write_all(data.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let written =
+            extract_natives(&resolved, &libraries_dir, &natives_dir, &Progress::default(), &CancellationToken::new())
+                .unwrap();
+        assert_eq!(written, 2);
+        assert!(natives_dir.join("liblwjgl64.so").is_file());
+        assert!(natives_dir.join("libopenal64.so").is_file());
+        assert!(!natives_dir.join("META-INF").exists());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,484 @@
+//! Versioned, Ed25519-signed modpack manifest.
+//!
+//! The admin side builds a `PackManifest` from the pack folder (sizes and
+//! hashes computed here), signs it with the pack's secret key and publishes
+//! the resulting `SignedManifest`. The launcher only trusts files listed in
+//! a manifest that verifies against the public key it ships with, so a
+//! compromised database or bucket cannot push arbitrary jars to players.
+//!
+//! `download_mods_parallel`, `plan_sync` and `apply_sync` only accept file
+//! lists in this form: they take the signed envelope plus the pinned public
+//! key and refuse to do anything if it does not verify.
+//!
+//! The signature covers the exact bytes of the `manifest` string inside the
+//! envelope, so no JSON canonicalisation is involved:
+//! `{"manifest": "{\"format_version\":1,...}", "signature": "<hex>"}`
+
+use crate::cancel::CancellationToken;
+use crate::context::CrystalCore;
+use crate::error::CoreError;
+use crate::ffi::{core_arg, ffi_string, json_arg, str_arg, to_json};
+use crate::hash_cache::HashCache;
+use crate::hashing::{HashAlgorithm, hash_files, is_hex};
+use crate::jobs::ffi_job;
+use crate::progress::Progress;
+use crate::r2_sync::ModInfo;
+use crate::safe_path::safe_relative_path;
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Newest `format_version` this build understands.
+pub const MANIFEST_FORMAT_VERSION: u32 = 1;
+
+/// Prepended to the manifest bytes before signing, so a pack key can never
+/// be tricked into signing anything else that verifies as a manifest.
+const SIGNING_CONTEXT: &[u8] = b"CrystalTides pack manifest v1\n";
+
+/// Where a file is needed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    #[default]
+    Both,
+    Client,
+    Server,
+}
+
+/// One file of the pack.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct ManifestFile {
+    /// Target path relative to the instance, with `/` separators (`mods/foo.jar`).
+    pub path: String,
+    pub url: String,
+    pub size: u64,
+    pub sha1: String,
+    pub sha512: String,
+    #[serde(default)]
+    pub side: Side,
+    /// Users may opt out of optional files.
+    #[serde(default)]
+    pub optional: bool,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct PackManifest {
+    pub format_version: u32,
+    pub pack_version: String,
+    pub minecraft_version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub neoforge_version: Option<String>,
+    pub files: Vec<ManifestFile>,
+}
+
+/// What gets published.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct SignedManifest {
+    /// `PackManifest` as JSON, exactly as signed.
+    pub manifest: String,
+    /// Hex Ed25519 signature of `SIGNING_CONTEXT` + `manifest`.
+    #[serde(default)]
+    pub signature: Option<String>,
+}
+
+/// Input of `manifest_generate`.
+#[derive(Debug, serde::Deserialize)]
+pub struct ManifestSpec {
+    pub pack_version: String,
+    pub minecraft_version: String,
+    #[serde(default)]
+    pub neoforge_version: Option<String>,
+    /// Local folder laid out like an instance (`mods/`, `config/`...).
+    pub root: String,
+    /// Download URL of a file, unless it has its own: `base_url` + its path.
+    pub base_url: String,
+    /// Files to include; empty means every file below `root`.
+    #[serde(default)]
+    pub files: Vec<FileSpec>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct FileSpec {
+    pub path: String,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub side: Side,
+    #[serde(default)]
+    pub optional: bool,
+}
+
+impl PackManifest {
+    /// Reject manifests this build cannot apply safely.
+    fn validate(&self) -> Result<(), CoreError> {
+        if self.format_version == 0 || self.format_version > MANIFEST_FORMAT_VERSION {
+            return Err(CoreError::invalid(
+                "manifest",
+                format!("unsupported format_version {} (max {})", self.format_version, MANIFEST_FORMAT_VERSION),
+            ));
+        }
+        let mut seen = std::collections::HashSet::new();
+        for file in &self.files {
+            safe_relative_path(&file.path)?;
+            if !seen.insert(file.path.as_str()) {
+                return Err(CoreError::invalid("manifest", format!("{} is listed twice", file.path)));
+            }
+            if !is_hex(&file.sha1, 40) || !is_hex(&file.sha512, 128) {
+                return Err(CoreError::invalid("manifest", format!("bad hashes for {}", file.path)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Files a client installs (`both` and `client`), in manifest order, as
+    /// downloads named by their path relative to the instance.
+    pub(crate) fn client_files(&self) -> Vec<ModInfo> {
+        self.files
+            .iter()
+            .filter(|f| f.side != Side::Server)
+            .map(|f| ModInfo { name: f.path.clone(), url: f.url.clone(), sha1: f.sha1.to_ascii_lowercase(), hash: None })
+            .collect()
+    }
+
+    /// The client files directly inside `mods/`, named by file name: the
+    /// list `plan_sync` keeps `mods/` in line with.
+    pub(crate) fn client_mods(&self) -> Vec<ModInfo> {
+        self.client_files()
+            .into_iter()
+            .filter_map(|m| {
+                let name = m.name.strip_prefix("mods/").filter(|n| !n.contains('/'))?.to_string();
+                Some(ModInfo { name, ..m })
+            })
+            .collect()
+    }
+}
+
+fn signature_error(reason: impl Into<String>) -> CoreError {
+    CoreError::Signature { reason: reason.into() }
+}
+
+/// Decode a hex key of exactly 32 bytes.
+fn key_bytes(hex_key: &str, name: &'static str) -> Result<[u8; 32], CoreError> {
+    hex::decode(hex_key.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| CoreError::invalid(name, "expected 64 hex characters"))
+}
+
+/// New random signing key: `(public_key_hex, secret_key_hex)`.
+pub fn generate_signing_keypair() -> Result<(String, String), CoreError> {
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).map_err(|e| CoreError::Internal(format!("no system randomness: {}", e)))?;
+    let key = SigningKey::from_bytes(&seed);
+    Ok((hex::encode(key.verifying_key().as_bytes()), hex::encode(seed)))
+}
+
+/// Sign `manifest` with the hex secret key.
+pub fn sign_manifest(manifest: &PackManifest, secret_key: &str) -> Result<SignedManifest, CoreError> {
+    manifest.validate()?;
+    let key = SigningKey::from_bytes(&key_bytes(secret_key, "secret_key")?);
+    let body = serde_json::to_string(manifest).map_err(|e| CoreError::Internal(e.to_string()))?;
+    let signature = key.sign(&[SIGNING_CONTEXT, body.as_bytes()].concat());
+    Ok(SignedManifest { manifest: body, signature: Some(hex::encode(signature.to_bytes())) })
+}
+
+/// Check the signature of `signed` against the hex public key and return
+/// the manifest. Unsigned and tampered manifests are rejected.
+pub fn verify_manifest(signed: &SignedManifest, public_key: &str) -> Result<PackManifest, CoreError> {
+    let key = VerifyingKey::from_bytes(&key_bytes(public_key, "public_key")?)
+        .map_err(|e| CoreError::invalid("public_key", e))?;
+    let signature = signed.signature.as_deref().ok_or_else(|| signature_error("manifest is not signed"))?;
+    let signature = hex::decode(signature)
+        .ok()
+        .and_then(|bytes| ed25519_dalek::Signature::from_slice(&bytes).ok())
+        .ok_or_else(|| signature_error("malformed signature"))?;
+
+    key.verify_strict(&[SIGNING_CONTEXT, signed.manifest.as_bytes()].concat(), &signature)
+        .map_err(|_| signature_error("signature does not match (wrong key or tampered manifest)"))?;
+
+    let manifest: PackManifest =
+        serde_json::from_str(&signed.manifest).map_err(|e| CoreError::invalid("manifest", e))?;
+    manifest.validate()?;
+    Ok(manifest)
+}
+
+/// Decode and verify the `signed_json` / `public_key` arguments of an export.
+pub(crate) fn verified_manifest_arg(signed_json: *const c_char, public_key: *const c_char) -> Result<PackManifest, CoreError> {
+    let signed: SignedManifest = json_arg(signed_json, "signed_manifest_json")?;
+    verify_manifest(&signed, str_arg(public_key, "public_key")?)
+}
+
+/// Every file below `root`, as `/`-separated relative paths.
+pub(crate) fn walk(root: &Path, dir: &Path, out: &mut Vec<String>) -> Result<(), CoreError> {
+    for entry in std::fs::read_dir(dir).map_err(|e| CoreError::io(dir, e))? {
+        let path = entry.map_err(|e| CoreError::io(dir, e))?.path();
+        if path.is_dir() {
+            walk(root, &path, out)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let parts: Vec<_> = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect();
+            out.push(parts.join("/"));
+        }
+    }
+    Ok(())
+}
+
+/// Default download URL of `path`: `base_url` followed by the path, each
+/// segment percent-encoded (file names may hold `#`, `?` or spaces).
+fn file_url(base_url: &reqwest::Url, path: &str) -> Result<String, CoreError> {
+    let mut url = base_url.clone();
+    url.path_segments_mut()
+        .map_err(|_| CoreError::invalid("base_url", "cannot have relative files"))?
+        .pop_if_empty()
+        .extend(path.split('/'));
+    Ok(url.into())
+}
+
+/// Build the manifest for the pack folder described by `spec`, hashing its
+/// files in parallel (through the hash cache).
+pub async fn generate_manifest(
+    spec: ManifestSpec,
+    cache: Arc<HashCache>,
+    progress: Progress,
+    cancel: CancellationToken,
+) -> Result<PackManifest, CoreError> {
+    // 1. File list
+    let root = PathBuf::from(&spec.root);
+    let base_url = reqwest::Url::parse(&spec.base_url).map_err(|e| CoreError::invalid("base_url", e))?;
+    let mut files = spec.files;
+    if files.is_empty() {
+        let mut paths = Vec::new();
+        walk(&root, &root, &mut paths)?;
+        files = paths
+            .into_iter()
+            .map(|path| FileSpec { path, url: None, side: Side::Both, optional: false })
+            .collect();
+    }
+    for file in &files {
+        safe_relative_path(&file.path)?;
+    }
+
+    // 2. Hashes
+    let paths = files.iter().map(|f| root.join(&f.path).display().to_string()).collect();
+    let hashes = hash_files(paths, vec![HashAlgorithm::Sha1, HashAlgorithm::Sha512], cache, progress, cancel).await?;
+
+    // 3. Entries
+    let mut entries = Vec::with_capacity(files.len());
+    for (file, (_, result)) in files.into_iter().zip(hashes) {
+        let hashes = result?;
+        let url = match file.url {
+            Some(url) => url,
+            None => file_url(&base_url, &file.path)?,
+        };
+        entries.push(ManifestFile {
+            url,
+            path: file.path,
+            size: hashes.size,
+            sha1: hashes.sha1.unwrap_or_default(),
+            sha512: hashes.sha512.unwrap_or_default(),
+            side: file.side,
+            optional: file.optional,
+        });
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(PackManifest {
+        format_version: MANIFEST_FORMAT_VERSION,
+        pack_version: spec.pack_version,
+        minecraft_version: spec.minecraft_version,
+        neoforge_version: spec.neoforge_version,
+        files: entries,
+    })
+}
+
+/// Create a new manifest signing key pair. Keep the secret key offline.
+///
+/// # Returns
+/// * JSON `{"public_key": "<64 hex>", "secret_key": "<64 hex>"}` (free with `free_string`)
+/// * null on error (see `crystal_last_error`)
+#[unsafe(no_mangle)]
+pub extern "C" fn manifest_generate_keypair(core: *const CrystalCore) -> *mut c_char {
+    ffi_string(|| {
+        core_arg(core)?;
+        let (public_key, secret_key) = generate_signing_keypair()?;
+        Ok(serde_json::json!({ "public_key": public_key, "secret_key": secret_key }).to_string())
+    })
+}
+
+/// Build an (unsigned) manifest from a local pack folder.
+///
+/// # Arguments
+/// * `spec_json` - `{"pack_version": "1.4.0", "minecraft_version": "1.21.1", "neoforge_version": "21.1.77",
+///   "root": "C:/pack", "base_url": "https://cdn/pack",
+///   "files": [{"path": "mods/a.jar", "side": "client", "optional": false, "url": null}]}`
+///   (`files` may be omitted to include everything below `root`)
+///
+/// # Returns
+/// * Manifest JSON for `manifest_sign` (free with `free_string`)
+/// * null on error (see `crystal_last_error`)
+#[unsafe(no_mangle)]
+pub extern "C" fn manifest_generate(core: *const CrystalCore, spec_json: *const c_char) -> *mut c_char {
+    ffi_string(|| {
+        let core = core_arg(core)?;
+        let spec: ManifestSpec = json_arg(spec_json, "spec_json")?;
+
+        let manifest = core.runtime.block_on(generate_manifest(
+            spec,
+            core.hash_cache.clone(),
+            Progress::for_core(core, None),
+            CancellationToken::new(),
+        ))?;
+        Ok(to_json(&manifest)?.to_string())
+    })
+}
+
+/// Background variant of `manifest_generate`.
+///
+/// # Returns
+/// * Job id for `crystal_job_poll` (result: the manifest)
+/// * negated `ErrorCode` on invalid arguments
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_start_manifest_generate(core: *const CrystalCore, spec_json: *const c_char) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        let spec: ManifestSpec = json_arg(spec_json, "spec_json")?;
+        let cache = core.hash_cache.clone();
+
+        Ok(core.spawn_job("manifest_generate", |progress, cancel| async move {
+            let manifest = generate_manifest(spec, cache, progress, cancel).await?;
+            to_json(&manifest)
+        }))
+    })
+}
+
+/// Sign a manifest.
+///
+/// # Arguments
+/// * `manifest_json` - Manifest from `manifest_generate`
+/// * `secret_key` - Hex secret key from `manifest_generate_keypair`
+///
+/// # Returns
+/// * Signed manifest JSON `{"manifest": "...", "signature": "..."}` to publish (free with `free_string`)
+/// * null on error (see `crystal_last_error`)
+#[unsafe(no_mangle)]
+pub extern "C" fn manifest_sign(
+    core: *const CrystalCore,
+    manifest_json: *const c_char,
+    secret_key: *const c_char,
+) -> *mut c_char {
+    ffi_string(|| {
+        core_arg(core)?;
+        let manifest: PackManifest = json_arg(manifest_json, "manifest_json")?;
+        let secret_key = str_arg(secret_key, "secret_key")?;
+        Ok(to_json(&sign_manifest(&manifest, secret_key)?)?.to_string())
+    })
+}
+
+/// Verify a signed manifest and return its contents.
+///
+/// # Arguments
+/// * `signed_json` - Signed manifest as published
+/// * `public_key` - Hex public key the launcher trusts
+///
+/// # Returns
+/// * Manifest JSON (free with `free_string`)
+/// * null on error (see `crystal_last_error`): `SIGNATURE` (-31) for unsigned
+///   or tampered manifests
+#[unsafe(no_mangle)]
+pub extern "C" fn manifest_verify(
+    core: *const CrystalCore,
+    signed_json: *const c_char,
+    public_key: *const c_char,
+) -> *mut c_char {
+    ffi_string(|| {
+        core_arg(core)?;
+        let signed: SignedManifest = json_arg(signed_json, "signed_json")?;
+        let public_key = str_arg(public_key, "public_key")?;
+        Ok(to_json(&verify_manifest(&signed, public_key)?)?.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(paths: &[&str]) -> PackManifest {
+        PackManifest {
+            format_version: MANIFEST_FORMAT_VERSION,
+            pack_version: "1.4.0".to_string(),
+            minecraft_version: "1.21.1".to_string(),
+            neoforge_version: Some("21.1.77".to_string()),
+            files: paths
+                .iter()
+                .map(|path| ManifestFile {
+                    path: path.to_string(),
+                    url: format!("https://cdn.example/pack/{}", path),
+                    size: 3,
+                    sha1: "a9993e364706816aba3e25717850c26c9cd0d89d".to_string(),
+                    sha512: "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+                             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+                        .to_string(),
+                    side: Side::Both,
+                    optional: false,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn signed_manifest_round_trips() {
+        let (public_key, secret_key) = generate_signing_keypair().unwrap();
+        let signed = sign_manifest(&manifest(&["mods/a.jar", "config/a.toml"]), &secret_key).unwrap();
+
+        // Through JSON, as published
+        let signed: SignedManifest = serde_json::from_str(&serde_json::to_string(&signed).unwrap()).unwrap();
+        let verified = verify_manifest(&signed, &public_key).unwrap();
+
+        assert_eq!(verified.pack_version, "1.4.0");
+        assert_eq!(verified.neoforge_version.as_deref(), Some("21.1.77"));
+        let paths: Vec<_> = verified.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["mods/a.jar", "config/a.toml"]);
+    }
+
+    #[test]
+    fn tampered_manifest_is_rejected() {
+        let (public_key, secret_key) = generate_signing_keypair().unwrap();
+        let mut signed = sign_manifest(&manifest(&["mods/a.jar"]), &secret_key).unwrap();
+        signed.manifest = signed.manifest.replace("cdn.example", "evil.example");
+
+        let err = verify_manifest(&signed, &public_key).unwrap_err();
+        assert!(matches!(err, CoreError::Signature { .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn wrong_key_and_unsigned_manifests_are_rejected() {
+        let (_, secret_key) = generate_signing_keypair().unwrap();
+        let (other_public_key, _) = generate_signing_keypair().unwrap();
+        let mut signed = sign_manifest(&manifest(&["mods/a.jar"]), &secret_key).unwrap();
+
+        let err = verify_manifest(&signed, &other_public_key).unwrap_err();
+        assert!(matches!(err, CoreError::Signature { .. }), "{:?}", err);
+
+        signed.signature = None;
+        let err = verify_manifest(&signed, &other_public_key).unwrap_err();
+        assert!(matches!(err, CoreError::Signature { .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn duplicate_paths_are_rejected() {
+        let (_, secret_key) = generate_signing_keypair().unwrap();
+        assert!(sign_manifest(&manifest(&["mods/a.jar", "mods/b.jar", "mods/a.jar"]), &secret_key).is_err());
+    }
+
+    #[test]
+    fn default_urls_percent_encode_each_segment() {
+        let base = reqwest::Url::parse("https://cdn.example/pack/").unwrap();
+        assert_eq!(
+            file_url(&base, "mods/Mod #1 100%.jar").unwrap(),
+            "https://cdn.example/pack/mods/Mod%20%231%20100%25.jar"
+        );
+
+        let base = reqwest::Url::parse("https://cdn.example/pack").unwrap();
+        assert_eq!(file_url(&base, "config/a?b.toml").unwrap(), "https://cdn.example/pack/config/a%3Fb.toml");
+    }
+}
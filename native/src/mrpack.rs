@@ -0,0 +1,638 @@
+//! Modrinth modpacks (`.mrpack`).
+//!
+//! An `.mrpack` is a zip with a `modrinth.index.json` listing files to
+//! download (path, hashes, `env` side flags, mirror URLs) plus `overrides/`
+//! and `client-overrides/` folders copied over the instance as-is.
+//!
+//! Import downloads the listed client files through the parallel engine
+//! (SHA-1 checked while downloading, SHA-512 checked afterwards), then
+//! extracts `overrides/` and `client-overrides/` (in that order, so the
+//! client ones win). Export does the reverse: files Modrinth knows (looked
+//! up by SHA-512) are listed with their CDN URL, everything else is packed
+//! into `overrides/`.
+
+use crate::archive::extract_zip_prefix;
+use crate::cancel::{CancellationToken, check};
+use crate::context::CrystalCore;
+use crate::download::TEMP_SUFFIXES;
+use crate::error::CoreError;
+use crate::ffi::{core_arg, ffi_string, join_error, json_arg, options_arg, str_arg, to_json};
+use crate::hash_cache::HashCache;
+use crate::hashing::{HashAlgorithm, hash_files, is_hex};
+use crate::jobs::ffi_job;
+use crate::manifest::walk;
+use crate::net::Net;
+use crate::progress::Progress;
+use crate::r2_sync::{DownloadRequest, ModInfo, download_batch};
+use crate::safe_path::{safe_join, safe_relative_path};
+use crate::sync::WORK_DIRS;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const INDEX_FILE: &str = "modrinth.index.json";
+const OVERRIDES: &str = "overrides/";
+const CLIENT_OVERRIDES: &str = "client-overrides/";
+const DEFAULT_MODRINTH_API: &str = "https://api.modrinth.com/v2";
+
+/// Hosts an index may download from (the list the Modrinth format allows).
+const ALLOWED_HOSTS: &[&str] = &["cdn.modrinth.com", "github.com", "raw.githubusercontent.com", "gitlab.com"];
+
+/// Folders whose files Modrinth may host; only these are looked up on export.
+const HOSTED_DIRS: &[&str] = &["mods/", "resourcepacks/", "shaderpacks/"];
+
+/// `env.client` / `env.server` of an index file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvSupport {
+    Required,
+    Optional,
+    Unsupported,
+    /// Any other value; rejected when the index is validated.
+    #[serde(other, skip_serializing)]
+    Unknown,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct MrpackEnv {
+    pub client: EnvSupport,
+    pub server: EnvSupport,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct MrpackHashes {
+    pub sha1: String,
+    pub sha512: String,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MrpackFile {
+    pub path: String,
+    pub hashes: MrpackHashes,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<MrpackEnv>,
+    pub downloads: Vec<String>,
+    pub file_size: u64,
+}
+
+impl MrpackFile {
+    /// `downloads` in index order, minus URLs that are not HTTPS or not on
+    /// one of `ALLOWED_HOSTS`.
+    fn allowed_downloads(&self) -> Vec<&str> {
+        self.downloads
+            .iter()
+            .filter(|url| {
+                reqwest::Url::parse(url)
+                    .is_ok_and(|u| u.scheme() == "https" && u.host_str().is_some_and(|h| ALLOWED_HOSTS.contains(&h)))
+            })
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// `modrinth.index.json`.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MrpackIndex {
+    pub format_version: u32,
+    pub game: String,
+    pub version_id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    pub files: Vec<MrpackFile>,
+    /// `minecraft`, `neoforge`, `forge`, `fabric-loader`, `quilt-loader`.
+    pub dependencies: BTreeMap<String, String>,
+}
+
+impl MrpackIndex {
+    /// Reject indexes we cannot apply safely.
+    fn validate(&self) -> Result<(), CoreError> {
+        if self.format_version != 1 {
+            return Err(CoreError::invalid("mrpack", format!("unsupported formatVersion {}", self.format_version)));
+        }
+        if self.game != "minecraft" {
+            return Err(CoreError::invalid("mrpack", format!("unsupported game {:?}", self.game)));
+        }
+        if !self.dependencies.contains_key("minecraft") {
+            return Err(CoreError::invalid("mrpack", "no minecraft dependency"));
+        }
+        for file in &self.files {
+            safe_relative_path(&file.path)?;
+            if !is_hex(&file.hashes.sha1, 40) || !is_hex(&file.hashes.sha512, 128) {
+                return Err(CoreError::invalid("mrpack", format!("bad hashes for {}", file.path)));
+            }
+            if let Some(env) = &file.env {
+                for (side, support) in [("client", env.client), ("server", env.server)] {
+                    if support == EnvSupport::Unknown {
+                        return Err(CoreError::invalid(
+                            "mrpack",
+                            format!("env.{} of {} must be required, optional or unsupported", side, file.path),
+                        ));
+                    }
+                }
+            }
+            if file.allowed_downloads().is_empty() {
+                return Err(CoreError::invalid("mrpack", format!("no download URL on an allowed host for {}", file.path)));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn archive_error(path: &Path, source: zip::result::ZipError) -> CoreError {
+    CoreError::Archive { path: path.to_path_buf(), source }
+}
+
+/// Read and validate the index of an `.mrpack`.
+pub fn read_mrpack_index(mrpack: &Path) -> Result<MrpackIndex, CoreError> {
+    let file = std::fs::File::open(mrpack).map_err(|e| CoreError::io(mrpack, e))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| archive_error(mrpack, e))?;
+    let mut json = String::new();
+    archive
+        .by_name(INDEX_FILE)
+        .map_err(|e| archive_error(mrpack, e))?
+        .read_to_string(&mut json)
+        .map_err(|e| CoreError::io(mrpack, e))?;
+
+    let index: MrpackIndex = serde_json::from_str(&json).map_err(|e| CoreError::invalid("mrpack", e))?;
+    index.validate()?;
+    Ok(index)
+}
+
+/// Options of `import_mrpack`.
+#[derive(Debug, serde::Deserialize)]
+#[serde(default)]
+pub struct ImportOptions {
+    /// Also download files marked `optional` for the client.
+    pub include_optional: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self { include_optional: true }
+    }
+}
+
+/// Result of `import_mrpack`.
+#[derive(Debug, serde::Serialize)]
+pub struct ImportReport {
+    pub name: String,
+    pub version_id: String,
+    pub dependencies: BTreeMap<String, String>,
+    pub downloaded: Vec<String>,
+    /// Server-only files, and optional ones when `include_optional` is off.
+    pub skipped: Vec<String>,
+    /// Files written from `overrides/` and `client-overrides/`.
+    pub overrides: u64,
+}
+
+/// A validated pack plus what `import` needs from the core.
+struct ImportRequest {
+    mrpack: PathBuf,
+    instance_dir: PathBuf,
+    index: MrpackIndex,
+    /// Client files to download, as `(path, sha512)`.
+    selected: Vec<(String, String)>,
+    skipped: Vec<String>,
+    /// Allowed URLs of each selected file, tried in order.
+    mirrors: HashMap<String, Vec<String>>,
+    net: Net,
+    cache: Arc<HashCache>,
+    downloads: DownloadRequest,
+}
+
+impl ImportRequest {
+    fn new(core: &CrystalCore, mrpack: &str, instance_dir: &str, options: ImportOptions) -> Result<Self, CoreError> {
+        let mrpack = PathBuf::from(mrpack);
+        let index = read_mrpack_index(&mrpack)?;
+
+        let mut selected = Vec::new();
+        let mut skipped = Vec::new();
+        let mut mods = Vec::new();
+        let mut mirrors = HashMap::new();
+        for file in &index.files {
+            let wanted = match file.env.as_ref().map(|env| env.client) {
+                None | Some(EnvSupport::Required) => true,
+                Some(EnvSupport::Optional) => options.include_optional,
+                Some(EnvSupport::Unsupported | EnvSupport::Unknown) => false,
+            };
+            if !wanted {
+                skipped.push(file.path.clone());
+                continue;
+            }
+            let urls: Vec<String> = file.allowed_downloads().into_iter().map(str::to_string).collect();
+            selected.push((file.path.clone(), file.hashes.sha512.to_ascii_lowercase()));
+            mods.push(ModInfo {
+                name: file.path.clone(),
+                url: urls[0].clone(),
+                sha1: file.hashes.sha1.to_ascii_lowercase(),
+                hash: None,
+            });
+            mirrors.insert(file.path.clone(), urls);
+        }
+
+        let downloads = DownloadRequest::new(
+            core,
+            mods,
+            instance_dir.to_string(),
+            core.config().max_concurrent_downloads,
+        )?;
+        Ok(Self {
+            mrpack,
+            instance_dir: PathBuf::from(instance_dir),
+            index,
+            selected,
+            skipped,
+            mirrors,
+            net: core.net.clone(),
+            cache: core.hash_cache.clone(),
+            downloads,
+        })
+    }
+}
+
+/// Download the pack's files into the instance, check their SHA-512, then
+/// apply the overrides.
+async fn import(request: ImportRequest, progress: Progress, cancel: CancellationToken) -> Result<ImportReport, CoreError> {
+    let ImportRequest { mrpack, instance_dir, index, selected, skipped, mirrors, net, cache, downloads } = request;
+
+    // 1. Download (SHA-1 verified by the engine), moving failed files on to
+    //    their next URL until one works or none is left
+    tokio::fs::create_dir_all(&instance_dir).await.map_err(|e| CoreError::io(&instance_dir, e))?;
+    let first: Vec<ModInfo> = downloads.mods().to_vec();
+    let mut report = download_batch(net.clone(), downloads.clone(), progress.clone(), cancel.clone(), |_| {}).await?;
+    for mirror in 1.. {
+        let retry: Vec<ModInfo> = report
+            .failures()
+            .filter_map(|(name, _)| {
+                let url = mirrors.get(name)?.get(mirror)?;
+                let mod_info = first.iter().find(|m| m.name == name)?;
+                Some(ModInfo { url: url.clone(), ..mod_info.clone() })
+            })
+            .collect();
+        if retry.is_empty() {
+            break;
+        }
+        println!("[Rust] Retrying {} mrpack files from their next download URL", retry.len());
+        let retried = download_batch(net.clone(), downloads.clone().with_mods(retry)?, progress.clone(), cancel.clone(), |_| {}).await?;
+        report.replace(retried);
+    }
+    report.into_result()?;
+
+    // 2. SHA-512
+    let paths = selected
+        .iter()
+        .map(|(path, _)| Ok(safe_join(&instance_dir, path)?.display().to_string()))
+        .collect::<Result<Vec<_>, CoreError>>()?;
+    let hashes = hash_files(paths, vec![HashAlgorithm::Sha512], cache, progress.clone(), cancel.clone()).await?;
+    for ((_, expected), (file, result)) in selected.iter().zip(hashes) {
+        let actual = result?.sha512.unwrap_or_default();
+        if actual != *expected {
+            let _ = std::fs::remove_file(&file);
+            return Err(CoreError::HashMismatch { path: file.into(), expected: expected.clone(), actual });
+        }
+    }
+    check(&cancel)?;
+
+    // 3. Overrides, client ones last so they win
+    let target = instance_dir.clone();
+    let overrides = tokio::task::spawn_blocking(move || -> Result<u64, CoreError> {
+        let mut written = 0;
+        for prefix in [OVERRIDES, CLIENT_OVERRIDES] {
+            written += extract_zip_prefix(&mrpack, prefix, &target, &progress, &cancel)?;
+        }
+        Ok(written)
+    })
+    .await
+    .map_err(join_error)??;
+
+    println!(
+        "[Rust] Imported mrpack '{}' {}: {} files downloaded, {} skipped, {} overrides",
+        index.name, index.version_id, selected.len(), skipped.len(), overrides
+    );
+    Ok(ImportReport {
+        name: index.name,
+        version_id: index.version_id,
+        dependencies: index.dependencies,
+        downloaded: selected.into_iter().map(|(path, _)| path).collect(),
+        skipped,
+        overrides,
+    })
+}
+
+/// Options of `export_mrpack`.
+#[derive(Debug, serde::Deserialize)]
+pub struct ExportOptions {
+    pub name: String,
+    pub version_id: String,
+    #[serde(default)]
+    pub summary: Option<String>,
+    /// Must contain `minecraft`; loaders as in the index (`neoforge`...).
+    pub dependencies: BTreeMap<String, String>,
+    /// Instance folders to include.
+    #[serde(default = "default_include")]
+    pub include: Vec<String>,
+    /// Look files up on Modrinth; when off (or not found) they go to `overrides/`.
+    #[serde(default = "default_true")]
+    pub resolve: bool,
+    #[serde(default = "default_modrinth_api")]
+    pub modrinth_api: String,
+}
+
+fn default_include() -> Vec<String> {
+    ["mods", "config", "resourcepacks", "shaderpacks"].map(String::from).to_vec()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_modrinth_api() -> String {
+    DEFAULT_MODRINTH_API.to_string()
+}
+
+/// Result of `export_mrpack`.
+#[derive(Debug, serde::Serialize)]
+pub struct ExportReport {
+    /// Files listed in the index (downloaded from Modrinth on import).
+    pub files: Vec<String>,
+    /// Files packed into `overrides/`.
+    pub overrides: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct ModrinthVersion {
+    files: Vec<ModrinthVersionFile>,
+}
+
+#[derive(serde::Deserialize)]
+struct ModrinthVersionFile {
+    hashes: HashMap<String, String>,
+    url: String,
+}
+
+/// `POST /version_files`: the download URL of each known SHA-512.
+async fn lookup_hashes(
+    net: &Net,
+    api: &str,
+    sha512s: Vec<String>,
+    cancel: &CancellationToken,
+) -> Result<HashMap<String, String>, CoreError> {
+    let url = format!("{}/version_files", api.trim_end_matches('/'));
+    let _connection = net.limits.connect(&url, cancel).await?;
+    let resp = net.client
+        .post(&url)
+        .json(&serde_json::json!({ "hashes": sha512s, "algorithm": "sha512" }))
+        .send()
+        .await
+        .map_err(|e| CoreError::network(&url, e))?;
+    if !resp.status().is_success() {
+        return Err(CoreError::http_status(url, &resp));
+    }
+    let versions: HashMap<String, ModrinthVersion> = resp.json().await.map_err(|e| CoreError::network(&url, e))?;
+
+    Ok(versions
+        .into_iter()
+        .filter_map(|(sha512, version)| {
+            let file = version.files.into_iter().find(|f| f.hashes.get("sha512") == Some(&sha512))?;
+            Some((sha512, file.url))
+        })
+        .collect())
+}
+
+/// Pack `instance_dir` into an `.mrpack` at `output`.
+pub async fn export(
+    net: Net,
+    cache: Arc<HashCache>,
+    instance_dir: PathBuf,
+    options: ExportOptions,
+    output: PathBuf,
+    progress: Progress,
+    cancel: CancellationToken,
+) -> Result<ExportReport, CoreError> {
+    if !options.dependencies.contains_key("minecraft") {
+        return Err(CoreError::invalid("options_json", "dependencies must contain minecraft"));
+    }
+
+    // 1. File list
+    let mut paths = Vec::new();
+    for dir in &options.include {
+        let root = safe_join(&instance_dir, dir)?;
+        if root.is_dir() {
+            walk(&instance_dir, &root, &mut paths)?;
+        }
+    }
+    // Leftovers of interrupted downloads and sync work dirs are not part of the instance
+    paths.retain(|p| {
+        !TEMP_SUFFIXES.iter().any(|suffix| p.ends_with(suffix))
+            && !p.split('/').any(|segment| WORK_DIRS.contains(&segment))
+    });
+    paths.sort();
+
+    // 2. Resolve hosted files by SHA-512
+    let mut listed = Vec::new();
+    if options.resolve {
+        let hosted: Vec<_> = paths.iter().filter(|p| HOSTED_DIRS.iter().any(|d| p.starts_with(d))).cloned().collect();
+        let full = hosted.iter().map(|p| instance_dir.join(p).display().to_string()).collect();
+        let hashes = hash_files(
+            full,
+            vec![HashAlgorithm::Sha1, HashAlgorithm::Sha512],
+            cache,
+            progress.clone(),
+            cancel.clone(),
+        )
+        .await?;
+        let hashes = hosted
+            .into_iter()
+            .zip(hashes)
+            .map(|(path, (_, result))| Ok((path, result?)))
+            .collect::<Result<Vec<_>, CoreError>>()?;
+
+        if !hashes.is_empty() {
+            let sha512s = hashes.iter().filter_map(|(_, h)| h.sha512.clone()).collect();
+            let urls = lookup_hashes(&net, &options.modrinth_api, sha512s, &cancel).await?;
+            for (path, hashes) in hashes {
+                let sha512 = hashes.sha512.unwrap_or_default();
+                if let Some(url) = urls.get(&sha512) {
+                    listed.push(MrpackFile {
+                        path,
+                        hashes: MrpackHashes { sha1: hashes.sha1.unwrap_or_default(), sha512 },
+                        env: None,
+                        downloads: vec![url.clone()],
+                        file_size: hashes.size,
+                    });
+                }
+            }
+        }
+    }
+    check(&cancel)?;
+
+    // 3. Write the zip
+    let index = MrpackIndex {
+        format_version: 1,
+        game: "minecraft".to_string(),
+        version_id: options.version_id,
+        name: options.name,
+        summary: options.summary,
+        files: listed,
+        dependencies: options.dependencies,
+    };
+    let overrides: Vec<String> = paths.into_iter().filter(|p| !index.files.iter().any(|f| f.path == *p)).collect();
+    let report = ExportReport { files: index.files.iter().map(|f| f.path.clone()).collect(), overrides };
+
+    tokio::task::spawn_blocking(move || write_mrpack(&output, &index, &instance_dir, &report.overrides).map(|_| report))
+        .await
+        .map_err(join_error)?
+}
+
+/// Write the archive next to `output` and rename it into place.
+fn write_mrpack(output: &Path, index: &MrpackIndex, instance_dir: &Path, overrides: &[String]) -> Result<(), CoreError> {
+    let part = output.with_extension("mrpack.part");
+    let zip_error = |e| archive_error(&part, e);
+
+    let file = std::fs::File::create(&part).map_err(|e| CoreError::io(&part, e))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    zip.start_file(INDEX_FILE, options).map_err(zip_error)?;
+    let json = serde_json::to_vec_pretty(index).map_err(|e| CoreError::Internal(e.to_string()))?;
+    zip.write_all(&json).map_err(|e| CoreError::io(&part, e))?;
+
+    for path in overrides {
+        let source = instance_dir.join(path);
+        zip.start_file(format!("{}{}", OVERRIDES, path), options).map_err(zip_error)?;
+        let mut reader = std::fs::File::open(&source).map_err(|e| CoreError::io(&source, e))?;
+        std::io::copy(&mut reader, &mut zip).map_err(|e| CoreError::io(&source, e))?;
+    }
+    zip.finish().map_err(zip_error)?;
+
+    std::fs::rename(&part, output).map_err(|e| CoreError::io(output, e))
+}
+
+/// Import a Modrinth `.mrpack` into an instance directory.
+///
+/// # Arguments
+/// * `mrpack_path` - The `.mrpack` file
+/// * `instance_dir` - Instance (game) directory; created if missing
+/// * `options_json` - `{"include_optional": true}`, or null for defaults
+///
+/// # Returns
+/// * JSON `{"name", "version_id", "dependencies": {"minecraft": "1.21.1", ...},
+///   "downloaded": [...], "skipped": [...], "overrides": 12}` (free with `free_string`)
+/// * null on error (see `crystal_last_error`); `INCOMPLETE` (-80) when some
+///   downloads failed
+#[unsafe(no_mangle)]
+pub extern "C" fn import_mrpack(
+    core: *const CrystalCore,
+    mrpack_path: *const c_char,
+    instance_dir: *const c_char,
+    options_json: *const c_char,
+) -> *mut c_char {
+    ffi_string(|| {
+        let core = core_arg(core)?;
+        let request = ImportRequest::new(
+            core,
+            str_arg(mrpack_path, "mrpack_path")?,
+            str_arg(instance_dir, "instance_dir")?,
+            options_arg(options_json)?,
+        )?;
+
+        let report = core.runtime.block_on(import(request, Progress::for_core(core, None), CancellationToken::new()))?;
+        Ok(to_json(&report)?.to_string())
+    })
+}
+
+/// Background variant of `import_mrpack`.
+///
+/// # Returns
+/// * Job id for `crystal_job_poll` (result: the import report)
+/// * negated `ErrorCode` on invalid arguments or an invalid pack
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_start_import_mrpack(
+    core: *const CrystalCore,
+    mrpack_path: *const c_char,
+    instance_dir: *const c_char,
+    options_json: *const c_char,
+) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        let request = ImportRequest::new(
+            core,
+            str_arg(mrpack_path, "mrpack_path")?,
+            str_arg(instance_dir, "instance_dir")?,
+            options_arg(options_json)?,
+        )?;
+
+        Ok(core.spawn_job("import_mrpack", |progress, cancel| async move {
+            to_json(&import(request, progress, cancel).await?)
+        }))
+    })
+}
+
+/// Export an instance as a Modrinth `.mrpack`.
+///
+/// # Arguments
+/// * `instance_dir` - Instance (game) directory
+/// * `options_json` - `{"name": "My Pack", "version_id": "1.0.0", "summary": null,
+///   "dependencies": {"minecraft": "1.21.1", "neoforge": "21.1.77"},
+///   "include": ["mods", "config", "resourcepacks", "shaderpacks"],
+///   "resolve": true, "modrinth_api": "https://api.modrinth.com/v2"}`
+/// * `output_path` - The `.mrpack` to write
+///
+/// # Returns
+/// * JSON `{"files": [...], "overrides": [...]}` (free with `free_string`)
+/// * null on error (see `crystal_last_error`)
+#[unsafe(no_mangle)]
+pub extern "C" fn export_mrpack(
+    core: *const CrystalCore,
+    instance_dir: *const c_char,
+    options_json: *const c_char,
+    output_path: *const c_char,
+) -> *mut c_char {
+    ffi_string(|| {
+        let core = core_arg(core)?;
+        let instance_dir = PathBuf::from(str_arg(instance_dir, "instance_dir")?);
+        let options: ExportOptions = json_arg(options_json, "options_json")?;
+        let output = PathBuf::from(str_arg(output_path, "output_path")?);
+
+        let report = core.runtime.block_on(export(
+            core.net.clone(),
+            core.hash_cache.clone(),
+            instance_dir,
+            options,
+            output,
+            Progress::for_core(core, None),
+            CancellationToken::new(),
+        ))?;
+        Ok(to_json(&report)?.to_string())
+    })
+}
+
+/// Background variant of `export_mrpack`.
+///
+/// # Returns
+/// * Job id for `crystal_job_poll` (result: the export report)
+/// * negated `ErrorCode` on invalid arguments
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_start_export_mrpack(
+    core: *const CrystalCore,
+    instance_dir: *const c_char,
+    options_json: *const c_char,
+    output_path: *const c_char,
+) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        let instance_dir = PathBuf::from(str_arg(instance_dir, "instance_dir")?);
+        let options: ExportOptions = json_arg(options_json, "options_json")?;
+        let output = PathBuf::from(str_arg(output_path, "output_path")?);
+        let (net, cache) = (core.net.clone(), core.hash_cache.clone());
+
+        Ok(core.spawn_job("export_mrpack", |progress, cancel| async move {
+            to_json(&export(net, cache, instance_dir, options, output, progress, cancel).await?)
+        }))
+    })
+}
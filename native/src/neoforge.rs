@@ -0,0 +1,170 @@
+use crate::cancel::{CancellationToken, run_cancellable};
+use crate::context::CrystalCore;
+use crate::download::{download_file, part_meta_path, part_path};
+use crate::error::CoreError;
+use crate::ffi::{core_arg, ffi_status, str_arg};
+use crate::jobs::ffi_job;
+use crate::net::Net;
+use crate::progress::{Phase, Progress};
+use crate::retry::RetryPolicy;
+use crate::safe_path::safe_file_name;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+
+const NEOFORGE_MAVEN: &str = "https://maven.neoforged.net/releases/net/neoforged/neoforge";
+const FORGE_MAVEN: &str = "https://maven.minecraftforge.net/net/minecraftforge/forge";
+
+/// Download the NeoForge installer for `neo_version` and run it against `game_dir`.
+pub async fn install(
+    net: &Net,
+    retry: &RetryPolicy,
+    neo_version: &str,
+    game_dir: &Path,
+    java_path: &str,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> Result<(), CoreError> {
+    let file_name = format!("neoforge-{}-installer.jar", neo_version);
+    let url = format!("{}/{}/{}", NEOFORGE_MAVEN, neo_version, file_name);
+    install_from(net, retry, &url, &file_name, game_dir, java_path, progress, cancel).await
+}
+
+/// Same as `install` for (legacy) Forge, whose installer takes the same arguments.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn install_forge(
+    net: &Net,
+    retry: &RetryPolicy,
+    minecraft_version: &str,
+    forge_version: &str,
+    game_dir: &Path,
+    java_path: &str,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> Result<(), CoreError> {
+    let version = format!("{}-{}", minecraft_version, forge_version);
+    let file_name = format!("forge-{}-installer.jar", version);
+    let url = format!("{}/{}/{}", FORGE_MAVEN, version, file_name);
+    install_from(net, retry, &url, &file_name, game_dir, java_path, progress, cancel).await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn install_from(
+    net: &Net,
+    retry: &RetryPolicy,
+    url: &str,
+    file_name: &str,
+    game_dir: &Path,
+    java_path: &str,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> Result<(), CoreError> {
+    // 1. Construct Paths
+    let installer_path = game_dir.join(safe_file_name(file_name)?);
+
+    println!("[Rust] Downloading installer from: {}", url);
+
+    // 2. Download File
+    progress.set_items_total(2);
+    let result = async {
+        let item = progress.start_item(file_name, Phase::Downloading, None);
+        let (downloaded, _) = retry
+            .run(cancel, file_name, |attempt| {
+                if attempt > 1 {
+                    item.restart(Phase::Downloading);
+                }
+                download_file(net, url, &installer_path, None, &item, cancel)
+            })
+            .await;
+        item.finish(downloaded)?;
+
+        println!("[Rust] Download Complete. Running Installer...");
+
+        // 3. Run Java Installer (killed if the job is cancelled)
+        let item = progress.start_item("installer", Phase::Installing, None);
+        item.finish(run_cancellable(cancel, run_installer(java_path, &installer_path, game_dir)).await)
+    }
+    .await;
+
+    // 4. Cleanup (the installer is fetched per install, so a partial
+    //    download left by a cancel or failure is never resumed)
+    for path in [part_meta_path(&installer_path), part_path(&installer_path), installer_path] {
+        if path.exists() {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+
+    result
+}
+
+async fn run_installer(java_path: &str, installer_path: &Path, game_dir: &Path) -> Result<(), CoreError> {
+    // java -jar installer.jar --installClient gameDir
+    let out = tokio::process::Command::new(java_path)
+        .arg("-jar")
+        .arg(installer_path)
+        .arg("--installClient")
+        .arg(game_dir)
+        .current_dir(game_dir)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| CoreError::ProcessSpawn { program: java_path.to_string(), source: e })?;
+
+    if !out.status.success() {
+        return Err(CoreError::ProcessFailed {
+            program: java_path.to_string(),
+            status: out.status.to_string(),
+            stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+        });
+    }
+
+    println!("[Rust] Installer Success: {}", String::from_utf8_lossy(&out.stdout));
+    Ok(())
+}
+
+/// Download and run the NeoForge client installer.
+///
+/// # Returns
+/// * 1 on success
+/// * negated `ErrorCode` on error (see `crystal_last_error`)
+#[unsafe(no_mangle)]
+pub extern "C" fn install_neoforge(
+    core: *const CrystalCore,
+    neo_version_ptr: *const c_char,
+    game_dir_ptr: *const c_char,
+    java_path_ptr: *const c_char
+) -> i32 {
+    ffi_status(1, || {
+        let core = core_arg(core)?;
+        let neo_version = str_arg(neo_version_ptr, "neo_version")?;
+        let game_dir = str_arg(game_dir_ptr, "game_dir")?;
+        let java_path = str_arg(java_path_ptr, "java_path")?;
+        core.runtime.block_on(install(&core.net, &core.config().retry, neo_version, Path::new(game_dir), java_path, &Progress::for_core(core, None), &CancellationToken::new()))
+    })
+}
+
+/// Background variant of `install_neoforge`.
+///
+/// # Returns
+/// * Job id for `crystal_job_poll` (result: `null`)
+/// * negated `ErrorCode` on invalid arguments
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_start_install_neoforge(
+    core: *const CrystalCore,
+    neo_version_ptr: *const c_char,
+    game_dir_ptr: *const c_char,
+    java_path_ptr: *const c_char
+) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        let neo_version = str_arg(neo_version_ptr, "neo_version")?.to_string();
+        let game_dir = PathBuf::from(str_arg(game_dir_ptr, "game_dir")?);
+        let java_path = str_arg(java_path_ptr, "java_path")?.to_string();
+        let net = core.net.clone();
+        let retry = core.config().retry.clone();
+
+        Ok(core.spawn_job("install_neoforge", |progress, cancel| async move {
+            install(&net, &retry, &neo_version, &game_dir, &java_path, &progress, &cancel).await?;
+            Ok(serde_json::Value::Null)
+        }))
+    })
+}
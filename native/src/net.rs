@@ -0,0 +1,235 @@
+//! HTTP client shared by every transfer of a core, with the core's
+//! bandwidth and connection limits.
+//!
+//! All downloads and uploads draw from one token bucket, so a sync never
+//! takes more than `max_bytes_per_sec` whatever the number of parallel
+//! transfers; the limit can be changed while transfers run. Independently,
+//! at most `max_connections_per_host` requests are open against any single
+//! host (R2, Mojang, Maven...).
+
+use crate::cancel::{CancellationToken, run_cancellable};
+use crate::context::CrystalCore;
+use crate::error::CoreError;
+use crate::ffi::{core_arg, ffi_status};
+use crate::progress::{ItemProgress, Phase};
+use bytes::Bytes;
+use futures::{Stream, TryStreamExt};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc};
+
+/// Smallest burst the bucket allows, so low limits still move whole chunks.
+const MIN_BURST: f64 = 64.0 * 1024.0;
+
+/// Chunk size of throttled upload bodies.
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// `reqwest::Client` plus the limits it must respect. Cheap to clone.
+#[derive(Clone)]
+pub struct Net {
+    pub(crate) client: reqwest::Client,
+    pub(crate) limits: Arc<NetLimits>,
+}
+
+/// Token bucket and per-host connection caps.
+pub struct NetLimits {
+    /// Bytes per second; 0 means unlimited.
+    rate: AtomicU64,
+    bucket: Mutex<Bucket>,
+    per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+struct Bucket {
+    /// Available bytes; negative while transfers are in debt.
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl NetLimits {
+    pub fn new(max_bytes_per_sec: u64, max_connections_per_host: usize) -> Self {
+        Self {
+            rate: AtomicU64::new(max_bytes_per_sec),
+            bucket: Mutex::new(Bucket { tokens: 0.0, refilled: Instant::now() }),
+            per_host: max_connections_per_host.max(1),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn max_bytes_per_sec(&self) -> u64 {
+        self.rate.load(Ordering::Relaxed)
+    }
+
+    pub fn max_connections_per_host(&self) -> usize {
+        self.per_host
+    }
+
+    /// Change the bandwidth limit; running transfers pick it up on their next chunk.
+    pub fn set_max_bytes_per_sec(&self, max_bytes_per_sec: u64) {
+        self.rate.store(max_bytes_per_sec, Ordering::Relaxed);
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        bucket.tokens = bucket.tokens.min(burst(max_bytes_per_sec));
+    }
+
+    /// Account for `n` bytes moved, sleeping as long as needed to stay under
+    /// the limit. Bytes are taken first and paid back by waiting, so
+    /// concurrent transfers queue up fairly.
+    pub async fn consume(&self, n: u64, cancel: &CancellationToken) -> Result<(), CoreError> {
+        let rate = self.max_bytes_per_sec();
+        if rate == 0 {
+            return Ok(());
+        }
+
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * rate as f64).min(burst(rate));
+            bucket.refilled = now;
+            bucket.tokens -= n as f64;
+            if bucket.tokens < 0.0 { -bucket.tokens / rate as f64 } else { 0.0 }
+        };
+
+        if wait > 0.0 {
+            run_cancellable(cancel, async {
+                tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+                Ok(())
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Wait for a connection slot to the host of `url`. Hold the permit for
+    /// as long as the request (including its body) is in flight.
+    pub async fn connect(&self, url: &str, cancel: &CancellationToken) -> Result<OwnedSemaphorePermit, CoreError> {
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| format!("{}:{}", h, u.port_or_known_default().unwrap_or(0))))
+            .unwrap_or_else(|| url.to_string());
+
+        let semaphore = self
+            .hosts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.per_host)))
+            .clone();
+
+        run_cancellable(cancel, async {
+            semaphore
+                .acquire_owned()
+                .await
+                .map_err(|_| CoreError::Internal("semaphore closed".to_string()))
+        })
+        .await
+    }
+}
+
+fn burst(rate: u64) -> f64 {
+    (rate as f64).max(MIN_BURST)
+}
+
+/// Stream `path` in chunks, paying each one to `limits`. Used as the body of
+/// uploads so they share the bandwidth limit with downloads.
+pub fn throttled_file_stream(
+    path: PathBuf,
+    limits: Arc<NetLimits>,
+    cancel: CancellationToken,
+) -> impl Stream<Item = Result<Bytes, CoreError>> + Send + 'static {
+    futures::stream::try_unfold(None, move |file: Option<tokio::fs::File>| {
+        let (path, limits, cancel) = (path.clone(), limits.clone(), cancel.clone());
+        async move {
+            let mut file = match file {
+                Some(file) => file,
+                None => tokio::fs::File::open(&path).await.map_err(|e| CoreError::io(&path, e))?,
+            };
+            let mut buffer = vec![0; UPLOAD_CHUNK_SIZE];
+            let count = file.read(&mut buffer).await.map_err(|e| CoreError::io(&path, e))?;
+            if count == 0 {
+                return Ok(None);
+            }
+            buffer.truncate(count);
+            limits.consume(count as u64, &cancel).await?;
+            Ok(Some((Bytes::from(buffer), Some(file))))
+        }
+    })
+}
+
+/// Reported by a `reported_file_stream` body to `track_upload`.
+pub(crate) enum Sent {
+    /// A new body was built, i.e. the transport (re)started the upload.
+    Opened,
+    /// This many bytes were handed to the transport.
+    Bytes(u64),
+}
+
+/// `throttled_file_stream` that reports every chunk on `sent`, so upload
+/// progress follows the bytes actually sent rather than jumping at the end.
+pub(crate) fn reported_file_stream(
+    path: PathBuf,
+    limits: Arc<NetLimits>,
+    cancel: CancellationToken,
+    sent: mpsc::UnboundedSender<Sent>,
+) -> impl Stream<Item = Result<Bytes, CoreError>> + Send + 'static {
+    let _ = sent.send(Sent::Opened);
+    throttled_file_stream(path, limits, cancel).inspect_ok(move |chunk| {
+        let _ = sent.send(Sent::Bytes(chunk.len() as u64));
+    })
+}
+
+/// Drive `upload` to completion, counting the bytes its body reports on
+/// `sent` against `item`. A restarted body forgets the bytes of the
+/// previous attempt.
+pub(crate) async fn track_upload<T>(
+    item: &ItemProgress,
+    mut sent: mpsc::UnboundedReceiver<Sent>,
+    upload: impl Future<Output = T>,
+) -> T {
+    let mut counted = 0;
+    let mut count = |event: Sent| match event {
+        Sent::Opened if counted > 0 => {
+            counted = 0;
+            item.restart(Phase::Uploading);
+        }
+        Sent::Opened => {}
+        Sent::Bytes(n) => {
+            counted += n;
+            item.advance(n);
+        }
+    };
+
+    tokio::pin!(upload);
+    loop {
+        tokio::select! {
+            result = &mut upload => {
+                while let Ok(event) = sent.try_recv() {
+                    count(event);
+                }
+                return result;
+            }
+            Some(event) = sent.recv() => count(event),
+        }
+    }
+}
+
+/// Change the bandwidth limit shared by all transfers of `core`.
+///
+/// # Arguments
+/// * `max_bytes_per_sec` - New limit in bytes per second, 0 for unlimited
+///
+/// # Returns
+/// * 1 on success
+/// * negated `ErrorCode` on error
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_set_bandwidth_limit(core: *const CrystalCore, max_bytes_per_sec: u64) -> i32 {
+    ffi_status(1, || {
+        core_arg(core)?.net.limits.set_max_bytes_per_sec(max_bytes_per_sec);
+        Ok(())
+    })
+}
@@ -0,0 +1,582 @@
+//! Packwiz packs (`pack.toml` + `index.toml` + `*.pw.toml` metafiles).
+//!
+//! `pack.toml` points at `index.toml` (with its hash); the index lists every
+//! file of the pack relative to its own location, each with a hash. Plain
+//! entries are fetched from next to the index; `metafile` entries are small
+//! TOML files naming the real file (`filename`), where to download it and
+//! its hash, plus the side it is needed on.
+//!
+//! `packwiz_plan` resolves a pack from a URL or a local `pack.toml` and
+//! compares it with the instance: files missing or with a different hash are
+//! planned for download (through the parallel engine, verified with the
+//! pack's own hash format) or, for a local pack, for copying. Files only
+//! needed on the other side, and opted-out optional ones, are skipped.
+//! Nothing is deleted: packwiz has no notion of files it used to own.
+
+use crate::cancel::CancellationToken;
+use crate::context::CrystalCore;
+use crate::download::part_path;
+use crate::error::CoreError;
+use crate::ffi::{core_arg, ffi_status, ffi_string, join_error, json_arg, options_arg, str_arg, to_json};
+use crate::hash_cache::HashCache;
+use crate::hashing::{ExpectedHash, HashAlgorithm, hash_files};
+use crate::jobs::ffi_job;
+use crate::manifest::Side;
+use crate::net::Net;
+use crate::progress::{Phase, Progress};
+use crate::r2_sync::{DownloadRequest, ModInfo, download_batch};
+use crate::retry::RetryPolicy;
+use crate::safe_path::{safe_file_name, safe_join, safe_relative_path};
+use futures::StreamExt;
+use std::collections::BTreeMap;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct PackToml {
+    name: String,
+    #[serde(default)]
+    version: String,
+    index: IndexRef,
+    #[serde(default)]
+    versions: BTreeMap<String, String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct IndexRef {
+    file: String,
+    hash_format: String,
+    hash: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct IndexToml {
+    hash_format: String,
+    #[serde(default)]
+    files: Vec<IndexEntry>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct IndexEntry {
+    file: String,
+    hash: String,
+    /// Overrides the index-wide format.
+    #[serde(default)]
+    hash_format: Option<String>,
+    /// Destination path, if not `file`.
+    #[serde(default)]
+    alias: Option<String>,
+    #[serde(default)]
+    metafile: bool,
+    /// Only written if missing, so local edits survive updates (configs).
+    #[serde(default)]
+    preserve: bool,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct MetaToml {
+    filename: String,
+    #[serde(default)]
+    side: Side,
+    download: MetaDownload,
+    #[serde(default)]
+    option: MetaOption,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct MetaDownload {
+    /// Absent for `mode = "metadata:curseforge"`, which needs the CurseForge API.
+    #[serde(default)]
+    url: Option<String>,
+    hash_format: String,
+    hash: String,
+    #[serde(default)]
+    mode: Option<String>,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct MetaOption {
+    #[serde(default)]
+    optional: bool,
+}
+
+/// Map a packwiz `hash-format` to ours (`md5` is not supported).
+fn hash_format(format: &str, hash: &str) -> Result<ExpectedHash, CoreError> {
+    let algorithm = match format {
+        "sha1" => HashAlgorithm::Sha1,
+        "sha256" => HashAlgorithm::Sha256,
+        "sha512" => HashAlgorithm::Sha512,
+        "murmur2" => HashAlgorithm::Murmur2,
+        other => return Err(CoreError::invalid("pack", format!("unsupported hash-format {:?}", other))),
+    };
+    Ok(ExpectedHash { algorithm, value: hash.trim().to_ascii_lowercase() })
+}
+
+fn parse_toml<T: serde::de::DeserializeOwned>(bytes: &[u8], what: &str) -> Result<T, CoreError> {
+    let text = std::str::from_utf8(bytes).map_err(|e| CoreError::invalid("pack", format!("{}: {}", what, e)))?;
+    toml::from_str(text).map_err(|e| CoreError::invalid("pack", format!("{}: {}", what, e)))
+}
+
+/// Where pack files come from: a URL or a local folder.
+#[derive(Clone, Debug)]
+enum Location {
+    Url(reqwest::Url),
+    Path(PathBuf),
+}
+
+impl Location {
+    fn parse(source: &str) -> Result<Self, CoreError> {
+        if source.starts_with("http://") || source.starts_with("https://") {
+            let url = reqwest::Url::parse(source).map_err(|e| CoreError::invalid("source", e))?;
+            return Ok(Location::Url(url));
+        }
+        Ok(Location::Path(PathBuf::from(source)))
+    }
+
+    /// `relative` next to this file.
+    fn sibling(&self, relative: &str) -> Result<Self, CoreError> {
+        let relative = safe_relative_path(relative)?;
+        Ok(match self {
+            Location::Url(url) => {
+                // Segments are percent-encoded one by one: file names may hold
+                // `#`, `?` or spaces, which `Url::join` would misread
+                let mut url = url.join(".").map_err(|e| CoreError::invalid("pack", e))?;
+                url.path_segments_mut()
+                    .map_err(|_| CoreError::invalid("pack", "source URL cannot have relative files"))?
+                    .pop_if_empty()
+                    .extend(relative.components().map(|c| c.as_os_str().to_string_lossy()));
+                Location::Url(url)
+            }
+            Location::Path(path) => Location::Path(path.parent().unwrap_or(Path::new("")).join(relative)),
+        })
+    }
+
+    async fn read(&self, net: &Net, retry: &RetryPolicy, cancel: &CancellationToken) -> Result<Vec<u8>, CoreError> {
+        match self {
+            Location::Path(path) => tokio::fs::read(path).await.map_err(|e| CoreError::io(path, e)),
+            Location::Url(url) => {
+                let url = url.as_str();
+                let (result, _) = retry
+                    .run(cancel, url, |_| async move {
+                        let _connection = net.limits.connect(url, cancel).await?;
+                        let resp = net.client.get(url).send().await.map_err(|e| CoreError::network(url, e))?;
+                        if !resp.status().is_success() {
+                            return Err(CoreError::http_status(url, &resp));
+                        }
+                        let bytes = resp.bytes().await.map_err(|e| CoreError::network(url, e))?;
+                        Ok(bytes.to_vec())
+                    })
+                    .await;
+                result
+            }
+        }
+    }
+
+    /// Read and check against `expected`.
+    async fn read_verified(
+        &self,
+        expected: &ExpectedHash,
+        net: &Net,
+        retry: &RetryPolicy,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<u8>, CoreError> {
+        let bytes = self.read(net, retry, cancel).await?;
+        expected.verify_bytes(&bytes, &self.to_string())?;
+        Ok(bytes)
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Location::Url(url) => write!(f, "{}", url),
+            Location::Path(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Options of `packwiz_plan`.
+#[derive(Debug, serde::Deserialize)]
+#[serde(default)]
+pub struct PackwizOptions {
+    /// `client` (default), `server`, or `both` for every file.
+    pub side: Side,
+    /// Include files marked optional.
+    pub include_optional: bool,
+}
+
+impl Default for PackwizOptions {
+    fn default() -> Self {
+        Self { side: Side::Client, include_optional: true }
+    }
+}
+
+/// A file of a local pack to copy into the instance.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub(crate) struct LocalFile {
+    pub(crate) path: String,
+    pub(crate) source: String,
+    pub(crate) hash: ExpectedHash,
+}
+
+/// A file the pack lists but that cannot be fetched (e.g. CurseForge-only
+/// metadata without a URL).
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct PackwizUnresolved {
+    pub path: String,
+    pub reason: String,
+}
+
+/// What `packwiz_apply` has to do to bring the instance in line with a pack.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct PackwizPlan {
+    pub instance_dir: String,
+    pub name: String,
+    pub version: String,
+    /// `[versions]` of `pack.toml` (`minecraft`, `neoforge`...).
+    pub versions: BTreeMap<String, String>,
+    /// Files missing or outdated, to download.
+    pub(crate) download: Vec<ModInfo>,
+    /// Files missing or outdated, to copy from a local pack.
+    pub(crate) copy: Vec<LocalFile>,
+    pub up_to_date: Vec<String>,
+    /// Other side only, or optional and not wanted.
+    pub skipped: Vec<String>,
+    pub unresolved: Vec<PackwizUnresolved>,
+}
+
+/// One file of the pack, resolved.
+struct Target {
+    path: String,
+    hash: ExpectedHash,
+    origin: Location,
+    preserve: bool,
+}
+
+/// Resolve the pack at `source` and compare it with `instance_dir`.
+#[allow(clippy::too_many_arguments)]
+async fn plan(
+    net: Net,
+    retry: RetryPolicy,
+    cache: Arc<HashCache>,
+    source: String,
+    instance_dir: PathBuf,
+    options: PackwizOptions,
+    progress: Progress,
+    cancel: CancellationToken,
+) -> Result<PackwizPlan, CoreError> {
+    // 1. pack.toml, then index.toml (checked against the hash in pack.toml)
+    let pack_location = Location::parse(&source)?;
+    let pack: PackToml = parse_toml(&pack_location.read(&net, &retry, &cancel).await?, "pack.toml")?;
+    let index_location = pack_location.sibling(&pack.index.file)?;
+    let index_hash = hash_format(&pack.index.hash_format, &pack.index.hash)?;
+    let index: IndexToml =
+        parse_toml(&index_location.read_verified(&index_hash, &net, &retry, &cancel).await?, "index.toml")?;
+
+    // 2. Metafiles, fetched in parallel
+    let mut entries = Vec::with_capacity(index.files.len());
+    for entry in index.files {
+        safe_relative_path(&entry.file)?;
+        let hash = hash_format(entry.hash_format.as_deref().unwrap_or(&index.hash_format), &entry.hash)?;
+        let location = index_location.sibling(&entry.file)?;
+        entries.push((entry, hash, location));
+    }
+    let parallelism = net.limits.max_connections_per_host();
+    let fetches: Vec<_> = entries
+        .iter()
+        .map(|(entry, hash, location)| entry.metafile.then(|| (hash.clone(), location.clone())))
+        .collect();
+    let metas: Vec<Option<Result<Vec<u8>, CoreError>>> = futures::stream::iter(fetches)
+        .map(|fetch| {
+            let (net, retry, cancel) = (net.clone(), retry.clone(), cancel.clone());
+            async move {
+                let (hash, location) = fetch?;
+                Some(location.read_verified(&hash, &net, &retry, &cancel).await)
+            }
+        })
+        .buffered(parallelism)
+        .collect()
+        .await;
+
+    // 3. Targets
+    let mut targets = Vec::new();
+    let mut skipped = Vec::new();
+    let mut unresolved = Vec::new();
+    for ((entry, hash, location), meta) in entries.into_iter().zip(metas) {
+        let Some(meta) = meta else {
+            let path = entry.alias.clone().unwrap_or(entry.file);
+            safe_relative_path(&path)?;
+            targets.push(Target { path, hash, origin: location, preserve: entry.preserve });
+            continue;
+        };
+        let meta: MetaToml = parse_toml(&meta?, &entry.file)?;
+        let dir = entry.file.rsplit_once('/').map_or("", |(dir, _)| dir);
+        let path = match (entry.alias, dir) {
+            (Some(alias), _) => {
+                safe_relative_path(&alias)?;
+                alias
+            }
+            (None, "") => safe_file_name(&meta.filename)?.to_string(),
+            (None, dir) => format!("{}/{}", dir, safe_file_name(&meta.filename)?),
+        };
+        let wrong_side = options.side != Side::Both && meta.side != Side::Both && meta.side != options.side;
+        if wrong_side || (meta.option.optional && !options.include_optional) {
+            skipped.push(path);
+            continue;
+        }
+        let hash = hash_format(&meta.download.hash_format, &meta.download.hash)?;
+        let origin = match meta.download.url.as_deref().map(reqwest::Url::parse) {
+            Some(Ok(url)) => Location::Url(url),
+            Some(Err(e)) => return Err(CoreError::invalid("pack", format!("{}: {}", entry.file, e))),
+            None => {
+                let mode = meta.download.mode.unwrap_or_default();
+                unresolved.push(PackwizUnresolved { path, reason: format!("no download URL (mode {:?})", mode) });
+                continue;
+            }
+        };
+        targets.push(Target { path, hash, origin, preserve: entry.preserve });
+    }
+
+    // 4. Compare with the instance, hashing with each file's own format
+    let mut current = vec![false; targets.len()];
+    for algorithm in [HashAlgorithm::Sha1, HashAlgorithm::Sha256, HashAlgorithm::Sha512, HashAlgorithm::Murmur2] {
+        let (indices, paths): (Vec<_>, Vec<_>) = targets
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.hash.algorithm == algorithm)
+            .map(|(i, t)| (i, instance_dir.join(&t.path)))
+            .filter(|(_, p)| p.is_file())
+            .map(|(i, p)| (i, p.display().to_string()))
+            .unzip();
+        if paths.is_empty() {
+            continue;
+        }
+        let hashes = hash_files(paths, vec![algorithm], cache.clone(), progress.clone(), cancel.clone()).await?;
+        for (i, (_, result)) in indices.into_iter().zip(hashes) {
+            let target = &targets[i];
+            current[i] = target.preserve || result.is_ok_and(|h| target.hash.matches(&h));
+        }
+    }
+
+    let mut plan = PackwizPlan {
+        instance_dir: instance_dir.display().to_string(),
+        name: pack.name,
+        version: pack.version,
+        versions: pack.versions,
+        download: Vec::new(),
+        copy: Vec::new(),
+        up_to_date: Vec::new(),
+        skipped,
+        unresolved,
+    };
+    for (target, current) in targets.into_iter().zip(current) {
+        if current {
+            plan.up_to_date.push(target.path);
+            continue;
+        }
+        match target.origin {
+            Location::Url(url) => {
+                let sha1 = match target.hash.algorithm {
+                    HashAlgorithm::Sha1 => target.hash.value.clone(),
+                    _ => String::new(),
+                };
+                plan.download.push(ModInfo { name: target.path, url: url.into(), sha1, hash: Some(target.hash) });
+            }
+            Location::Path(source) => plan.copy.push(LocalFile {
+                path: target.path,
+                source: source.display().to_string(),
+                hash: target.hash,
+            }),
+        }
+    }
+
+    println!(
+        "[Rust] Packwiz plan for '{}' {}: {} to download, {} to copy, {} up to date, {} skipped, {} unresolved",
+        plan.name, plan.version, plan.download.len(), plan.copy.len(), plan.up_to_date.len(),
+        plan.skipped.len(), plan.unresolved.len()
+    );
+    Ok(plan)
+}
+
+/// A validated plan plus what `apply` needs from the core.
+struct ApplyRequest {
+    plan: PackwizPlan,
+    net: Net,
+    downloads: DownloadRequest,
+}
+
+impl ApplyRequest {
+    fn new(core: &CrystalCore, plan: PackwizPlan) -> Result<Self, CoreError> {
+        // The plan comes back from the host: check it again.
+        for file in &plan.copy {
+            safe_relative_path(&file.path)?;
+        }
+        let downloads = DownloadRequest::new(
+            core,
+            plan.download.clone(),
+            plan.instance_dir.clone(),
+            core.config().max_concurrent_downloads,
+        )?;
+        Ok(Self { plan, net: core.net.clone(), downloads })
+    }
+}
+
+/// Result of `packwiz_apply`.
+#[derive(Debug, serde::Serialize)]
+pub struct PackwizReport {
+    pub downloaded: Vec<String>,
+    pub copied: Vec<String>,
+}
+
+/// Copy local files (verified before they replace anything), then download
+/// the rest. Every download is attempted; failures end in `Incomplete`.
+async fn apply(request: ApplyRequest, progress: Progress, cancel: CancellationToken) -> Result<PackwizReport, CoreError> {
+    let ApplyRequest { plan, net, downloads } = request;
+    let instance_dir = PathBuf::from(&plan.instance_dir);
+
+    // 1. Copies
+    let copied: Vec<String> = plan.copy.iter().map(|f| f.path.clone()).collect();
+    let (copy, dir, copy_progress, copy_cancel) = (plan.copy, instance_dir.clone(), progress.clone(), cancel.clone());
+    tokio::task::spawn_blocking(move || copy_files(&copy, &dir, &copy_progress, &copy_cancel))
+        .await
+        .map_err(join_error)??;
+
+    // 2. Downloads
+    let downloaded = plan.download.iter().map(|m| m.name.clone()).collect();
+    if !plan.download.is_empty() {
+        tokio::fs::create_dir_all(&instance_dir).await.map_err(|e| CoreError::io(&instance_dir, e))?;
+        download_batch(net, downloads, progress, cancel, |_| {}).await?.into_result()?;
+    }
+    Ok(PackwizReport { downloaded, copied })
+}
+
+fn copy_files(files: &[LocalFile], instance_dir: &Path, progress: &Progress, cancel: &CancellationToken) -> Result<(), CoreError> {
+    for file in files {
+        let dest = safe_join(instance_dir, &file.path)?;
+        let item = progress.start_item(file.path.as_str(), Phase::Writing, None);
+        let result = (|| {
+            if let Some(parent) = dest.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| CoreError::io(parent, e))?;
+            }
+            let part = part_path(&dest);
+            std::fs::copy(&file.source, &part).map_err(|e| CoreError::io(&file.source, e))?;
+            item.set_phase(Phase::Verifying);
+            if let Err(e) = file.hash.verify_file(&part, &item, cancel) {
+                let _ = std::fs::remove_file(&part);
+                return Err(e);
+            }
+            std::fs::rename(&part, &dest).map_err(|e| CoreError::io(&dest, e))
+        })();
+        item.finish(result)?;
+    }
+    Ok(())
+}
+
+/// Resolve a packwiz pack and compare it with an instance.
+///
+/// # Arguments
+/// * `source` - URL or local path of `pack.toml`
+/// * `instance_dir` - Instance (game) directory
+/// * `options_json` - `{"side": "client", "include_optional": true}`, or null for defaults
+///
+/// # Returns
+/// * JSON plan to pass to `packwiz_apply` (free with `free_string`):
+///   `{"instance_dir", "name", "version", "versions": {"minecraft": "1.21.1"},
+///     "download": [...], "copy": [...], "up_to_date": [...], "skipped": [...],
+///     "unresolved": [{"path", "reason"}]}`
+/// * null on error (see `crystal_last_error`)
+#[unsafe(no_mangle)]
+pub extern "C" fn packwiz_plan(
+    core: *const CrystalCore,
+    source: *const c_char,
+    instance_dir: *const c_char,
+    options_json: *const c_char,
+) -> *mut c_char {
+    ffi_string(|| {
+        let core = core_arg(core)?;
+        let source = str_arg(source, "source")?.to_string();
+        let instance_dir = PathBuf::from(str_arg(instance_dir, "instance_dir")?);
+        let options = options_arg(options_json)?;
+
+        let plan = core.runtime.block_on(plan(
+            core.net.clone(),
+            core.config().retry.clone(),
+            core.hash_cache.clone(),
+            source,
+            instance_dir,
+            options,
+            Progress::for_core(core, None),
+            CancellationToken::new(),
+        ))?;
+        Ok(to_json(&plan)?.to_string())
+    })
+}
+
+/// Background variant of `packwiz_plan`.
+///
+/// # Returns
+/// * Job id for `crystal_job_poll` (result: the plan)
+/// * negated `ErrorCode` on invalid arguments
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_start_packwiz_plan(
+    core: *const CrystalCore,
+    source: *const c_char,
+    instance_dir: *const c_char,
+    options_json: *const c_char,
+) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        let source = str_arg(source, "source")?.to_string();
+        let instance_dir = PathBuf::from(str_arg(instance_dir, "instance_dir")?);
+        let options = options_arg(options_json)?;
+        let (net, retry, cache) = (core.net.clone(), core.config().retry.clone(), core.hash_cache.clone());
+
+        Ok(core.spawn_job("packwiz_plan", |progress, cancel| async move {
+            to_json(&plan(net, retry, cache, source, instance_dir, options, progress, cancel).await?)
+        }))
+    })
+}
+
+/// Execute a plan returned by `packwiz_plan`: copy and download the listed
+/// files into the instance, each verified with the pack's hash format
+/// before it replaces the old one.
+///
+/// # Returns
+/// * 1 on success
+/// * negated `ErrorCode` on error (see `crystal_last_error`); `-80` (`INCOMPLETE`)
+///   when some downloads failed
+#[unsafe(no_mangle)]
+pub extern "C" fn packwiz_apply(core: *const CrystalCore, plan_json: *const c_char) -> i32 {
+    ffi_status(1, || {
+        let core = core_arg(core)?;
+        let request = ApplyRequest::new(core, json_arg(plan_json, "plan_json")?)?;
+
+        core.runtime
+            .block_on(apply(request, Progress::for_core(core, None), CancellationToken::new()))
+            .map(|_| ())
+    })
+}
+
+/// Background variant of `packwiz_apply`.
+///
+/// # Returns
+/// * Job id for `crystal_job_poll` (result: `{"downloaded": [...], "copied": [...]}`)
+/// * negated `ErrorCode` on invalid arguments
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_start_packwiz_apply(core: *const CrystalCore, plan_json: *const c_char) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        let request = ApplyRequest::new(core, json_arg(plan_json, "plan_json")?)?;
+
+        Ok(core.spawn_job("packwiz_apply", |progress, cancel| async move {
+            to_json(&apply(request, progress, cancel).await?)
+        }))
+    })
+}
@@ -0,0 +1,353 @@
+//! Progress reporting for long-running operations.
+//!
+//! Every operation receives a `Progress`. It keeps the aggregate counters that
+//! `crystal_job_poll` reports and, when attached to a core, pushes JSON events
+//! (per-file bytes, phase, throughput, ETA, per-file failures) onto the core's
+//! `EventQueue`, which the host drains from a timer with `crystal_events_poll`.
+//! Nothing crosses isolates: the host pulls, Rust never calls back.
+
+use crate::context::CrystalCore;
+use crate::error::{CoreError, ErrorReport};
+use crate::ffi::{core_arg, ffi_string};
+use std::collections::VecDeque;
+use std::os::raw::c_char;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Events kept before the oldest `progress` events start being dropped.
+const EVENT_QUEUE_CAPACITY: usize = 4096;
+
+/// Minimum delay between two byte-level events for the same item.
+const ITEM_EVENT_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Hashing,
+    Downloading,
+    Verifying,
+    Writing,
+    Uploading,
+    Extracting,
+    Installing,
+}
+
+/// One entry of the array returned by `crystal_events_poll`.
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Progress {
+        job_id: Option<u64>,
+        phase: Phase,
+        item: String,
+        item_bytes_done: u64,
+        item_bytes_total: Option<u64>,
+        bytes_done: u64,
+        bytes_total: u64,
+        items_done: u64,
+        items_total: u64,
+        throughput_bps: f64,
+        eta_secs: Option<f64>,
+    },
+    ItemDone {
+        job_id: Option<u64>,
+        item: String,
+    },
+    ItemFailed {
+        job_id: Option<u64>,
+        item: String,
+        error: ErrorReport,
+    },
+    JobFinished {
+        job_id: u64,
+        state: crate::jobs::JobState,
+    },
+    GameState {
+        game_id: u64,
+        state: crate::game_process::GameState,
+        exit_code: Option<i32>,
+    },
+}
+
+#[derive(serde::Serialize)]
+struct QueuedEvent {
+    seq: u64,
+    #[serde(flatten)]
+    event: Event,
+}
+
+/// Bounded FIFO of events owned by a `CrystalCore`.
+#[derive(Default)]
+pub struct EventQueue {
+    next_seq: AtomicU64,
+    events: Mutex<VecDeque<QueuedEvent>>,
+}
+
+impl EventQueue {
+    pub fn push(&self, event: Event) {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        if events.len() >= EVENT_QUEUE_CAPACITY {
+            // Byte-level updates are superseded by the next one; drop those first.
+            match events.iter().position(|e| matches!(e.event, Event::Progress { .. })) {
+                Some(i) => { events.remove(i); }
+                None => { events.pop_front(); }
+            }
+        }
+        events.push_back(QueuedEvent { seq, event });
+    }
+
+    fn drain(&self, max: usize) -> Vec<QueuedEvent> {
+        let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        let n = max.min(events.len());
+        events.drain(..n).collect()
+    }
+}
+
+/// Progress counters shared between a running operation and its pollers.
+///
+/// Callers that don't care (tools, tests) can pass `Progress::default()`,
+/// which only keeps counters.
+#[derive(Clone, Default)]
+pub struct Progress(Arc<ProgressInner>);
+
+#[derive(Default)]
+struct ProgressInner {
+    bytes_done: AtomicU64,
+    bytes_total: AtomicU64,
+    items_done: AtomicU64,
+    items_total: AtomicU64,
+    current_item: Mutex<Option<String>>,
+    started: Mutex<Option<Instant>>,
+    sink: Option<(Arc<EventQueue>, Option<u64>)>,
+}
+
+impl Progress {
+    /// Progress that publishes events on `core`'s queue, tagged with `job_id`.
+    pub fn for_core(core: &CrystalCore, job_id: Option<u64>) -> Self {
+        Self::with_sink(core.events.clone(), job_id)
+    }
+
+    pub(crate) fn with_sink(events: Arc<EventQueue>, job_id: Option<u64>) -> Self {
+        Progress(Arc::new(ProgressInner { sink: Some((events, job_id)), ..Default::default() }))
+    }
+
+    pub fn add_bytes_total(&self, n: u64) {
+        self.0.bytes_total.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn add_bytes(&self, n: u64) {
+        self.start_clock();
+        self.0.bytes_done.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn set_items_total(&self, n: u64) {
+        self.0.items_total.store(n, Ordering::Relaxed);
+    }
+
+    pub fn item_done(&self) {
+        self.0.items_done.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_item(&self, item: impl Into<String>) {
+        *self.0.current_item.lock().unwrap_or_else(|e| e.into_inner()) = Some(item.into());
+    }
+
+    /// Start tracking one file / entry. `total` is its size if known; unlike
+    /// `ItemProgress::set_total` it is not added to the aggregate, for callers
+    /// that sized the whole operation up front.
+    pub fn start_item(&self, name: impl Into<String>, phase: Phase, total: Option<u64>) -> ItemProgress {
+        let name = name.into();
+        self.set_item(name.as_str());
+        self.start_clock();
+        let item = ItemProgress {
+            progress: self.clone(),
+            name,
+            phase: Mutex::new(phase),
+            done: AtomicU64::new(0),
+            total: Mutex::new(total),
+            last_event: Mutex::new(None),
+        };
+        item.emit(true);
+        item
+    }
+
+    fn start_clock(&self) {
+        self.0.started.lock().unwrap_or_else(|e| e.into_inner()).get_or_insert_with(Instant::now);
+    }
+
+    fn job_id(&self) -> Option<u64> {
+        self.0.sink.as_ref().and_then(|(_, id)| *id)
+    }
+
+    fn publish(&self, event: Event) {
+        if let Some((events, _)) = &self.0.sink {
+            events.push(event);
+        }
+    }
+
+    /// Aggregate bytes per second since the first byte, and the matching ETA.
+    fn rate(&self) -> (f64, Option<f64>) {
+        let elapsed = self.0.started
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .map(|t| t.elapsed().as_secs_f64())
+            .unwrap_or(0.0);
+        let done = self.0.bytes_done.load(Ordering::Relaxed);
+        let total = self.0.bytes_total.load(Ordering::Relaxed);
+        if elapsed <= 0.0 || done == 0 {
+            return (0.0, None);
+        }
+        let throughput = done as f64 / elapsed;
+        let eta = (total > done).then(|| (total - done) as f64 / throughput);
+        (throughput, eta)
+    }
+
+    pub(crate) fn snapshot(&self) -> ProgressSnapshot {
+        let (throughput_bps, eta_secs) = self.rate();
+        ProgressSnapshot {
+            bytes_done: self.0.bytes_done.load(Ordering::Relaxed),
+            bytes_total: self.0.bytes_total.load(Ordering::Relaxed),
+            items_done: self.0.items_done.load(Ordering::Relaxed),
+            items_total: self.0.items_total.load(Ordering::Relaxed),
+            current_item: self.0.current_item.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+            throughput_bps,
+            eta_secs,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub(crate) struct ProgressSnapshot {
+    bytes_done: u64,
+    bytes_total: u64,
+    items_done: u64,
+    items_total: u64,
+    current_item: Option<String>,
+    throughput_bps: f64,
+    eta_secs: Option<f64>,
+}
+
+/// Progress of a single file inside an operation, created by `Progress::start_item`.
+pub struct ItemProgress {
+    progress: Progress,
+    name: String,
+    phase: Mutex<Phase>,
+    done: AtomicU64,
+    total: Mutex<Option<u64>>,
+    last_event: Mutex<Option<Instant>>,
+}
+
+impl ItemProgress {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_phase(&self, phase: Phase) {
+        *self.phase.lock().unwrap_or_else(|e| e.into_inner()) = phase;
+        self.emit(true);
+    }
+
+    /// Size learned after the item started (e.g. from `Content-Length`).
+    /// Also grows the aggregate total.
+    pub fn set_total(&self, total: u64) {
+        let mut slot = self.total.lock().unwrap_or_else(|e| e.into_inner());
+        if slot.is_none() {
+            self.progress.add_bytes_total(total);
+        }
+        *slot = Some(total);
+    }
+
+    /// Forget the bytes counted so far before another attempt at this item.
+    pub fn restart(&self, phase: Phase) {
+        let done = self.done.swap(0, Ordering::Relaxed);
+        self.progress.0.bytes_done.fetch_sub(done, Ordering::Relaxed);
+        self.set_phase(phase);
+    }
+
+    /// Count `n` more bytes for this item and for the aggregate.
+    pub fn advance(&self, n: u64) {
+        self.done.fetch_add(n, Ordering::Relaxed);
+        self.progress.add_bytes(n);
+        self.emit(false);
+    }
+
+    /// Close the item according to `result`: `item_done` on success,
+    /// `item_failed` on error (cancellation is not reported as a failure).
+    pub fn finish<T>(self, result: Result<T, CoreError>) -> Result<T, CoreError> {
+        match &result {
+            Ok(_) => self.done(),
+            Err(CoreError::Cancelled) => {}
+            Err(e) => self.failed(e),
+        }
+        result
+    }
+
+    pub fn done(self) {
+        self.progress.item_done();
+        self.progress.publish(Event::ItemDone { job_id: self.progress.job_id(), item: self.name });
+    }
+
+    pub fn failed(self, error: &CoreError) {
+        self.progress.publish(Event::ItemFailed {
+            job_id: self.progress.job_id(),
+            item: self.name,
+            error: ErrorReport::from(error),
+        });
+    }
+
+    fn emit(&self, force: bool) {
+        if self.progress.0.sink.is_none() {
+            return;
+        }
+        {
+            let mut last = self.last_event.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            if !force && last.is_some_and(|t| now.duration_since(t) < ITEM_EVENT_INTERVAL) {
+                return;
+            }
+            *last = Some(now);
+        }
+
+        let snapshot = self.progress.snapshot();
+        self.progress.publish(Event::Progress {
+            job_id: self.progress.job_id(),
+            phase: *self.phase.lock().unwrap_or_else(|e| e.into_inner()),
+            item: self.name.clone(),
+            item_bytes_done: self.done.load(Ordering::Relaxed),
+            item_bytes_total: *self.total.lock().unwrap_or_else(|e| e.into_inner()),
+            bytes_done: snapshot.bytes_done,
+            bytes_total: snapshot.bytes_total,
+            items_done: snapshot.items_done,
+            items_total: snapshot.items_total,
+            throughput_bps: snapshot.throughput_bps,
+            eta_secs: snapshot.eta_secs,
+        });
+    }
+}
+
+/// Drain up to `max_events` queued events (oldest first) as a JSON array.
+///
+/// Each event carries a `seq` number and a `type`:
+/// * `progress` - `phase` (`hashing`, `downloading`, `verifying`, `writing`,
+///   `uploading`, `extracting`, `installing`), `item`, `item_bytes_done`,
+///   `item_bytes_total`, aggregate `bytes_done`/`bytes_total`/`items_done`/
+///   `items_total`, `throughput_bps` and `eta_secs`
+/// * `item_done` / `item_failed` (with an `error` shaped like `crystal_last_error`)
+/// * `job_finished` - final `state` of a job
+/// * `game_state` - `game_id`, new `state` and `exit_code` of a game started with `launch_game`
+///
+/// Events from blocking exports have `job_id: null`. Byte-level events are
+/// throttled per item, and dropped first if the host stops draining.
+///
+/// # Returns
+/// * JSON array (free with `free_string`), empty when nothing is queued
+/// * null on error
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_events_poll(core: *const CrystalCore, max_events: i32) -> *mut c_char {
+    ffi_string(|| {
+        let events = core_arg(core)?.events.drain(max_events.max(0) as usize);
+        serde_json::to_string(&events).map_err(|e| CoreError::Internal(e.to_string()))
+    })
+}
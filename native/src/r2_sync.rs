@@ -1,33 +1,54 @@
 use aws_sdk_s3::{Client, primitives::ByteStream};
 use aws_config::{BehaviorVersion, Region};
+use aws_config::retry::RetryConfig;
 use aws_credential_types::Credentials;
-use tokio::runtime::Runtime;
-use std::ffi::{CStr, CString};
+use aws_smithy_types::body::SdkBody;
 use std::os::raw::c_char;
 use std::sync::Arc;
-use tokio::sync::Semaphore;
-use std::path::Path;
+use tokio::sync::{Semaphore, mpsc};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::cancel::{CancellationToken, check, run_cancellable};
+use crate::context::CrystalCore;
+use crate::download::{download_file, download_file_with_hash};
+use crate::error::{CoreError, ErrorReport};
+use crate::ffi::{core_arg, ffi_status, guard_task, join_error, json_arg, str_arg};
+use crate::hashing::ExpectedHash;
+use crate::jobs::ffi_job;
+use crate::manifest::verified_manifest_arg;
+use crate::net::{Net, NetLimits, reported_file_stream, track_upload};
+use crate::progress::{ItemProgress, Phase, Progress};
+use crate::retry::RetryPolicy;
+use crate::safe_path::{safe_join, safe_relative_path};
+use crate::store::ObjectStore;
 
 // Callback type for progress updates
-// Simplified to just an index to avoid Isolate issues in Dart FFI
+// Simplified to just an index to avoid Isolate issues in Dart FFI.
+// Byte-level progress, phases and per-file failures are published on the
+// core event queue instead (see `crystal_events_poll`).
 type R2SyncCallback = extern "C" fn(i32);
 
 /// Upload multiple files to R2 in parallel
-/// 
+///
 /// # Arguments
+/// * `core` - Handle from `crystal_core_new`
 /// * `files_json` - JSON array of file paths: ["path1.jar", "path2.jar", ...]
 /// * `access_key` - R2 Access Key ID
 /// * `secret_key` - R2 Secret Access Key
 /// * `endpoint` - R2 endpoint (e.g., "xxx.r2.cloudflarestorage.com")
 /// * `bucket` - Bucket name
 /// * `max_concurrent` - Maximum concurrent uploads (recommended: 10)
-/// * `callback` - Progress callback function
-/// 
+/// * `callback` - Called with the index of each file that succeeded
+///
+/// Every file is attempted even if some fail.
+///
 /// # Returns
 /// * 0 on success
-/// * -1 on error
+/// * negated `ErrorCode` on error (see `crystal_last_error`); `-80` (`INCOMPLETE`)
+///   when some files failed, listed in the message
 #[unsafe(no_mangle)]
 pub extern "C" fn upload_mods_parallel(
+    core: *const CrystalCore,
     files_json: *const c_char,
     access_key: *const c_char,
     secret_key: *const c_char,
@@ -36,170 +57,493 @@ pub extern "C" fn upload_mods_parallel(
     max_concurrent: i32,
     callback: R2SyncCallback,
 ) -> i32 {
-    let rt = match Runtime::new() {
-        Ok(r) => r,
-        Err(_) => return -1,
-    };
-
-    rt.block_on(async {
-        // Parse inputs
-        let files = match parse_files_json(files_json) {
-            Ok(f) => f,
-            Err(_) => return -1,
-        };
-
-        let access_key_str = unsafe { CStr::from_ptr(access_key).to_str().unwrap() };
-        let secret_key_str = unsafe { CStr::from_ptr(secret_key).to_str().unwrap() };
-        let endpoint_str = unsafe { CStr::from_ptr(endpoint).to_str().unwrap() };
-        let bucket_str = unsafe { CStr::from_ptr(bucket).to_str().unwrap() };
-
-        // Build S3 client
-        let client = match build_s3_client(endpoint_str, access_key_str, secret_key_str).await {
-            Ok(c) => c,
-            Err(_) => return -1,
-        };
-
-        // Semaphore for concurrency control
-        let semaphore = Arc::new(Semaphore::new(max_concurrent as usize));
-        let total = files.len();
-
-        // Spawn parallel upload tasks
-        let tasks: Vec<_> = files
-            .into_iter()
-            .enumerate()
-            .map(|(idx, path)| {
-                let client = client.clone();
-                let bucket = bucket_str.to_string();
-                let sem = semaphore.clone();
-
-                tokio::spawn(async move {
-                    let _permit = sem.acquire().await.unwrap();
-
-                    // Upload file
-                    upload_single_file(&client, &bucket, &path).await?;
-
-                    // Progress callback
-                    callback(idx as i32);
-
-                    Ok::<_, anyhow::Error>(())
-                })
-            })
-            .collect();
-
-        // Wait for all uploads
-        for task in tasks {
-            if let Err(_) = task.await.unwrap() {
-                return -1;
-            }
-        }
+    ffi_status(0, || {
+        let core = core_arg(core)?;
+        let request = UploadRequest::from_args(core, files_json, access_key, secret_key, endpoint, bucket, max_concurrent)?;
+
+        core.runtime.block_on(upload_batch(
+            request,
+            Progress::for_core(core, None),
+            CancellationToken::new(),
+            move |idx| callback(idx as i32),
+        ))?
+        .into_result()
+    })
+}
 
-        0
+/// Background variant of `upload_mods_parallel`.
+///
+/// # Returns
+/// * Job id for `crystal_job_poll`. The job completes once every file was
+///   attempted; its result lists them:
+///   `{"files": [{"name": "a.jar", "ok": false, "attempts": 4, "error": {...}}, ...], "failed": 1}`
+/// * negated `ErrorCode` on invalid arguments
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_start_upload_mods(
+    core: *const CrystalCore,
+    files_json: *const c_char,
+    access_key: *const c_char,
+    secret_key: *const c_char,
+    endpoint: *const c_char,
+    bucket: *const c_char,
+    max_concurrent: i32,
+) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        let request = UploadRequest::from_args(core, files_json, access_key, secret_key, endpoint, bucket, max_concurrent)?;
+
+        Ok(core.spawn_job("upload_mods", |progress, cancel| async move {
+            Ok(upload_batch(request, progress, cancel, |_| {}).await?.to_json())
+        }))
     })
 }
 
-/// Download multiple files from R2 in parallel with SHA1 verification
-/// 
+/// Download the client files of a signed pack manifest in parallel with
+/// SHA1 verification
+///
 /// # Arguments
-/// * `mods_json` - JSON array of mod objects: [{"name": "mod.jar", "url": "...", "sha1": "..."}]
-/// * `output_dir` - Directory to save downloaded files
+/// * `core` - Handle from `crystal_core_new`
+/// * `signed_manifest_json` - Signed manifest as published (see `manifest_sign`)
+/// * `public_key` - Hex public key the launcher trusts
+/// * `output_dir` - Instance directory; each file goes to its manifest `path`
+///   (`mods/a.jar`, `config/x.toml`), creating subdirectories as needed
 /// * `max_concurrent` - Maximum concurrent downloads (recommended: 10)
-/// * `callback` - Progress callback function
-/// 
+/// * `callback` - Called with the index of each file that succeeded, counted
+///   over the manifest's `client` and `both` files in manifest order
+///
+/// Nothing is downloaded unless the manifest verifies. Every file is then
+/// attempted even if some fail. When the core has a `store_dir`, files are
+/// fetched into the object store (skipping ones it already holds) and
+/// linked into `output_dir`.
+///
 /// # Returns
 /// * 0 on success
-/// * -1 on error
+/// * negated `ErrorCode` on error (see `crystal_last_error`); `-31` (`SIGNATURE`)
+///   for unsigned or tampered manifests, `-80` (`INCOMPLETE`) when some files
+///   failed, listed in the message
 #[unsafe(no_mangle)]
 pub extern "C" fn download_mods_parallel(
-    mods_json: *const c_char,
+    core: *const CrystalCore,
+    signed_manifest_json: *const c_char,
+    public_key: *const c_char,
     output_dir: *const c_char,
     max_concurrent: i32,
     callback: R2SyncCallback,
 ) -> i32 {
-    let rt = match Runtime::new() {
-        Ok(r) => r,
-        Err(_) => return -1,
-    };
-
-    rt.block_on(async {
-        // Parse inputs
-        let mods = match parse_mods_json(mods_json) {
-            Ok(m) => m,
-            Err(_) => return -1,
-        };
-
-        let output_dir_str = unsafe { CStr::from_ptr(output_dir).to_str().unwrap() };
-
-        let client = reqwest::Client::new();
-        let semaphore = Arc::new(Semaphore::new(max_concurrent as usize));
-        let _total = mods.len();
-
-        // Spawn parallel download tasks
-        let tasks: Vec<_> = mods
-            .into_iter()
-            .enumerate()
-            .map(|(idx, mod_info)| {
-                let client = client.clone();
-                let output_dir = output_dir_str.to_string();
-                let sem = semaphore.clone();
-
-                tokio::spawn(async move {
-                    let _permit = sem.acquire().await.unwrap();
-
-                    // Download and verify file
-                    let file_path = format!("{}/{}", output_dir, mod_info.name);
-                    download_and_verify(&client, &mod_info.url, &file_path, &mod_info.sha1).await?;
+    ffi_status(0, || {
+        let core = core_arg(core)?;
+        let request = DownloadRequest::from_args(core, signed_manifest_json, public_key, output_dir, max_concurrent)?;
+
+        core.runtime.block_on(download_batch(
+            core.net.clone(),
+            request,
+            Progress::for_core(core, None),
+            CancellationToken::new(),
+            move |idx| callback(idx as i32),
+        ))?
+        .into_result()
+    })
+}
 
-                    // Progress callback
-                    callback(idx as i32);
+/// Background variant of `download_mods_parallel`.
+///
+/// # Returns
+/// * Job id for `crystal_job_poll`. The job completes once every file was
+///   attempted; its result lists them:
+///   `{"files": [{"name": "a.jar", "ok": false, "attempts": 4, "error": {...}}, ...], "failed": 1}`
+/// * negated `ErrorCode` on invalid arguments or a manifest that does not verify
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_start_download_mods(
+    core: *const CrystalCore,
+    signed_manifest_json: *const c_char,
+    public_key: *const c_char,
+    output_dir: *const c_char,
+    max_concurrent: i32,
+) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        let request = DownloadRequest::from_args(core, signed_manifest_json, public_key, output_dir, max_concurrent)?;
+        let net = core.net.clone();
+
+        Ok(core.spawn_job("download_mods", |progress, cancel| async move {
+            Ok(download_batch(net, request, progress, cancel, |_| {}).await?.to_json())
+        }))
+    })
+}
 
-                    Ok::<_, anyhow::Error>(())
-                })
+// Batch engines (shared by the blocking exports and the job variants)
+
+struct UploadRequest {
+    files: Vec<String>,
+    access_key: String,
+    secret_key: String,
+    endpoint: String,
+    bucket: String,
+    max_concurrent: usize,
+    retry: RetryPolicy,
+    limits: Arc<NetLimits>,
+}
+
+impl UploadRequest {
+    fn from_args(
+        core: &CrystalCore,
+        files_json: *const c_char,
+        access_key: *const c_char,
+        secret_key: *const c_char,
+        endpoint: *const c_char,
+        bucket: *const c_char,
+        max_concurrent: i32,
+    ) -> Result<Self, CoreError> {
+        Ok(Self {
+            files: json_arg(files_json, "files_json")?,
+            access_key: str_arg(access_key, "access_key")?.to_string(),
+            secret_key: str_arg(secret_key, "secret_key")?.to_string(),
+            endpoint: str_arg(endpoint, "endpoint")?.to_string(),
+            bucket: str_arg(bucket, "bucket")?.to_string(),
+            max_concurrent: max_concurrent.max(1) as usize,
+            retry: core.config().retry.clone(),
+            limits: core.net.limits.clone(),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct DownloadRequest {
+    mods: Vec<ModInfo>,
+    output_dir: String,
+    max_concurrent: usize,
+    retry: RetryPolicy,
+    store: Option<ObjectStore>,
+}
+
+impl DownloadRequest {
+    /// The client files of a signed manifest; fails before anything else if
+    /// it does not verify against `public_key`.
+    fn from_args(
+        core: &CrystalCore,
+        signed_manifest_json: *const c_char,
+        public_key: *const c_char,
+        output_dir: *const c_char,
+        max_concurrent: i32,
+    ) -> Result<Self, CoreError> {
+        let manifest = verified_manifest_arg(signed_manifest_json, public_key)?;
+        Self::new(
+            core,
+            manifest.client_files(),
+            str_arg(output_dir, "output_dir")?.to_string(),
+            max_concurrent.max(1) as usize,
+        )
+    }
+
+    /// Names are paths relative to `output_dir` (usually plain file names).
+    /// Fails if any of them is unsafe (see `safe_path`): a manifest with one
+    /// such entry is not trusted at all.
+    pub(crate) fn new(core: &CrystalCore, mods: Vec<ModInfo>, output_dir: String, max_concurrent: usize) -> Result<Self, CoreError> {
+        check_mods(&mods)?;
+        Ok(Self {
+            mods,
+            output_dir,
+            max_concurrent: max_concurrent.max(1),
+            retry: core.config().retry.clone(),
+            store: core.store.clone(),
+        })
+    }
+
+    pub(crate) fn mods(&self) -> &[ModInfo] {
+        &self.mods
+    }
+
+    /// Same settings for another set of files (checked like in `new`).
+    pub(crate) fn with_mods(self, mods: Vec<ModInfo>) -> Result<Self, CoreError> {
+        check_mods(&mods)?;
+        Ok(Self { mods, ..self })
+    }
+}
+
+fn check_mods(mods: &[ModInfo]) -> Result<(), CoreError> {
+    for mod_info in mods {
+        safe_relative_path(&mod_info.name)?;
+        if mod_info.sha1.is_empty() && mod_info.hash.is_none() {
+            return Err(CoreError::invalid("mods", format!("no hash for {}", mod_info.name)));
+        }
+    }
+    Ok(())
+}
+
+/// Outcome of one file of a batch.
+struct FileOutcome {
+    name: String,
+    /// Attempts made by our retry layer (`None` when the transport retries itself).
+    attempts: Option<u32>,
+    result: Result<(), CoreError>,
+}
+
+/// JSON shape of one entry of a batch job result.
+#[derive(serde::Serialize)]
+struct FileResult<'a> {
+    name: &'a str,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attempts: Option<u32>,
+    error: Option<ErrorReport>,
+}
+
+/// Per-file outcomes of a batch: every file is attempted even if others fail.
+pub(crate) struct BatchReport {
+    files: Vec<FileOutcome>,
+}
+
+impl BatchReport {
+    /// Job result: `{"files": [{"name", "ok", "attempts", "error"}, ...], "failed": 2}`.
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        let files: Vec<_> = self.files
+            .iter()
+            .map(|f| FileResult {
+                name: &f.name,
+                ok: f.result.is_ok(),
+                attempts: f.attempts,
+                error: f.result.as_ref().err().map(ErrorReport::from),
             })
             .collect();
+        let failed = files.iter().filter(|f| !f.ok).count();
+        serde_json::json!({ "files": files, "failed": failed })
+    }
+
+    /// Names and errors of the files that failed.
+    pub(crate) fn failures(&self) -> impl Iterator<Item = (&str, &CoreError)> {
+        self.files.iter().filter_map(|f| f.result.as_ref().err().map(|e| (f.name.as_str(), e)))
+    }
 
-        // Wait for all downloads
-        for task in tasks {
-            if let Err(_) = task.await.unwrap() {
-                return -1;
+    /// Take the outcomes of files attempted again in `retried`.
+    pub(crate) fn replace(&mut self, retried: BatchReport) {
+        for outcome in retried.files {
+            match self.files.iter_mut().find(|f| f.name == outcome.name) {
+                Some(file) => *file = outcome,
+                None => self.files.push(outcome),
             }
         }
+    }
 
-        0
-    })
+    /// `Ok` if every file succeeded, `CoreError::Incomplete` otherwise.
+    pub(crate) fn into_result(self) -> Result<(), CoreError> {
+        let total = self.files.len();
+        let mut failed = Vec::new();
+        let mut first = None;
+        for file in self.files {
+            if let Err(e) = file.result {
+                failed.push(file.name);
+                first.get_or_insert(e);
+            }
+        }
+        match first {
+            None => Ok(()),
+            Some(first) => Err(CoreError::Incomplete { failed, total, first: Box::new(first) }),
+        }
+    }
 }
 
-// Helper functions
+/// Wait for every task of a batch. A task that died (panic) counts as a
+/// failure of its file; cancellation of the batch wins over everything.
+async fn join_batch(
+    tasks: Vec<(String, tokio::task::JoinHandle<Result<FileOutcome, CoreError>>)>,
+    cancel: &CancellationToken,
+) -> Result<BatchReport, CoreError> {
+    let mut files = Vec::with_capacity(tasks.len());
+    for (name, task) in tasks {
+        match task.await.map_err(join_error).and_then(|r| r) {
+            Ok(outcome) => files.push(outcome),
+            Err(CoreError::Cancelled) => {}
+            Err(e) => files.push(FileOutcome { name, attempts: None, result: Err(e) }),
+        }
+    }
+    check(cancel)?;
+    Ok(BatchReport { files })
+}
+
+/// Upload every file of `request`, calling `on_done(index)` as each one succeeds.
+async fn upload_batch<F>(
+    request: UploadRequest,
+    progress: Progress,
+    cancel: CancellationToken,
+    on_done: F,
+) -> Result<BatchReport, CoreError>
+where
+    F: Fn(usize) + Copy + Send + 'static,
+{
+    // Build S3 client (the SDK retries transient failures itself)
+    let client = build_s3_client(&request.endpoint, &request.access_key, &request.secret_key, &request.retry).await?;
+
+    // Tasks share a child token: cancelling the job, or this future being
+    // dropped, stops every in-flight upload.
+    let batch = cancel.child_token();
+    let _stop_on_exit = batch.clone().drop_guard();
+
+    // Semaphore for concurrency control
+    let semaphore = Arc::new(Semaphore::new(request.max_concurrent));
+    progress.set_items_total(request.files.len() as u64);
+
+    // Spawn parallel upload tasks
+    let tasks: Vec<_> = request.files
+        .into_iter()
+        .enumerate()
+        .map(|(idx, path)| {
+            let client = client.clone();
+            let bucket = request.bucket.clone();
+            let endpoint = request.endpoint.clone();
+            let limits = request.limits.clone();
+            let sem = semaphore.clone();
+            let progress = progress.clone();
+            let token = batch.clone();
+
+            let task = tokio::spawn(guard_task({
+                let path = path.clone();
+                async move {
+                    let _permit = run_cancellable(&token, async {
+                        sem.acquire()
+                            .await
+                            .map_err(|_| CoreError::Internal("semaphore closed".to_string()))
+                    })
+                    .await?;
 
-fn parse_files_json(files_json: *const c_char) -> Result<Vec<String>, anyhow::Error> {
-    let json_str = unsafe { CStr::from_ptr(files_json).to_str()? };
-    let files: Vec<String> = serde_json::from_str(json_str)?;
-    Ok(files)
+                    // Upload file
+                    let item = progress.start_item(path.as_str(), Phase::Uploading, None);
+                    let result = run_cancellable(&token, upload_single_file(&client, &bucket, &endpoint, &path, limits, &item, &token)).await;
+                    let result = item.finish(result);
+
+                    // Progress callback
+                    if result.is_ok() {
+                        on_done(idx);
+                    }
+
+                    Ok(FileOutcome { name: path, attempts: None, result })
+                }
+            }));
+            (path, task)
+        })
+        .collect();
+
+    // Wait for all uploads
+    join_batch(tasks, &batch).await
 }
 
-#[derive(serde::Deserialize)]
-struct ModInfo {
-    name: String,
-    url: String,
-    sha1: String,
+/// Download and verify every mod of `request`, calling `on_done(index)` as each one succeeds.
+pub(crate) async fn download_batch<F>(
+    net: Net,
+    request: DownloadRequest,
+    progress: Progress,
+    cancel: CancellationToken,
+    on_done: F,
+) -> Result<BatchReport, CoreError>
+where
+    F: Fn(usize) + Copy + Send + 'static,
+{
+    // See `upload_batch`: one token stops every in-flight download.
+    let batch = cancel.child_token();
+    let _stop_on_exit = batch.clone().drop_guard();
+
+    let semaphore = Arc::new(Semaphore::new(request.max_concurrent));
+    progress.set_items_total(request.mods.len() as u64);
+
+    // Spawn parallel download tasks
+    let tasks: Vec<_> = request.mods
+        .into_iter()
+        .enumerate()
+        .map(|(idx, mod_info)| {
+            let net = net.clone();
+            let output_dir = request.output_dir.clone();
+            let retry = request.retry.clone();
+            let store = request.store.clone();
+            let sem = semaphore.clone();
+            let progress = progress.clone();
+            let token = batch.clone();
+            let name = mod_info.name.clone();
+
+            let task = tokio::spawn(guard_task(async move {
+                let _permit = run_cancellable(&token, async {
+                    sem.acquire()
+                        .await
+                        .map_err(|_| CoreError::Internal("semaphore closed".to_string()))
+                })
+                .await?;
+
+                // Download and verify file (retrying transient failures; a
+                // retry resumes from the `.part` file). With an object store
+                // the file lands there once and is linked into `output_dir`.
+                let item = progress.start_item(mod_info.name.as_str(), Phase::Downloading, None);
+                let file_path = safe_join(Path::new(&output_dir), &mod_info.name)?;
+                if let Some(parent) = file_path.parent() {
+                    tokio::fs::create_dir_all(parent).await.map_err(|e| CoreError::io(parent, e))?;
+                }
+                let (result, attempts) = retry
+                    .run(&token, &mod_info.name, |attempt| {
+                        if attempt > 1 {
+                            item.restart(Phase::Downloading);
+                        }
+                        let (url, sha1, hash) = (&mod_info.url, &mod_info.sha1, &mod_info.hash);
+                        let (net, store, file_path, item, token) = (&net, &store, &file_path, &item, &token);
+                        async move {
+                            match (store, hash) {
+                                // The store is keyed by SHA-1: other digests bypass it.
+                                (_, Some(hash)) if sha1.is_empty() => {
+                                    download_file_with_hash(net, url, file_path, hash, item, token).await
+                                }
+                                (Some(store), _) => store.fetch_and_link(net, url, sha1, file_path, item, token).await.map(|_| ()),
+                                (None, _) => download_file(net, url, file_path, Some(sha1), item, token).await,
+                            }
+                        }
+                    })
+                    .await;
+                let result = item.finish(result);
+
+                // Progress callback
+                if result.is_ok() {
+                    on_done(idx);
+                }
+
+                Ok(FileOutcome { name: mod_info.name, attempts: Some(attempts), result })
+            }));
+            (name, task)
+        })
+        .collect();
+
+    // Wait for all downloads
+    join_batch(tasks, &batch).await
 }
 
-fn parse_mods_json(mods_json: *const c_char) -> Result<Vec<ModInfo>, anyhow::Error> {
-    let json_str = unsafe { CStr::from_ptr(mods_json).to_str()? };
-    let mods: Vec<ModInfo> = serde_json::from_str(json_str)?;
-    Ok(mods)
+// Helper functions
+
+/// A file to download: `{"name": "mod.jar", "url": "...", "sha1": "..."}`.
+/// `download_url` is accepted for `url`, as in the `official_mods` table.
+/// Sources without a SHA-1 leave `sha1` empty and set `hash` instead.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+pub(crate) struct ModInfo {
+    pub(crate) name: String,
+    #[serde(alias = "download_url")]
+    pub(crate) url: String,
+    #[serde(default)]
+    pub(crate) sha1: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) hash: Option<ExpectedHash>,
 }
 
 async fn build_s3_client(
     endpoint: &str,
     access_key: &str,
     secret_key: &str,
-) -> Result<Client, anyhow::Error> {
+    retry: &RetryPolicy,
+) -> Result<Client, CoreError> {
     let creds = Credentials::new(access_key, secret_key, None, None, "r2");
 
     let config = aws_config::defaults(BehaviorVersion::latest())
         .region(Region::new("auto"))
         .endpoint_url(format!("https://{}", endpoint))
         .credentials_provider(creds)
+        .retry_config(
+            RetryConfig::standard()
+                .with_max_attempts(retry.max_attempts.max(1))
+                .with_initial_backoff(Duration::from_millis(retry.initial_backoff_ms))
+                .with_max_backoff(Duration::from_millis(retry.max_backoff_ms)),
+        )
         .load()
         .await;
 
@@ -209,57 +553,40 @@ async fn build_s3_client(
 async fn upload_single_file(
     client: &Client,
     bucket: &str,
+    endpoint: &str,
     file_path: &str,
-) -> Result<(), anyhow::Error> {
+    limits: Arc<NetLimits>,
+    item: &ItemProgress,
+    cancel: &CancellationToken,
+) -> Result<(), CoreError> {
     let file_name = Path::new(file_path)
         .file_name()
-        .ok_or_else(|| anyhow::anyhow!("Invalid file path"))?
-        .to_str()
-        .ok_or_else(|| anyhow::anyhow!("Invalid UTF-8 in filename"))?;
-
-    let body = ByteStream::from_path(file_path).await?;
-
-    client
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| CoreError::invalid("files_json", format!("invalid file path {}", file_path)))?;
+
+    let size = tokio::fs::metadata(file_path).await.map_err(|e| CoreError::io(file_path, e))?.len();
+    item.set_total(size);
+    let _connection = limits.connect(&format!("https://{}", endpoint), cancel).await?;
+
+    // Streamed through the shared bandwidth limiter; rebuilt if the SDK retries
+    let (sent_tx, sent_rx) = mpsc::unbounded_channel();
+    let path = PathBuf::from(file_path);
+    let token = cancel.clone();
+    let body = SdkBody::retryable(move || {
+        let stream = reported_file_stream(path.clone(), limits.clone(), token.clone(), sent_tx.clone());
+        SdkBody::from_body_0_4(hyper::Body::wrap_stream(stream))
+    });
+
+    let upload = client
         .put_object()
         .bucket(bucket)
         .key(file_name)
-        .body(body)
-        .send()
-        .await?;
-
-    Ok(())
-}
-
-async fn download_and_verify(
-    client: &reqwest::Client,
-    url: &str,
-    output_path: &str,
-    expected_sha1: &str,
-) -> Result<(), anyhow::Error> {
-    use sha1::{Sha1, Digest};
-    use tokio::fs::File;
-    use tokio::io::AsyncWriteExt;
-
-    // Stream download
-    let response = client.get(url).send().await?;
-    let bytes = response.bytes().await?;
-
-    // Verify SHA1
-    let mut hasher = Sha1::new();
-    hasher.update(&bytes);
-    let hash = hex::encode(hasher.finalize());
-
-    if hash != expected_sha1 {
-        return Err(anyhow::anyhow!(
-            "SHA1 mismatch: expected {}, got {}",
-            expected_sha1,
-            hash
-        ));
-    }
-
-    // Write to disk
-    let mut file = File::create(output_path).await?;
-    file.write_all(&bytes).await?;
+        .content_length(size as i64)
+        .body(ByteStream::new(body))
+        .send();
+    track_upload(item, sent_rx, upload)
+        .await
+        .map_err(|e| CoreError::storage(file_name, e))?;
 
     Ok(())
 }
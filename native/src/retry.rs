@@ -0,0 +1,99 @@
+//! Retry policy for network operations.
+//!
+//! Only transient failures are retried (see `CoreError::is_transient`):
+//! timeouts, connection resets, HTTP 408/429/5xx. A 404 or a hash mismatch
+//! fails immediately. Between attempts we back off exponentially with
+//! jitter, or wait as long as the server's `Retry-After` asked (capped by
+//! `max_retry_after_ms`).
+
+use crate::cancel::{CancellationToken, run_cancellable};
+use crate::error::CoreError;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// Retry settings, part of `CoreConfig` (`"retry": {...}`).
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Attempts per operation, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled on every further attempt.
+    pub initial_backoff_ms: u64,
+    /// Upper bound for the computed delay (not for `Retry-After`).
+    pub max_backoff_ms: u64,
+    /// Upper bound for a server-requested `Retry-After` delay, so one bad
+    /// 429/503 cannot stall a batch for hours.
+    pub max_retry_after_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 30_000,
+            max_retry_after_ms: 120_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before attempt `attempt + 1` (`attempt` starts at 1): half of the
+    /// exponential step, plus a random share of the other half.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let step = self
+            .initial_backoff_ms
+            .saturating_mul(1u64 << (attempt - 1).min(20))
+            .min(self.max_backoff_ms);
+        let half = step / 2;
+        Duration::from_millis(half + jitter(half))
+    }
+
+    /// Run `op` until it succeeds, fails with a non-transient error, runs out
+    /// of attempts or `cancel` fires. Returns the result and the number of
+    /// attempts made.
+    pub async fn run<T, F, Fut>(&self, cancel: &CancellationToken, what: &str, mut op: F) -> (Result<T, CoreError>, u32)
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = Result<T, CoreError>>,
+    {
+        let max_attempts = self.max_attempts.max(1);
+        let mut attempt = 1;
+        loop {
+            let err = match op(attempt).await {
+                Ok(value) => return (Ok(value), attempt),
+                Err(e) => e,
+            };
+            if attempt >= max_attempts || !err.is_transient() {
+                return (Err(err), attempt);
+            }
+
+            let delay = match err.retry_after() {
+                Some(delay) => delay.min(Duration::from_millis(self.max_retry_after_ms)),
+                None => self.backoff(attempt),
+            };
+            println!("[Rust] {} failed (attempt {}/{}): {}; retrying in {:?}", what, attempt, max_attempts, err, delay);
+            let slept = run_cancellable(cancel, async {
+                tokio::time::sleep(delay).await;
+                Ok(())
+            })
+            .await;
+            if let Err(cancelled) = slept {
+                return (Err(cancelled), attempt);
+            }
+            attempt += 1;
+        }
+    }
+}
+
+/// Random value in `0..=max`, good enough to spread retries apart.
+fn jitter(max: u64) -> u64 {
+    if max == 0 {
+        return 0;
+    }
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(max);
+    hasher.finish() % (max + 1)
+}
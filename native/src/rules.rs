@@ -0,0 +1,183 @@
+//! Mojang `rules` evaluation, shared by version JSON libraries and launch
+//! arguments.
+//!
+//! A rule list starts out disallowed; every rule whose `os` and `features`
+//! match the environment sets the outcome to its `action`, so the last
+//! matching rule wins. An absent list allows everything.
+
+use std::collections::BTreeMap;
+
+/// What rules are evaluated against. Defaults to the machine we run on with
+/// no features enabled; the host may override any field (e.g. to resolve
+/// libraries for another OS).
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct Environment {
+    /// Mojang OS name: `windows`, `osx` or `linux`.
+    pub os_name: String,
+    /// Matched against `os.version` regexes (Java's `os.version`, e.g. `10.0`).
+    pub os_version: String,
+    /// Mojang arch names: `x86`, `amd64`, `arm64`...
+    pub arch: String,
+    /// Launch features (`is_demo_user`, `has_custom_resolution`, ...); missing ones are off.
+    pub features: BTreeMap<String, bool>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::current()
+    }
+}
+
+impl Environment {
+    /// The machine we run on.
+    pub fn current() -> Self {
+        let os_name = match std::env::consts::OS {
+            "windows" => "windows",
+            "macos" => "osx",
+            _ => "linux",
+        };
+        let arch = match std::env::consts::ARCH {
+            "x86_64" => "amd64",
+            "aarch64" => "arm64",
+            other => other,
+        };
+        Self {
+            os_name: os_name.to_string(),
+            os_version: os_version().to_string(),
+            arch: arch.to_string(),
+            features: BTreeMap::new(),
+        }
+    }
+
+    /// `32` or `64`, substituted for `${arch}` in natives classifiers.
+    pub fn arch_bits(&self) -> &'static str {
+        match self.arch.as_str() {
+            "x86" | "i386" | "i686" | "arm" => "32",
+            _ => "64",
+        }
+    }
+
+    fn feature(&self, name: &str) -> bool {
+        self.features.get(name).copied().unwrap_or(false)
+    }
+}
+
+/// OS version as Java reports it (`10.0` on Windows 10/11, `14.2.1` on macOS,
+/// the kernel release on Linux), read once per process.
+fn os_version() -> &'static str {
+    static OS_VERSION: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+    OS_VERSION.get_or_init(|| read_os_version().unwrap_or_default())
+}
+
+#[cfg(target_os = "linux")]
+fn read_os_version() -> Option<String> {
+    std::fs::read_to_string("/proc/sys/kernel/osrelease").ok().map(|v| v.trim().to_string())
+}
+
+#[cfg(target_os = "macos")]
+fn read_os_version() -> Option<String> {
+    let out = std::process::Command::new("sw_vers").arg("-productVersion").output().ok()?;
+    Some(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+/// `RtlGetVersion` reports the real version whatever the executable's
+/// manifest says (`GetVersionEx` caps it at 6.2), and needs no locale parsing.
+#[cfg(windows)]
+fn read_os_version() -> Option<String> {
+    #[repr(C)]
+    struct OsVersionInfoW {
+        size: u32,
+        major: u32,
+        minor: u32,
+        build: u32,
+        platform_id: u32,
+        csd_version: [u16; 128],
+    }
+
+    #[link(name = "ntdll")]
+    unsafe extern "system" {
+        fn RtlGetVersion(info: *mut OsVersionInfoW) -> i32;
+    }
+
+    let mut info = OsVersionInfoW {
+        size: std::mem::size_of::<OsVersionInfoW>() as u32,
+        major: 0,
+        minor: 0,
+        build: 0,
+        platform_id: 0,
+        csd_version: [0; 128],
+    };
+    // `info` is a properly sized OSVERSIONINFOW with `size` set.
+    let status = unsafe { RtlGetVersion(&mut info) };
+    (status == 0).then(|| format!("{}.{}", info.major, info.minor))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn read_os_version() -> Option<String> {
+    None
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Rule {
+    pub action: RuleAction,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<OsRule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub features: Option<BTreeMap<String, bool>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    Allow,
+    Disallow,
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct OsRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Regex over `Environment::os_version`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+}
+
+impl Rule {
+    /// Whether this rule applies in `env` (not whether it allows).
+    pub fn matches(&self, env: &Environment) -> bool {
+        let os = self.os.as_ref().is_none_or(|os| {
+            os.name.as_deref().is_none_or(|name| name == env.os_name)
+                && os.arch.as_deref().is_none_or(|arch| arch == env.arch)
+                && os.version.as_deref().is_none_or(|pattern| version_matches(pattern, &env.os_version))
+        });
+        let features = self
+            .features
+            .as_ref()
+            .is_none_or(|features| features.iter().all(|(name, value)| env.feature(name) == *value));
+        os && features
+    }
+}
+
+fn version_matches(pattern: &str, version: &str) -> bool {
+    match regex_lite::Regex::new(pattern) {
+        Ok(regex) => regex.is_match(version),
+        Err(e) => {
+            println!("[Rust] Ignoring invalid os.version rule {:?}: {}", pattern, e);
+            false
+        }
+    }
+}
+
+/// Evaluate a rule list: `None` allows, otherwise the last matching rule
+/// decides and no match disallows.
+pub fn rules_allow(rules: Option<&[Rule]>, env: &Environment) -> bool {
+    let Some(rules) = rules else { return true };
+    rules
+        .iter()
+        .rev()
+        .find(|rule| rule.matches(env))
+        .is_some_and(|rule| rule.action == RuleAction::Allow)
+}
@@ -0,0 +1,81 @@
+//! Validation of relative paths that come from manifests, archives and the
+//! host before they are joined to a directory on disk.
+//!
+//! Rejects anything that could land outside the target directory or that
+//! Windows would silently reinterpret: `..`, absolute paths, drive letters
+//! and `:` streams, reserved device names (`CON`, `NUL`, `COM1`...), names
+//! ending in a dot or space, and characters Windows does not allow.
+//!
+//! Also compiled into the bootstrapper (`#[path]` module), so it must only
+//! depend on `std` and `thiserror`.
+
+use std::path::{Path, PathBuf};
+
+/// A path was rejected; `reason` says which rule it broke.
+#[derive(Debug, thiserror::Error)]
+#[error("unsafe path {path:?}: {reason}")]
+pub struct UnsafePath {
+    pub path: String,
+    pub reason: &'static str,
+}
+
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "CONIN$", "CONOUT$",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+fn check_component(component: &str) -> Result<(), &'static str> {
+    if component == ".." {
+        return Err("parent directory component");
+    }
+    if component.contains(':') {
+        return Err("drive letter or stream name");
+    }
+    if component.chars().any(|c| c.is_control() || matches!(c, '<' | '>' | '"' | '|' | '?' | '*')) {
+        return Err("character not allowed in file names");
+    }
+    if component.ends_with('.') || component.ends_with(' ') {
+        return Err("name ends with a dot or space");
+    }
+    // `CON`, `con.txt` and `Con .jar` all open the console device on Windows.
+    let stem = component.split('.').next().unwrap_or(component).trim_end();
+    if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        return Err("reserved device name");
+    }
+    Ok(())
+}
+
+/// Validate a relative path using `/` or `\` separators and return it as a
+/// native path. Empty and `.` components are dropped, so `./mods//a.jar`
+/// becomes `mods/a.jar`; a trailing separator (zip directories) is fine.
+pub fn safe_relative_path(path: &str) -> Result<PathBuf, UnsafePath> {
+    let reject = |reason| UnsafePath { path: path.to_string(), reason };
+
+    if path.starts_with(['/', '\\']) {
+        return Err(reject("absolute path"));
+    }
+    let mut out = PathBuf::new();
+    for component in path.split(['/', '\\']).filter(|c| !c.is_empty() && *c != ".") {
+        check_component(component).map_err(reject)?;
+        out.push(component);
+    }
+    if out.as_os_str().is_empty() {
+        return Err(reject("empty path"));
+    }
+    Ok(out)
+}
+
+/// `base` joined with the validated `relative` path.
+pub fn safe_join(base: &Path, relative: &str) -> Result<PathBuf, UnsafePath> {
+    Ok(base.join(safe_relative_path(relative)?))
+}
+
+/// Validate a single file name (no directories at all).
+pub fn safe_file_name(name: &str) -> Result<&str, UnsafePath> {
+    if name.contains(['/', '\\']) {
+        return Err(UnsafePath { path: name.to_string(), reason: "contains a path separator" });
+    }
+    safe_relative_path(name)?;
+    Ok(name)
+}
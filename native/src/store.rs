@@ -0,0 +1,417 @@
+//! Content-addressed object store shared by every instance.
+//!
+//! Downloads with a known SHA-1 land once in `<store>/objects/ab/abcdef…`
+//! and are materialised into each instance's `mods/` or `libraries/` as a
+//! reflink where the filesystem supports it, else as a hard link (NTFS has no
+//! reflinks), else a plain copy. Hard-linked objects are made read-only, so an
+//! instance cannot write through its link into the shared object, and every
+//! object is re-verified through the hash cache before it is reused. Every
+//! materialised path is recorded in the core database, so `gc` can delete
+//! objects no instance uses any more.
+
+use crate::cancel::CancellationToken;
+use crate::context::{CrystalCore, SharedDb, lock_db};
+use crate::download::download_file;
+use crate::error::CoreError;
+use crate::ffi::{core_arg, ffi_status, ffi_string, join_error, str_arg};
+use crate::hash_cache::HashCache;
+use crate::hashing::{HashAlgorithm, hash_file};
+use crate::net::Net;
+use crate::progress::{ItemProgress, Phase, Progress};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::RwLock;
+
+/// Objects younger than this survive `gc` even when unreferenced: they may
+/// have just been fetched by a job that has not linked them yet.
+const GC_GRACE: Duration = Duration::from_secs(60 * 60);
+
+/// How an object was materialised at its destination.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkKind {
+    Reflink,
+    HardLink,
+    Copy,
+}
+
+/// Result of `ObjectStore::gc`.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct GcReport {
+    /// References dropped because their file was deleted or replaced.
+    pub pruned_refs: u64,
+    pub removed_objects: u64,
+    pub freed_bytes: u64,
+}
+
+/// Handle to the store; cheap to clone into jobs.
+#[derive(Clone)]
+pub struct ObjectStore {
+    root: PathBuf,
+    db: SharedDb,
+    /// Existing objects are re-verified through it before being trusted.
+    hash_cache: Arc<HashCache>,
+    /// Fetches and links take it shared (`fetch_and_link` for both steps),
+    /// `gc` exclusively, so an object is never collected between being found
+    /// and being linked.
+    lock: Arc<RwLock<()>>,
+}
+
+impl ObjectStore {
+    /// Open (creating if needed) the store rooted at `root`.
+    pub fn open(root: impl Into<PathBuf>, db: SharedDb, hash_cache: Arc<HashCache>) -> Result<Self, CoreError> {
+        let root = root.into();
+        let objects = root.join("objects");
+        std::fs::create_dir_all(&objects).map_err(|e| CoreError::io(&objects, e))?;
+
+        lock_db(&db).execute_batch(
+            "CREATE TABLE IF NOT EXISTS store_refs (
+                path TEXT PRIMARY KEY,
+                sha1 TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS store_refs_sha1 ON store_refs (sha1);",
+        )?;
+
+        Ok(Self { root, db, hash_cache, lock: Arc::new(RwLock::new(())) })
+    }
+
+    /// Path of the object with hex SHA-1 `sha1`.
+    pub fn object_path(&self, sha1: &str) -> Result<PathBuf, CoreError> {
+        if sha1.len() != 40 || !sha1.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(CoreError::invalid("sha1", format!("not a SHA-1 hex digest: {}", sha1)));
+        }
+        let sha1 = sha1.to_ascii_lowercase();
+        Ok(self.root.join("objects").join(&sha1[..2]).join(sha1))
+    }
+
+    pub fn contains(&self, sha1: &str) -> bool {
+        self.object_path(sha1).is_ok_and(|p| p.is_file())
+    }
+
+    /// Make sure the object exists, downloading it from `url` (verified
+    /// against `sha1`) if it does not or if the stored copy no longer hashes
+    /// to `sha1`.
+    pub async fn fetch(
+        &self,
+        net: &Net,
+        url: &str,
+        sha1: &str,
+        item: &ItemProgress,
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf, CoreError> {
+        let _shared = self.lock.read().await;
+        self.fetch_locked(net, url, sha1, item, cancel).await
+    }
+
+    async fn fetch_locked(
+        &self,
+        net: &Net,
+        url: &str,
+        sha1: &str,
+        item: &ItemProgress,
+        cancel: &CancellationToken,
+    ) -> Result<PathBuf, CoreError> {
+        let path = self.object_path(sha1)?;
+        if path.is_file() {
+            item.set_phase(Phase::Verifying);
+            if self.verify_object(&path, sha1, cancel).await? {
+                return Ok(path);
+            }
+            println!("[Rust] Store object {} is corrupt, downloading it again", path.display());
+            item.set_phase(Phase::Downloading);
+        }
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(|e| CoreError::io(parent, e))?;
+        }
+        download_file(net, url, &path, Some(sha1), item, cancel).await?;
+        Ok(path)
+    }
+
+    /// Whether the object at `path` still hashes to `sha1` (answered from the
+    /// hash cache while the file is unchanged). A corrupt object is deleted.
+    async fn verify_object(&self, path: &Path, sha1: &str, cancel: &CancellationToken) -> Result<bool, CoreError> {
+        let (store, path, sha1, cancel) = (self.clone(), path.to_path_buf(), sha1.to_string(), cancel.clone());
+        tokio::task::spawn_blocking(move || store.verify_object_blocking(&path, &sha1, &cancel))
+            .await
+            .map_err(join_error)?
+    }
+
+    fn verify_object_blocking(&self, path: &Path, sha1: &str, cancel: &CancellationToken) -> Result<bool, CoreError> {
+        // Not counted in the caller's download progress.
+        let item = Progress::default().start_item(path.display().to_string(), Phase::Verifying, None);
+        let (hashes, _) =
+            self.hash_cache.get_or_hash(path, &[HashAlgorithm::Sha1], |missing| hash_file(path, missing, &item, cancel))?;
+        if hashes.sha1.is_some_and(|actual| actual.eq_ignore_ascii_case(sha1)) {
+            return Ok(true);
+        }
+        std::fs::remove_file(path).map_err(|e| CoreError::io(path, e))?;
+        Ok(false)
+    }
+
+    /// `fetch` then `link`: the download path for files with a known hash.
+    pub async fn fetch_and_link(
+        &self,
+        net: &Net,
+        url: &str,
+        sha1: &str,
+        dest: &Path,
+        item: &ItemProgress,
+        cancel: &CancellationToken,
+    ) -> Result<LinkKind, CoreError> {
+        // Held until the link exists, so `gc` cannot collect the object in between.
+        let shared = self.lock.clone().read_owned().await;
+        self.fetch_locked(net, url, sha1, item, cancel).await?;
+        item.set_phase(Phase::Writing);
+
+        let (store, sha1, dest) = (self.clone(), sha1.to_string(), dest.to_path_buf());
+        tokio::task::spawn_blocking(move || {
+            let _shared = shared;
+            store.link_locked(&sha1, &dest)
+        })
+        .await
+        .map_err(join_error)?
+    }
+
+    /// Materialise object `sha1` at `dest` (replacing whatever is there) and
+    /// record the reference. Blocking; must not be called from async code.
+    pub fn link(&self, sha1: &str, dest: &Path) -> Result<LinkKind, CoreError> {
+        let _shared = self.lock.blocking_read();
+        self.link_locked(sha1, dest)
+    }
+
+    fn link_locked(&self, sha1: &str, dest: &Path) -> Result<LinkKind, CoreError> {
+        let object = self.object_path(sha1)?;
+        // Cheap while the object is unchanged (hash cache hit); a corrupt
+        // object is deleted rather than linked.
+        if !object.is_file() || !self.verify_object_blocking(&object, sha1, &CancellationToken::new())? {
+            return Err(CoreError::io(&object, std::io::Error::from(std::io::ErrorKind::NotFound)));
+        }
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| CoreError::io(parent, e))?;
+        }
+
+        // Build next to `dest`, then rename over it: `dest` is never half-written.
+        let mut tmp_name = dest.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".link");
+        let tmp = dest.with_file_name(tmp_name);
+        let _ = std::fs::remove_file(&tmp);
+
+        let kind = if reflink_copy::reflink(&object, &tmp).is_ok() {
+            LinkKind::Reflink
+        } else if make_read_only(&object).is_ok() && std::fs::hard_link(&object, &tmp).is_ok() {
+            LinkKind::HardLink
+        } else {
+            copy_contents(&object, &tmp)?;
+            LinkKind::Copy
+        };
+        // A read-only `dest` (an earlier hard link) cannot be replaced by a rename on Windows.
+        if std::fs::metadata(dest).is_ok_and(|m| m.permissions().readonly()) {
+            let _ = std::fs::remove_file(dest);
+        }
+        if let Err(e) = std::fs::rename(&tmp, dest) {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(CoreError::io(dest, e));
+        }
+
+        self.track(sha1, dest)?;
+        Ok(kind)
+    }
+
+    /// Record that `path` holds object `sha1`, e.g. after a linked file was
+    /// moved to its final place.
+    pub fn track(&self, sha1: &str, path: &Path) -> Result<(), CoreError> {
+        lock_db(&self.db).execute(
+            "INSERT INTO store_refs (path, sha1) VALUES (?1, ?2)
+             ON CONFLICT(path) DO UPDATE SET sha1 = excluded.sha1",
+            (ref_key(path), sha1.to_ascii_lowercase()),
+        )?;
+        Ok(())
+    }
+
+    /// Drop references whose file is gone or no longer matches its object,
+    /// then delete every object nothing refers to. Blocking; must not be
+    /// called from async code.
+    pub fn gc(&self) -> Result<GcReport, CoreError> {
+        let _exclusive = self.lock.blocking_write();
+        let mut report = GcReport::default();
+
+        // 1. Prune stale references (cheap check: existence and size)
+        let refs: Vec<(String, String)> = {
+            let db = lock_db(&self.db);
+            let mut stmt = db.prepare("SELECT path, sha1 FROM store_refs")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<_, _>>()?
+        };
+        let mut live = std::collections::HashSet::new();
+        for (path, sha1) in refs {
+            let object_len = self.object_path(&sha1).ok().and_then(|p| std::fs::metadata(p).ok()).map(|m| m.len());
+            let file_len = std::fs::metadata(&path).ok().map(|m| m.len());
+            if file_len.is_some() && file_len == object_len {
+                live.insert(sha1);
+            } else {
+                lock_db(&self.db).execute("DELETE FROM store_refs WHERE path = ?1", [&path])?;
+                report.pruned_refs += 1;
+            }
+        }
+
+        // 2. Delete unreferenced objects
+        let objects = self.root.join("objects");
+        for shard in read_dir(&objects)? {
+            for object in read_dir(&shard)? {
+                let name = object.file_name().unwrap_or_default().to_string_lossy().to_ascii_lowercase();
+                // Leaves `.part` downloads and anything else that is not an object alone.
+                if self.object_path(&name).is_err() || live.contains(&name) {
+                    continue;
+                }
+                let Ok(meta) = std::fs::metadata(&object) else { continue };
+                let age = meta.modified().ok().and_then(|t| SystemTime::now().duration_since(t).ok());
+                if age.is_none_or(|age| age < GC_GRACE) {
+                    continue;
+                }
+                if std::fs::remove_file(&object).is_ok() {
+                    report.removed_objects += 1;
+                    report.freed_bytes += meta.len();
+                }
+            }
+            // Only succeeds once the shard is empty.
+            let _ = std::fs::remove_dir(&shard);
+        }
+
+        println!(
+            "[Rust] Store GC: {} stale refs, {} objects removed ({} bytes)",
+            report.pruned_refs, report.removed_objects, report.freed_bytes
+        );
+        Ok(report)
+    }
+}
+
+/// Mark `object` read-only, which also covers every hard link to it.
+fn make_read_only(object: &Path) -> std::io::Result<()> {
+    let mut permissions = std::fs::metadata(object)?.permissions();
+    if !permissions.readonly() {
+        permissions.set_readonly(true);
+        std::fs::set_permissions(object, permissions)?;
+    }
+    Ok(())
+}
+
+/// Copy the bytes of `from` into a new, writable `to` (`std::fs::copy` would
+/// carry over the read-only flag of a hard-linked object).
+fn copy_contents(from: &Path, to: &Path) -> Result<(), CoreError> {
+    let mut src = std::fs::File::open(from).map_err(|e| CoreError::io(from, e))?;
+    let mut dst = std::fs::File::create(to).map_err(|e| CoreError::io(to, e))?;
+    std::io::copy(&mut src, &mut dst).map_err(|e| CoreError::io(to, e))?;
+    Ok(())
+}
+
+/// Key under which a destination is recorded: its canonical path when it
+/// exists, so the same file reached through different spellings matches.
+fn ref_key(path: &Path) -> String {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()).display().to_string()
+}
+
+fn read_dir(dir: &Path) -> Result<Vec<PathBuf>, CoreError> {
+    let entries = std::fs::read_dir(dir).map_err(|e| CoreError::io(dir, e))?;
+    Ok(entries.filter_map(|e| e.ok()).map(|e| e.path()).collect())
+}
+
+impl CrystalCore {
+    /// The object store, if `store_dir` was configured.
+    pub fn store(&self) -> Result<&ObjectStore, CoreError> {
+        self.store
+            .as_ref()
+            .ok_or_else(|| CoreError::invalid("store_dir", "object store not configured"))
+    }
+}
+
+/// Materialise a stored object at `dest_path` and record the reference.
+///
+/// # Returns
+/// * 1 on success
+/// * negated `ErrorCode` on error (`NOT_FOUND` if the object is not in the store)
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_store_link(core: *const CrystalCore, sha1: *const c_char, dest_path: *const c_char) -> i32 {
+    ffi_status(1, || {
+        let store = core_arg(core)?.store()?;
+        let sha1 = str_arg(sha1, "sha1")?;
+        let dest = str_arg(dest_path, "dest_path")?;
+        store.link(sha1, Path::new(dest)).map(|_| ())
+    })
+}
+
+/// Garbage-collect the object store: forget references to deleted or
+/// replaced files and delete objects no instance uses.
+///
+/// # Returns
+/// * JSON `{"pruned_refs": 3, "removed_objects": 2, "freed_bytes": 1048576}` (free with `free_string`)
+/// * null on error (see `crystal_last_error`)
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_store_gc(core: *const CrystalCore) -> *mut c_char {
+    ffi_string(|| {
+        let report = core_arg(core)?.store()?.gc()?;
+        serde_json::to_string(&report).map_err(|e| CoreError::Internal(e.to_string()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    const ABC_SHA1: &str = "a9993e364706816aba3e25717850c26c9cd0d89d";
+
+    fn open_store(dir: &Path) -> ObjectStore {
+        let db: SharedDb = Arc::new(Mutex::new(rusqlite::Connection::open_in_memory().unwrap()));
+        let cache = Arc::new(HashCache::open(db.clone()).unwrap());
+        ObjectStore::open(dir.join("store"), db, cache).unwrap()
+    }
+
+    fn put_object(store: &ObjectStore, sha1: &str, contents: &[u8]) -> PathBuf {
+        let object = store.object_path(sha1).unwrap();
+        std::fs::create_dir_all(object.parent().unwrap()).unwrap();
+        std::fs::write(&object, contents).unwrap();
+        object
+    }
+
+    #[test]
+    fn linked_objects_are_protected_and_copies_stay_writable() {
+        let dir = std::env::temp_dir().join(format!("crystal-store-link-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = open_store(&dir);
+        let object = put_object(&store, ABC_SHA1, b"abc");
+
+        let dest = dir.join("instance/mods/a.jar");
+        let kind = store.link(ABC_SHA1, &dest).unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), b"abc");
+        assert!(!dest.with_file_name("a.jar.link").exists());
+        if kind == LinkKind::HardLink {
+            assert!(std::fs::metadata(&object).unwrap().permissions().readonly());
+        }
+
+        // Linking again over an earlier (possibly read-only) link
+        store.link(ABC_SHA1, &dest).unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), b"abc");
+
+        let copy = dir.join("copy.jar");
+        copy_contents(&object, &copy).unwrap();
+        assert!(!std::fs::metadata(&copy).unwrap().permissions().readonly());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn corrupt_objects_are_not_linked() {
+        let dir = std::env::temp_dir().join(format!("crystal-store-corrupt-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = open_store(&dir);
+        let object = put_object(&store, ABC_SHA1, b"abd");
+
+        assert!(store.link(ABC_SHA1, &dir.join("instance/mods/a.jar")).is_err());
+        assert!(!object.exists());
+        assert!(!dir.join("instance/mods/a.jar").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,666 @@
+//! Differential sync of the official mods folder.
+//!
+//! `plan_sync` compares the `mods/` files of a signed pack manifest (see
+//! `manifest`) with `mods/`:
+//! official files whose SHA-1 matches are left as they are, missing or
+//! outdated ones are downloaded, and files we installed earlier (listed in
+//! `<game_dir>/.official_mods`) that are no longer official are deleted.
+//! Anything else in `mods/` was added by the user and is never touched.
+//!
+//! `apply_sync` is transactional. Downloads go to `<game_dir>/.sync_staging`
+//! and are verified there. Only then is the new folder assembled in
+//! `.mods_next` (hard links of the files we keep plus the staged downloads)
+//! and swapped in with two renames. The new manifest is written beforehand
+//! as `.official_mods.next` and promoted right after the swap; if we die in
+//! between, the next plan or apply finishes the promotion, so `mods/` and
+//! `.official_mods` never disagree. The previous folder and manifest are
+//! kept in `.mods_snapshot`, and `rollback_mods` swaps them back if the new
+//! pack crashes.
+
+use crate::cancel::{CancellationToken, check};
+use crate::context::CrystalCore;
+use crate::error::CoreError;
+use crate::ffi::{core_arg, ffi_status, ffi_string, join_error, json_arg, str_arg, to_json};
+use crate::hash_cache::HashCache;
+use crate::hashing::{HashAlgorithm, hash_files};
+use crate::jobs::ffi_job;
+use crate::manifest::{PackManifest, verified_manifest_arg};
+use crate::net::Net;
+use crate::progress::Progress;
+use crate::r2_sync::{DownloadRequest, ModInfo, download_batch};
+use crate::safe_path::safe_file_name;
+use crate::store::ObjectStore;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Names of the official files installed by the last sync, one per line.
+const MANIFEST_FILE: &str = ".official_mods";
+/// The manifest of `.mods_next`, promoted once it has been swapped in.
+const PENDING_MANIFEST: &str = ".official_mods.next";
+const STAGING_DIR: &str = ".sync_staging";
+/// The next `mods/`, assembled before the swap.
+const NEXT_DIR: &str = ".mods_next";
+/// `mods/` and `.official_mods` as they were before the last sync.
+const SNAPSHOT_DIR: &str = ".mods_snapshot";
+/// Directories a sync leaves next to `mods/`; never part of an instance.
+pub(crate) const WORK_DIRS: [&str; 3] = [STAGING_DIR, NEXT_DIR, SNAPSHOT_DIR];
+
+/// What `apply_sync` has to do to bring `mods_dir` in line with the remote list.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct SyncPlan {
+    pub mods_dir: String,
+    /// Official files missing locally or with a different SHA-1.
+    pub(crate) download: Vec<ModInfo>,
+    /// Official files already present with the right SHA-1.
+    pub up_to_date: Vec<String>,
+    /// Files from a previous sync that are no longer official.
+    pub delete: Vec<String>,
+    /// Files the user added; left alone.
+    pub user_files: Vec<String>,
+    /// The new official list, written to `.official_mods` once applied.
+    pub official: Vec<String>,
+}
+
+/// Result of `apply_sync`.
+#[derive(Debug, serde::Serialize)]
+pub struct SyncReport {
+    pub downloaded: Vec<String>,
+    pub deleted: Vec<String>,
+}
+
+/// Directory holding `.official_mods` and the staging area: the game
+/// directory that contains `mods/`.
+fn game_dir(mods_dir: &Path) -> &Path {
+    mods_dir.parent().unwrap_or(mods_dir)
+}
+
+fn read_manifest(mods_dir: &Path) -> Result<HashSet<String>, CoreError> {
+    let path = game_dir(mods_dir).join(MANIFEST_FILE);
+    match std::fs::read_to_string(&path) {
+        Ok(content) => Ok(content.lines().map(str::trim).filter(|l| !l.is_empty()).map(String::from).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(e) => Err(CoreError::io(&path, e)),
+    }
+}
+
+/// Replace `.official_mods` without ever leaving a truncated one behind.
+fn write_manifest(mods_dir: &Path, names: &[String]) -> Result<(), CoreError> {
+    write_names(&game_dir(mods_dir).join(MANIFEST_FILE), names)
+}
+
+fn write_names(path: &Path, names: &[String]) -> Result<(), CoreError> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, names.join("\n")).map_err(|e| CoreError::io(&tmp, e))?;
+    std::fs::rename(&tmp, path).map_err(|e| CoreError::io(path, e))
+}
+
+/// Finish an apply that died between swapping in `.mods_next` and promoting
+/// its manifest. `.mods_next` only disappears through the swap, so while it
+/// exists `mods/` is still the old folder and the pending manifest is dropped.
+fn recover_manifest(mods_dir: &Path) -> Result<(), CoreError> {
+    let game_dir = game_dir(mods_dir);
+    let pending = game_dir.join(PENDING_MANIFEST);
+    if !pending.exists() {
+        return Ok(());
+    }
+    if game_dir.join(NEXT_DIR).exists() {
+        return remove_all(&pending);
+    }
+    println!("[Rust] Completing interrupted sync in {}", game_dir.display());
+    let manifest = game_dir.join(MANIFEST_FILE);
+    std::fs::rename(&pending, &manifest).map_err(|e| CoreError::io(&manifest, e))
+}
+
+/// Mirror `src` into `dst` with hard links (copies where linking fails),
+/// skipping top-level names in `skip`.
+fn link_tree(src: &Path, dst: &Path, skip: &HashSet<&str>) -> Result<(), CoreError> {
+    std::fs::create_dir_all(dst).map_err(|e| CoreError::io(dst, e))?;
+    let entries = match std::fs::read_dir(src) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(CoreError::io(src, e)),
+    };
+    for entry in entries {
+        let entry = entry.map_err(|e| CoreError::io(src, e))?;
+        if entry.file_name().to_str().is_some_and(|n| skip.contains(n)) {
+            continue;
+        }
+        let (from, to) = (entry.path(), dst.join(entry.file_name()));
+        let file_type = entry.file_type().map_err(|e| CoreError::io(&from, e))?;
+        if file_type.is_dir() {
+            link_tree(&from, &to, &HashSet::new())?;
+        } else if std::fs::hard_link(&from, &to).is_err() {
+            std::fs::copy(&from, &to).map_err(|e| CoreError::io(&to, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove `path` (file or directory) if it exists.
+fn remove_all(path: &Path) -> Result<(), CoreError> {
+    let result = if path.is_dir() { std::fs::remove_dir_all(path) } else { std::fs::remove_file(path) };
+    match result {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(CoreError::io(path, e)),
+        _ => Ok(()),
+    }
+}
+
+/// Put `next` in place of `current`, moving `current` to `old`. If the second
+/// rename fails the first is undone, so `current` never goes missing.
+fn swap_dirs(current: &Path, next: &Path, old: &Path) -> Result<(), CoreError> {
+    let had_current = current.exists();
+    if had_current {
+        std::fs::rename(current, old).map_err(|e| CoreError::io(current, e))?;
+    }
+    if let Err(e) = std::fs::rename(next, current) {
+        if had_current {
+            let _ = std::fs::rename(old, current);
+        }
+        return Err(CoreError::io(current, e));
+    }
+    Ok(())
+}
+
+/// Regular files directly inside `dir` (none if it doesn't exist yet).
+fn list_files(dir: &Path) -> Result<BTreeSet<String>, CoreError> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeSet::new()),
+        Err(e) => return Err(CoreError::io(dir, e)),
+    };
+    Ok(entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+        .filter_map(|e| e.file_name().into_string().ok())
+        .collect())
+}
+
+/// Compare `remote` with the contents of `mods_dir`. Local copies of
+/// official files are hashed in parallel through `cache`.
+pub(crate) async fn plan(
+    remote: Vec<ModInfo>,
+    mods_dir: PathBuf,
+    cache: Arc<HashCache>,
+    progress: Progress,
+    cancel: CancellationToken,
+) -> Result<SyncPlan, CoreError> {
+    // 1. Validate the remote list
+    let mut names = HashSet::new();
+    for m in &remote {
+        safe_file_name(&m.name)?;
+        if !names.insert(m.name.as_str()) {
+            return Err(CoreError::invalid("remote_manifest_json", format!("{} is listed twice", m.name)));
+        }
+    }
+
+    // 2. Previous official list and local files
+    recover_manifest(&mods_dir)?;
+    let previous = read_manifest(&mods_dir)?;
+    let local = list_files(&mods_dir)?;
+
+    // 3. Hash local copies of official files
+    let present: Vec<&ModInfo> = remote.iter().filter(|m| local.contains(&m.name)).collect();
+    let paths = present.iter().map(|m| mods_dir.join(&m.name).display().to_string()).collect();
+    let hashes = hash_files(paths, vec![HashAlgorithm::Sha1], cache, progress, cancel).await?;
+    let local_sha1: HashMap<&str, String> = present
+        .iter()
+        .zip(hashes)
+        .filter_map(|(m, (_, result))| Some((m.name.as_str(), result.ok()?.sha1?)))
+        .collect();
+
+    // 4. Classify
+    let mut plan = SyncPlan {
+        mods_dir: mods_dir.display().to_string(),
+        download: Vec::new(),
+        up_to_date: Vec::new(),
+        delete: Vec::new(),
+        user_files: Vec::new(),
+        official: remote.iter().map(|m| m.name.clone()).collect(),
+    };
+    for m in &remote {
+        match local_sha1.get(m.name.as_str()) {
+            Some(sha1) if sha1.eq_ignore_ascii_case(&m.sha1) => plan.up_to_date.push(m.name.clone()),
+            _ => plan.download.push(m.clone()),
+        }
+    }
+    for name in local.into_iter().filter(|n| !names.contains(n.as_str())) {
+        if previous.contains(&name) {
+            plan.delete.push(name);
+        } else {
+            plan.user_files.push(name);
+        }
+    }
+
+    println!(
+        "[Rust] Sync plan: {} to download, {} up to date, {} to delete, {} user files",
+        plan.download.len(), plan.up_to_date.len(), plan.delete.len(), plan.user_files.len()
+    );
+    Ok(plan)
+}
+
+/// A validated plan plus what `apply` needs from the core.
+struct ApplyRequest {
+    plan: SyncPlan,
+    net: Net,
+    store: Option<ObjectStore>,
+    downloads: DownloadRequest,
+}
+
+impl ApplyRequest {
+    fn new(core: &CrystalCore, mut plan: SyncPlan, manifest: &PackManifest) -> Result<Self, CoreError> {
+        // The plan comes back from the host: only its file names are used,
+        // URLs and hashes are taken from the signed manifest again.
+        let signed: HashMap<String, ModInfo> = manifest.client_mods().into_iter().map(|m| (m.name.clone(), m)).collect();
+        plan.download = plan
+            .download
+            .iter()
+            .map(|m| {
+                signed.get(&m.name).cloned().ok_or_else(|| CoreError::Signature {
+                    reason: format!("{} is not in the signed manifest", m.name),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        plan.official = manifest.client_mods().into_iter().map(|m| m.name).collect();
+        for name in plan.download.iter().map(|m| &m.name).chain(&plan.delete).chain(&plan.official) {
+            safe_file_name(name)?;
+        }
+        let staging = game_dir(Path::new(&plan.mods_dir)).join(STAGING_DIR);
+        let downloads = DownloadRequest::new(
+            core,
+            plan.download.clone(),
+            staging.display().to_string(),
+            core.config().max_concurrent_downloads,
+        )?;
+        Ok(Self { plan, net: core.net.clone(), store: core.store.clone(), downloads })
+    }
+}
+
+/// Download everything into the staging directory, then swap in the new
+/// `mods_dir` (see the module docs) and record the new official list. If
+/// any download fails `mods_dir` is left untouched (and the staging
+/// directory keeps partial downloads for the next attempt).
+async fn apply(request: ApplyRequest, progress: Progress, cancel: CancellationToken) -> Result<SyncReport, CoreError> {
+    let ApplyRequest { plan, net, store, downloads } = request;
+    let mods_dir = PathBuf::from(&plan.mods_dir);
+    let staging = game_dir(&mods_dir).join(STAGING_DIR);
+
+    // 1. Download and verify into staging
+    if !plan.download.is_empty() {
+        tokio::fs::create_dir_all(&staging).await.map_err(|e| CoreError::io(&staging, e))?;
+        download_batch(net, downloads, progress, cancel.clone(), |_| {}).await?.into_result()?;
+    }
+    check(&cancel)?;
+
+    // 2. Commit (not cancellable: it only renames and deletes)
+    tokio::task::spawn_blocking(move || commit(&plan, &mods_dir, &staging, store.as_ref()))
+        .await
+        .map_err(join_error)?
+}
+
+fn commit(plan: &SyncPlan, mods_dir: &Path, staging: &Path, store: Option<&ObjectStore>) -> Result<SyncReport, CoreError> {
+    recover_manifest(mods_dir)?;
+
+    // Nothing changes: keep the existing snapshot rather than replace it with
+    // a copy of the current folder.
+    if plan.download.is_empty() && plan.delete.is_empty() {
+        write_manifest(mods_dir, &plan.official)?;
+        return Ok(SyncReport { downloaded: Vec::new(), deleted: Vec::new() });
+    }
+
+    let game_dir = game_dir(mods_dir);
+    let next = game_dir.join(NEXT_DIR);
+    let snapshot = game_dir.join(SNAPSHOT_DIR);
+
+    // 1. Assemble the new folder: everything we keep, plus the downloads
+    //    (already verified against their SHA-1 when they reached staging)
+    remove_all(&next)?;
+    let replaced: HashSet<&str> =
+        plan.download.iter().map(|m| m.name.as_str()).chain(plan.delete.iter().map(String::as_str)).collect();
+    link_tree(mods_dir, &next, &replaced)?;
+    for m in &plan.download {
+        let dest = next.join(&m.name);
+        std::fs::rename(staging.join(&m.name), &dest).map_err(|e| CoreError::io(&dest, e))?;
+    }
+
+    // 2. Swap it in; the old folder and manifest become the snapshot, and
+    //    the new manifest (written first) is promoted
+    remove_all(&snapshot)?;
+    std::fs::create_dir_all(&snapshot).map_err(|e| CoreError::io(&snapshot, e))?;
+    let manifest = game_dir.join(MANIFEST_FILE);
+    if manifest.exists() {
+        std::fs::copy(&manifest, snapshot.join(MANIFEST_FILE)).map_err(|e| CoreError::io(&manifest, e))?;
+    }
+    let pending = game_dir.join(PENDING_MANIFEST);
+    write_names(&pending, &plan.official)?;
+    if let Err(e) = swap_dirs(mods_dir, &next, &snapshot.join("mods")) {
+        let _ = std::fs::remove_file(&pending);
+        return Err(e);
+    }
+    std::fs::rename(&pending, &manifest).map_err(|e| CoreError::io(&manifest, e))?;
+
+    // 3. Bookkeeping
+    let report = SyncReport {
+        downloaded: plan.download.iter().map(|m| m.name.clone()).collect(),
+        deleted: plan.delete.iter().filter(|n| snapshot.join("mods").join(n).exists()).cloned().collect(),
+    };
+    if let Some(store) = store {
+        for m in &plan.download {
+            store.track(&m.sha1, &mods_dir.join(&m.name))?;
+        }
+    }
+    let _ = std::fs::remove_dir_all(staging);
+
+    println!("[Rust] Sync applied: {} downloaded, {} deleted", report.downloaded.len(), report.deleted.len());
+    Ok(report)
+}
+
+/// Swap the snapshot taken by the last `apply_sync` back into place.
+/// Files the user added since then (not in the current `.official_mods`)
+/// are carried over. The snapshot is consumed.
+pub fn rollback(instance_dir: &Path) -> Result<(), CoreError> {
+    let mods_dir = instance_dir.join("mods");
+    let snapshot = instance_dir.join(SNAPSHOT_DIR);
+    let previous = snapshot.join("mods");
+    if !previous.is_dir() {
+        return Err(CoreError::io(&previous, std::io::Error::from(std::io::ErrorKind::NotFound)));
+    }
+
+    // 1. Carry user files over into the snapshot
+    recover_manifest(&mods_dir)?;
+    let official = read_manifest(&mods_dir)?;
+    for name in list_files(&mods_dir)?.into_iter().filter(|n| !official.contains(n)) {
+        let dest = previous.join(&name);
+        if !dest.exists() {
+            let src = mods_dir.join(&name);
+            std::fs::hard_link(&src, &dest)
+                .or_else(|_| std::fs::copy(&src, &dest).map(|_| ()))
+                .map_err(|e| CoreError::io(&dest, e))?;
+        }
+    }
+
+    // 2. Swap, then restore the manifest that went with the snapshot
+    let discarded = instance_dir.join(NEXT_DIR);
+    remove_all(&discarded)?;
+    swap_dirs(&mods_dir, &previous, &discarded)?;
+    let manifest = instance_dir.join(MANIFEST_FILE);
+    let saved = snapshot.join(MANIFEST_FILE);
+    if saved.exists() {
+        std::fs::rename(&saved, &manifest).map_err(|e| CoreError::io(&manifest, e))?;
+    } else {
+        remove_all(&manifest)?;
+    }
+
+    let _ = std::fs::remove_dir_all(&discarded);
+    let _ = std::fs::remove_dir_all(&snapshot);
+    println!("[Rust] Rolled back mods in {}", instance_dir.display());
+    Ok(())
+}
+
+/// Work out what it takes to sync `mods_dir` with the official mod list.
+///
+/// # Arguments
+/// * `signed_manifest_json` - Signed pack manifest as published; its `client` and
+///   `both` files directly under `mods/` are the official list
+/// * `public_key` - Hex public key the launcher trusts
+/// * `mods_dir` - The instance's `mods` directory; `.official_mods` is read from its parent
+///
+/// # Returns
+/// * JSON plan to pass to `apply_sync` (free with `free_string`):
+///   `{"mods_dir": "...", "download": [{"name", "url", "sha1"}], "up_to_date": ["a.jar"],
+///     "delete": ["old.jar"], "user_files": ["mine.jar"], "official": ["a.jar", ...]}`
+/// * null on error (see `crystal_last_error`); `SIGNATURE` (-31) for unsigned
+///   or tampered manifests, checked before anything is read
+#[unsafe(no_mangle)]
+pub extern "C" fn plan_sync(
+    core: *const CrystalCore,
+    signed_manifest_json: *const c_char,
+    public_key: *const c_char,
+    mods_dir: *const c_char,
+) -> *mut c_char {
+    ffi_string(|| {
+        let core = core_arg(core)?;
+        let remote = verified_manifest_arg(signed_manifest_json, public_key)?.client_mods();
+        let mods_dir = PathBuf::from(str_arg(mods_dir, "mods_dir")?);
+
+        let plan = core.runtime.block_on(plan(
+            remote,
+            mods_dir,
+            core.hash_cache.clone(),
+            Progress::for_core(core, None),
+            CancellationToken::new(),
+        ))?;
+        serde_json::to_string(&plan).map_err(|e| CoreError::Internal(e.to_string()))
+    })
+}
+
+/// Background variant of `plan_sync`.
+///
+/// # Returns
+/// * Job id for `crystal_job_poll` (result: the plan)
+/// * negated `ErrorCode` on invalid arguments or a manifest that does not verify
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_start_plan_sync(
+    core: *const CrystalCore,
+    signed_manifest_json: *const c_char,
+    public_key: *const c_char,
+    mods_dir: *const c_char,
+) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        let remote = verified_manifest_arg(signed_manifest_json, public_key)?.client_mods();
+        let mods_dir = PathBuf::from(str_arg(mods_dir, "mods_dir")?);
+        let cache = core.hash_cache.clone();
+
+        Ok(core.spawn_job("plan_sync", |progress, cancel| async move {
+            to_json(&plan(remote, mods_dir, cache, progress, cancel).await?)
+        }))
+    })
+}
+
+/// Execute a plan returned by `plan_sync`. Downloads go to a staging
+/// directory first; `mods_dir` only changes once all of them succeeded, and
+/// is then replaced in one step. The previous folder is kept for `rollback_mods`.
+///
+/// # Arguments
+/// * `plan_json` - Plan from `plan_sync`
+/// * `signed_manifest_json`, `public_key` - The manifest the plan was made from;
+///   download URLs and hashes are taken from it, not from the plan
+///
+/// # Returns
+/// * 1 on success
+/// * negated `ErrorCode` on error (see `crystal_last_error`); `-31` (`SIGNATURE`)
+///   if the manifest does not verify or the plan lists files it does not contain,
+///   `-80` (`INCOMPLETE`) when some downloads failed, in which case nothing was changed
+#[unsafe(no_mangle)]
+pub extern "C" fn apply_sync(
+    core: *const CrystalCore,
+    plan_json: *const c_char,
+    signed_manifest_json: *const c_char,
+    public_key: *const c_char,
+) -> i32 {
+    ffi_status(1, || {
+        let core = core_arg(core)?;
+        let manifest = verified_manifest_arg(signed_manifest_json, public_key)?;
+        let request = ApplyRequest::new(core, json_arg(plan_json, "plan_json")?, &manifest)?;
+
+        core.runtime
+            .block_on(apply(request, Progress::for_core(core, None), CancellationToken::new()))
+            .map(|_| ())
+    })
+}
+
+/// Background variant of `apply_sync`.
+///
+/// # Returns
+/// * Job id for `crystal_job_poll` (result: `{"downloaded": ["a.jar"], "deleted": ["old.jar"]}`)
+/// * negated `ErrorCode` on invalid arguments or a manifest that does not verify
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_start_apply_sync(
+    core: *const CrystalCore,
+    plan_json: *const c_char,
+    signed_manifest_json: *const c_char,
+    public_key: *const c_char,
+) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        let manifest = verified_manifest_arg(signed_manifest_json, public_key)?;
+        let request = ApplyRequest::new(core, json_arg(plan_json, "plan_json")?, &manifest)?;
+
+        Ok(core.spawn_job("apply_sync", |progress, cancel| async move {
+            to_json(&apply(request, progress, cancel).await?)
+        }))
+    })
+}
+
+/// Restore the `mods` folder (and `.official_mods`) from before the last
+/// `apply_sync`, e.g. when the updated pack crashes on launch.
+///
+/// # Arguments
+/// * `instance_dir` - Game directory containing `mods/`
+///
+/// # Returns
+/// * 1 on success
+/// * negated `ErrorCode` on error (`NOT_FOUND` if there is no snapshot)
+#[unsafe(no_mangle)]
+pub extern "C" fn rollback_mods(core: *const CrystalCore, instance_dir: *const c_char) -> i32 {
+    ffi_status(1, || {
+        core_arg(core)?;
+        rollback(Path::new(str_arg(instance_dir, "instance_dir")?))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashing::hash_bytes;
+    use std::sync::Mutex;
+
+    /// `<tmp>/crystal-sync-<name>-<pid>` with `mods/` holding `files` and
+    /// `.official_mods` listing `official`.
+    fn instance(name: &str, files: &[(&str, &str)], official: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("crystal-sync-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("mods")).unwrap();
+        for (file, contents) in files {
+            std::fs::write(dir.join("mods").join(file), contents).unwrap();
+        }
+        std::fs::write(dir.join(MANIFEST_FILE), official.join("\n")).unwrap();
+        dir
+    }
+
+    fn remote(name: &str, contents: &str) -> ModInfo {
+        let sha1 = hash_bytes(contents.as_bytes(), HashAlgorithm::Sha1);
+        ModInfo { name: name.to_string(), url: format!("https://cdn.example/{}", name), sha1, hash: None }
+    }
+
+    fn cache() -> Arc<HashCache> {
+        let db = Arc::new(Mutex::new(rusqlite::Connection::open_in_memory().unwrap()));
+        Arc::new(HashCache::open(db).unwrap())
+    }
+
+    fn sorted(names: &[String]) -> Vec<&str> {
+        let mut names: Vec<&str> = names.iter().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    async fn plan_for(dir: &Path, remote: Vec<ModInfo>) -> SyncPlan {
+        plan(remote, dir.join("mods"), cache(), Progress::default(), CancellationToken::new()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn plan_classifies_local_files() {
+        let dir = instance(
+            "plan",
+            &[("same.jar", "v1"), ("outdated.jar", "v1"), ("dropped.jar", "v1"), ("mine.jar", "v1")],
+            &["same.jar", "outdated.jar", "dropped.jar"],
+        );
+        let plan = plan_for(&dir, vec![remote("same.jar", "v1"), remote("outdated.jar", "v2"), remote("new.jar", "v1")]).await;
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let download: Vec<String> = plan.download.iter().map(|m| m.name.clone()).collect();
+        assert_eq!(sorted(&download), ["new.jar", "outdated.jar"]);
+        assert_eq!(plan.up_to_date, ["same.jar"]);
+        assert_eq!(plan.delete, ["dropped.jar"]);
+        assert_eq!(plan.user_files, ["mine.jar"]);
+        assert_eq!(plan.official, ["same.jar", "outdated.jar", "new.jar"]);
+    }
+
+    #[tokio::test]
+    async fn plan_rejects_unsafe_and_duplicate_names() {
+        let dir = instance("plan-invalid", &[], &[]);
+        let mods = dir.join("mods");
+        for remote in [vec![remote("../evil.jar", "v1")], vec![remote("a.jar", "v1"), remote("a.jar", "v2")]] {
+            assert!(plan(remote, mods.clone(), cache(), Progress::default(), CancellationToken::new()).await.is_err());
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn commit_swaps_in_staged_files_and_rollback_restores_them() {
+        let dir = instance(
+            "commit",
+            &[("same.jar", "v1"), ("outdated.jar", "v1"), ("dropped.jar", "v1"), ("mine.jar", "v1")],
+            &["same.jar", "outdated.jar", "dropped.jar"],
+        );
+        let mods = dir.join("mods");
+        let plan = plan_for(&dir, vec![remote("same.jar", "v1"), remote("outdated.jar", "v2"), remote("new.jar", "v1")]).await;
+
+        // 1. Stage the downloads, as `apply` leaves them once verified
+        let staging = dir.join(STAGING_DIR);
+        std::fs::create_dir_all(&staging).unwrap();
+        std::fs::write(staging.join("outdated.jar"), "v2").unwrap();
+        std::fs::write(staging.join("new.jar"), "v1").unwrap();
+
+        // 2. Commit: staged files swapped in, dropped file gone, user file kept
+        let report = commit(&plan, &mods, &staging, None).unwrap();
+        assert_eq!(sorted(&report.downloaded), ["new.jar", "outdated.jar"]);
+        assert_eq!(report.deleted, ["dropped.jar"]);
+        let files: Vec<String> = list_files(&mods).unwrap().into_iter().collect();
+        assert_eq!(files, ["mine.jar", "new.jar", "outdated.jar", "same.jar"]);
+        assert_eq!(std::fs::read_to_string(mods.join("outdated.jar")).unwrap(), "v2");
+        assert_eq!(sorted(&read_manifest(&mods).unwrap().into_iter().collect::<Vec<_>>()), ["new.jar", "outdated.jar", "same.jar"]);
+        assert!(!staging.exists() && !dir.join(NEXT_DIR).exists() && !dir.join(PENDING_MANIFEST).exists());
+
+        // 3. Snapshot: the previous folder and manifest
+        let snapshot = dir.join(SNAPSHOT_DIR);
+        assert_eq!(std::fs::read_to_string(snapshot.join("mods/outdated.jar")).unwrap(), "v1");
+        assert!(snapshot.join("mods/dropped.jar").exists());
+        assert!(snapshot.join(MANIFEST_FILE).exists());
+
+        // 4. Rollback, carrying over a file the user added after the sync
+        std::fs::write(mods.join("added.jar"), "v1").unwrap();
+        rollback(&dir).unwrap();
+        let files: Vec<String> = list_files(&mods).unwrap().into_iter().collect();
+        assert_eq!(files, ["added.jar", "dropped.jar", "mine.jar", "outdated.jar", "same.jar"]);
+        assert_eq!(std::fs::read_to_string(mods.join("outdated.jar")).unwrap(), "v1");
+        assert_eq!(
+            sorted(&read_manifest(&mods).unwrap().into_iter().collect::<Vec<_>>()),
+            ["dropped.jar", "outdated.jar", "same.jar"]
+        );
+        assert!(!snapshot.exists() && !dir.join(NEXT_DIR).exists());
+        assert!(rollback(&dir).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn interrupted_commit_is_completed_or_dropped() {
+        // Died after the swap: the pending manifest is promoted
+        let dir = instance("recover", &[("a.jar", "v1")], &["old.jar"]);
+        std::fs::write(dir.join(PENDING_MANIFEST), "a.jar").unwrap();
+        recover_manifest(&dir.join("mods")).unwrap();
+        assert_eq!(read_manifest(&dir.join("mods")).unwrap(), HashSet::from(["a.jar".to_string()]));
+
+        // Died before it: `.mods_next` is still there, the old manifest stays
+        std::fs::write(dir.join(PENDING_MANIFEST), "b.jar").unwrap();
+        std::fs::create_dir_all(dir.join(NEXT_DIR)).unwrap();
+        recover_manifest(&dir.join("mods")).unwrap();
+        assert_eq!(read_manifest(&dir.join("mods")).unwrap(), HashSet::from(["a.jar".to_string()]));
+        assert!(!dir.join(PENDING_MANIFEST).exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,386 @@
+//! Vanilla client install: version manifest -> version JSON -> client jar,
+//! libraries and assets, all through the parallel verified downloader.
+//!
+//! Layout under the game directory (as the official launcher and the Dart
+//! installer use it):
+//! `versions/<id>/<id>.json`, `versions/<id>/<id>.jar`, `libraries/<maven path>`,
+//! `assets/indexes/<index>.json`, `assets/objects/ab/abcdef...`.
+//!
+//! Files already present with the right SHA-1 (checked through the hash
+//! cache) are not downloaded again, so re-running an install is a cheap
+//! repair.
+
+use crate::cancel::{CancellationToken, check};
+use crate::context::CrystalCore;
+use crate::download::download_file;
+use crate::error::CoreError;
+use crate::ffi::{core_arg, ffi_status, join_error, str_arg, to_json};
+use crate::hash_cache::HashCache;
+use crate::hashing::{HashAlgorithm, hash_file, hash_files};
+use crate::jobs::ffi_job;
+use crate::libraries::resolve_libraries;
+use crate::net::Net;
+use crate::progress::{Phase, Progress};
+use crate::r2_sync::{DownloadRequest, ModInfo, download_batch};
+use crate::retry::RetryPolicy;
+use crate::rules::Environment;
+use crate::safe_path::{safe_file_name, safe_join, safe_relative_path};
+use crate::version::VersionJson;
+use std::collections::{BTreeMap, HashSet};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Mojang endpoints, configurable (`CoreConfig::mojang`) so installs can be
+/// tested against a local mirror.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MojangUrls {
+    /// `version_manifest_v2.json`.
+    pub version_manifest: String,
+    /// Base of asset objects: `{resources}/ab/abcdef...`.
+    pub resources: String,
+    /// URL prefixes replaced in everything the version JSONs point at
+    /// (version JSON, client jar, libraries, asset index), e.g.
+    /// `{"https://libraries.minecraft.net/": "http://127.0.0.1:8080/libraries/"}`.
+    pub rewrite: BTreeMap<String, String>,
+}
+
+impl Default for MojangUrls {
+    fn default() -> Self {
+        Self {
+            version_manifest: "https://piston-meta.mojang.com/mc/game/version_manifest_v2.json".to_string(),
+            resources: "https://resources.download.minecraft.net".to_string(),
+            rewrite: BTreeMap::new(),
+        }
+    }
+}
+
+impl MojangUrls {
+    /// `url` with the longest matching `rewrite` prefix replaced.
+    pub fn resolve(&self, url: &str) -> String {
+        self.rewrite
+            .iter()
+            .filter(|(from, _)| url.starts_with(from.as_str()))
+            .max_by_key(|(from, _)| from.len())
+            .map_or_else(|| url.to_string(), |(from, to)| format!("{}{}", to, &url[from.len()..]))
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct VersionManifest {
+    versions: Vec<ManifestVersion>,
+}
+
+#[derive(serde::Deserialize)]
+struct ManifestVersion {
+    id: String,
+    url: String,
+    sha1: String,
+}
+
+#[derive(serde::Deserialize)]
+pub(crate) struct AssetIndex {
+    objects: BTreeMap<String, AssetObject>,
+    /// 1.7.2 - 1.7.10 (`legacy`): objects are also laid out by name under `assets/virtual/<index>`.
+    #[serde(default, rename = "virtual")]
+    pub(crate) is_virtual: bool,
+    /// Before 1.6 (`pre-1.6`): objects are copied by name into `<game_dir>/resources`.
+    #[serde(default)]
+    pub(crate) map_to_resources: bool,
+}
+
+#[derive(serde::Deserialize)]
+struct AssetObject {
+    hash: String,
+}
+
+/// Result of `install_vanilla`.
+#[derive(Debug, serde::Serialize)]
+pub struct VanillaReport {
+    pub version_id: String,
+    pub asset_index: Option<String>,
+    pub libraries: usize,
+    pub assets: usize,
+    /// Files fetched by this run (the rest were already valid).
+    pub downloaded: usize,
+    pub up_to_date: usize,
+}
+
+/// What `install` needs from the core.
+struct InstallRequest {
+    version_id: String,
+    game_dir: PathBuf,
+    urls: MojangUrls,
+    net: Net,
+    retry: RetryPolicy,
+    cache: Arc<HashCache>,
+    downloads: DownloadRequest,
+}
+
+impl InstallRequest {
+    fn new(core: &CrystalCore, version_id: &str, game_dir: &str) -> Result<Self, CoreError> {
+        safe_file_name(version_id)?;
+        let downloads = DownloadRequest::new(core, Vec::new(), game_dir.to_string(), core.config().max_concurrent_downloads)?;
+        Ok(Self {
+            version_id: version_id.to_string(),
+            game_dir: PathBuf::from(game_dir),
+            urls: core.config().mojang.clone(),
+            net: core.net.clone(),
+            retry: core.config().retry.clone(),
+            cache: core.hash_cache.clone(),
+            downloads,
+        })
+    }
+}
+
+/// Download one small metadata file to `dest` unless it is already there with `sha1`.
+#[allow(clippy::too_many_arguments)]
+async fn fetch_verified(
+    net: &Net,
+    retry: &RetryPolicy,
+    cache: &Arc<HashCache>,
+    url: &str,
+    dest: &Path,
+    sha1: &str,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> Result<(), CoreError> {
+    if dest.is_file() {
+        let hashes = hash_files(vec![dest.display().to_string()], vec![HashAlgorithm::Sha1], cache.clone(), Progress::default(), cancel.clone()).await?;
+        if let Some((_, Ok(h))) = hashes.first()
+            && h.sha1.as_deref().is_some_and(|h| h.eq_ignore_ascii_case(sha1))
+        {
+            return Ok(());
+        }
+    }
+    if let Some(parent) = dest.parent() {
+        tokio::fs::create_dir_all(parent).await.map_err(|e| CoreError::io(parent, e))?;
+    }
+    let name = dest.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let item = progress.start_item(name.as_str(), Phase::Downloading, None);
+    let (result, _) = retry
+        .run(cancel, &name, |attempt| {
+            if attempt > 1 {
+                item.restart(Phase::Downloading);
+            }
+            download_file(net, url, dest, Some(sha1), &item, cancel)
+        })
+        .await;
+    item.finish(result)
+}
+
+async fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, CoreError> {
+    let bytes = tokio::fs::read(path).await.map_err(|e| CoreError::io(path, e))?;
+    serde_json::from_slice(&bytes).map_err(|e| CoreError::invalid("version", format!("{}: {}", path.display(), e)))
+}
+
+/// Install `version_id` into `game_dir`.
+async fn install(request: InstallRequest, progress: Progress, cancel: CancellationToken) -> Result<VanillaReport, CoreError> {
+    let InstallRequest { version_id, game_dir, urls, net, retry, cache, downloads } = request;
+
+    // 1. Version manifest
+    let manifest_url = &urls.version_manifest;
+    let (manifest, _) = retry
+        .run(&cancel, manifest_url, |_| async {
+            let _connection = net.limits.connect(manifest_url, &cancel).await?;
+            let resp = net.client.get(manifest_url).send().await.map_err(|e| CoreError::network(manifest_url, e))?;
+            if !resp.status().is_success() {
+                return Err(CoreError::http_status(manifest_url, &resp));
+            }
+            resp.json::<VersionManifest>().await.map_err(|e| CoreError::network(manifest_url, e))
+        })
+        .await;
+    let manifest = manifest?;
+    let entry = manifest
+        .versions
+        .iter()
+        .find(|v| v.id == version_id)
+        .ok_or_else(|| CoreError::invalid("version_id", format!("{} is not in the version manifest", version_id)))?;
+
+    // 2. Version JSON
+    let version_dir = game_dir.join("versions").join(&version_id);
+    let version_path = version_dir.join(format!("{}.json", version_id));
+    fetch_verified(&net, &retry, &cache, &urls.resolve(&entry.url), &version_path, &entry.sha1, &progress, &cancel).await?;
+    let version: VersionJson = read_json(&version_path).await?;
+    if version.id != version_id {
+        return Err(CoreError::invalid("version", format!("{} declares id {}", version_path.display(), version.id)));
+    }
+
+    // 3. Client jar and libraries (Mojang lists a SHA-1 for every download:
+    //    one without is refused rather than installed unverified)
+    let no_sha1 = |what: &str| CoreError::invalid("version", format!("{} of {} has no sha1", what, version_id));
+    let mut files = Vec::new();
+    if let Some(client) = version.downloads.get("client") {
+        files.push(ModInfo {
+            name: format!("versions/{}/{}.jar", version_id, version_id),
+            url: urls.resolve(&client.url),
+            sha1: client.sha1.clone().ok_or_else(|| no_sha1("client jar"))?,
+            hash: None,
+        });
+    }
+    let resolved = resolve_libraries(&version.libraries, &Environment::current())?;
+    let mut libraries = 0;
+    for library in resolved {
+        // Files without a URL are generated locally (loader installers).
+        let Some(url) = library.url else { continue };
+        let sha1 = library.sha1.ok_or_else(|| no_sha1(&library.name))?;
+        files.push(ModInfo { name: format!("libraries/{}", library.path), url: urls.resolve(&url), sha1, hash: None });
+        libraries += 1;
+    }
+
+    // 4. Asset index and objects (deduplicated: many names share an object)
+    let mut assets = 0;
+    let mut asset_index = None;
+    if let Some(index_ref) = &version.asset_index {
+        safe_file_name(&index_ref.id)?;
+        let index_path = game_dir.join("assets").join("indexes").join(format!("{}.json", index_ref.id));
+        fetch_verified(&net, &retry, &cache, &urls.resolve(&index_ref.url), &index_path, &index_ref.sha1, &progress, &cancel).await?;
+        let index: AssetIndex = read_json(&index_path).await?;
+
+        let mut seen = HashSet::new();
+        for object in index.objects.values() {
+            let hash = object.hash.to_ascii_lowercase();
+            if hash.len() != 40 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(CoreError::invalid("asset index", format!("bad hash {:?}", object.hash)));
+            }
+            if seen.insert(hash.clone()) {
+                files.push(ModInfo {
+                    name: format!("assets/objects/{}/{}", &hash[..2], hash),
+                    url: format!("{}/{}/{}", urls.resources.trim_end_matches('/'), &hash[..2], hash),
+                    sha1: hash,
+                    hash: None,
+                });
+            }
+        }
+        assets = seen.len();
+        asset_index = Some((index_ref.id.clone(), index));
+    }
+
+    // 5. Skip what is already there
+    let existing: Vec<_> = files
+        .iter()
+        .map(|f| game_dir.join(&f.name))
+        .filter(|p| p.is_file())
+        .map(|p| p.display().to_string())
+        .collect();
+    let mut valid = HashSet::new();
+    for (path, result) in hash_files(existing, vec![HashAlgorithm::Sha1], cache.clone(), progress.clone(), cancel.clone()).await? {
+        if let Ok(hashes) = result {
+            valid.insert((path, hashes.sha1.unwrap_or_default()));
+        }
+    }
+    let total = files.len();
+    files.retain(|f| !valid.contains(&(game_dir.join(&f.name).display().to_string(), f.sha1.to_ascii_lowercase())));
+    let downloaded = files.len();
+
+    // 6. Download
+    println!("[Rust] Installing {}: {} files to download, {} up to date", version_id, downloaded, total - downloaded);
+    tokio::fs::create_dir_all(&game_dir).await.map_err(|e| CoreError::io(&game_dir, e))?;
+    download_batch(net, downloads.with_mods(files)?, progress, cancel.clone(), |_| {}).await?.into_result()?;
+
+    // 7. Legacy asset layouts
+    let asset_index_id = asset_index.as_ref().map(|(id, _)| id.clone());
+    if let Some((id, index)) = asset_index
+        && (index.is_virtual || index.map_to_resources)
+    {
+        let dir = game_dir.clone();
+        tokio::task::spawn_blocking(move || lay_out_legacy_assets(&dir, &id, &index, &cache, &cancel))
+            .await
+            .map_err(join_error)??;
+    }
+
+    Ok(VanillaReport {
+        version_id,
+        asset_index: asset_index_id,
+        libraries,
+        assets,
+        downloaded,
+        up_to_date: total - downloaded,
+    })
+}
+
+/// Copy objects by name for old versions that read assets that way. A copy
+/// is kept only if its SHA-1 (through the hash cache) is the object's.
+fn lay_out_legacy_assets(
+    game_dir: &Path,
+    index_id: &str,
+    index: &AssetIndex,
+    cache: &HashCache,
+    cancel: &CancellationToken,
+) -> Result<(), CoreError> {
+    let objects = game_dir.join("assets").join("objects");
+    let target = match index.map_to_resources {
+        true => game_dir.join("resources"),
+        false => game_dir.join("assets").join("virtual").join(index_id),
+    };
+    for (name, object) in &index.objects {
+        safe_relative_path(name)?;
+        let hash = object.hash.to_ascii_lowercase();
+        let source = objects.join(&hash[..2]).join(&hash);
+        let dest = safe_join(&target, name)?;
+        check(cancel)?;
+        let up_to_date = dest.is_file() && {
+            // Not counted in the install progress.
+            let item = Progress::default().start_item(name.as_str(), Phase::Verifying, None);
+            let (hashes, _) = cache.get_or_hash(&dest, &[HashAlgorithm::Sha1], |missing| hash_file(&dest, missing, &item, cancel))?;
+            hashes.sha1.as_deref() == Some(hash.as_str())
+        };
+        if up_to_date {
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| CoreError::io(parent, e))?;
+        }
+        std::fs::copy(&source, &dest).map_err(|e| CoreError::io(&dest, e))?;
+    }
+    Ok(())
+}
+
+/// Install a vanilla Minecraft version: version JSON, client jar, libraries
+/// and assets, downloaded in parallel and verified. Already valid files are
+/// kept, so this also repairs an install. Endpoints come from the `mojang`
+/// section of the core config.
+///
+/// # Arguments
+/// * `version_id` - e.g. `1.21.1`
+/// * `game_dir` - Game directory (`versions/`, `libraries/`, `assets/` go there)
+///
+/// # Returns
+/// * 1 on success
+/// * negated `ErrorCode` on error (see `crystal_last_error`); `-80` (`INCOMPLETE`)
+///   when some downloads failed
+#[unsafe(no_mangle)]
+pub extern "C" fn install_vanilla(core: *const CrystalCore, version_id: *const c_char, game_dir: *const c_char) -> i32 {
+    ffi_status(1, || {
+        let core = core_arg(core)?;
+        let request = InstallRequest::new(core, str_arg(version_id, "version_id")?, str_arg(game_dir, "game_dir")?)?;
+
+        core.runtime
+            .block_on(install(request, Progress::for_core(core, None), CancellationToken::new()))
+            .map(|_| ())
+    })
+}
+
+/// Background variant of `install_vanilla`.
+///
+/// # Returns
+/// * Job id for `crystal_job_poll` (result: `{"version_id", "asset_index", "libraries",
+///   "assets", "downloaded", "up_to_date"}`)
+/// * negated `ErrorCode` on invalid arguments
+#[unsafe(no_mangle)]
+pub extern "C" fn crystal_job_start_install_vanilla(
+    core: *const CrystalCore,
+    version_id: *const c_char,
+    game_dir: *const c_char,
+) -> i64 {
+    ffi_job(|| {
+        let core = core_arg(core)?;
+        let request = InstallRequest::new(core, str_arg(version_id, "version_id")?, str_arg(game_dir, "game_dir")?)?;
+
+        Ok(core.spawn_job("install_vanilla", |progress, cancel| async move {
+            let report = install(request, progress, cancel).await?;
+            to_json(&report)
+        }))
+    })
+}
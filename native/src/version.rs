@@ -0,0 +1,234 @@
+//! Version JSONs (`versions/<id>/<id>.json`) and their `inheritsFrom`
+//! chains.
+//!
+//! A loader version (NeoForge, Fabric...) only lists what it adds to the
+//! version it inherits from. `read_version` merges the chain the way the
+//! official launcher does: the child's scalar fields win, its libraries come
+//! first, and its `arguments.game` / `arguments.jvm` are appended to the
+//! parent's.
+
+use crate::error::CoreError;
+use crate::libraries::{Artifact, Library};
+use crate::rules::Rule;
+use crate::safe_path::safe_file_name;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct VersionJson {
+    pub(crate) id: String,
+    #[serde(default)]
+    pub(crate) inherits_from: Option<String>,
+    #[serde(default, rename = "type")]
+    pub(crate) version_type: Option<String>,
+    #[serde(default)]
+    pub(crate) main_class: Option<String>,
+    /// 1.13+.
+    #[serde(default)]
+    pub(crate) arguments: Option<Arguments>,
+    /// Up to 1.12: the whole game command line as one string.
+    #[serde(default)]
+    pub(crate) minecraft_arguments: Option<String>,
+    #[serde(default)]
+    pub(crate) libraries: Vec<Library>,
+    #[serde(default)]
+    pub(crate) downloads: BTreeMap<String, Artifact>,
+    #[serde(default)]
+    pub(crate) asset_index: Option<AssetIndexRef>,
+    /// Asset index id for versions without `assetIndex`.
+    #[serde(default)]
+    pub(crate) assets: Option<String>,
+    /// Version whose jar is launched (older Forge JSONs).
+    #[serde(default)]
+    pub(crate) jar: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AssetIndexRef {
+    pub(crate) id: String,
+    pub(crate) url: String,
+    pub(crate) sha1: String,
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+pub(crate) struct Arguments {
+    #[serde(default)]
+    pub(crate) game: Vec<Argument>,
+    #[serde(default)]
+    pub(crate) jvm: Vec<Argument>,
+}
+
+/// An entry of `arguments.game` / `arguments.jvm`.
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Argument {
+    Plain(String),
+    Conditional { rules: Vec<Rule>, value: ArgumentValue },
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(untagged)]
+pub(crate) enum ArgumentValue {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl VersionJson {
+    pub(crate) fn asset_index_id(&self) -> Option<&str> {
+        self.asset_index.as_ref().map(|a| a.id.as_str()).or(self.assets.as_deref())
+    }
+
+    /// Merge `self` (the child) over `parent`.
+    fn inherit(self, parent: VersionJson) -> VersionJson {
+        let arguments = match (parent.arguments, self.arguments) {
+            (Some(mut merged), Some(child)) => {
+                merged.game.extend(child.game);
+                merged.jvm.extend(child.jvm);
+                Some(merged)
+            }
+            (parent, child) => child.or(parent),
+        };
+        let mut libraries = self.libraries;
+        libraries.extend(parent.libraries);
+        let mut downloads = parent.downloads;
+        downloads.extend(self.downloads);
+
+        VersionJson {
+            id: self.id,
+            inherits_from: parent.inherits_from,
+            version_type: self.version_type.or(parent.version_type),
+            main_class: self.main_class.or(parent.main_class),
+            arguments,
+            minecraft_arguments: self.minecraft_arguments.or(parent.minecraft_arguments),
+            libraries,
+            downloads,
+            asset_index: self.asset_index.or(parent.asset_index),
+            assets: self.assets.or(parent.assets),
+            jar: self.jar.or(parent.jar),
+        }
+    }
+}
+
+/// `versions/<id>/<id>.json` under `game_dir`.
+pub(crate) fn version_json_path(game_dir: &Path, version_id: &str) -> Result<PathBuf, CoreError> {
+    safe_file_name(version_id)?;
+    Ok(game_dir.join("versions").join(version_id).join(format!("{}.json", version_id)))
+}
+
+pub(crate) fn parse_version(bytes: &[u8], path: &Path) -> Result<VersionJson, CoreError> {
+    serde_json::from_slice(bytes).map_err(|e| CoreError::invalid("version", format!("{}: {}", path.display(), e)))
+}
+
+/// The installed version `version_id`, merged with everything it inherits
+/// from, and the ids of the chain (child first).
+pub(crate) fn read_version(game_dir: &Path, version_id: &str) -> Result<(VersionJson, Vec<String>), CoreError> {
+    let mut chain = Vec::new();
+    let mut visited = HashSet::new();
+    let mut merged: Option<VersionJson> = None;
+    let mut next = Some(version_id.to_string());
+    while let Some(id) = next {
+        if !visited.insert(id.clone()) {
+            return Err(CoreError::invalid("version", format!("inheritsFrom cycle at {}", id)));
+        }
+        let path = version_json_path(game_dir, &id)?;
+        let bytes = std::fs::read(&path).map_err(|e| CoreError::io(&path, e))?;
+        let version = parse_version(&bytes, &path)?;
+        next = version.inherits_from.clone();
+        chain.push(id);
+        merged = Some(match merged {
+            Some(child) => child.inherit(version),
+            None => version,
+        });
+    }
+    let merged = merged.ok_or_else(|| CoreError::invalid("version_id", "empty"))?;
+    Ok((merged, chain))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(id: &str) -> VersionJson {
+        let json = match id {
+            "1.8" => include_str!("../tests/fixtures/versions/1.8.json"),
+            "1.21" => include_str!("../tests/fixtures/versions/1.21.json"),
+            "neoforge-21.1.219" => include_str!("../tests/fixtures/versions/neoforge-21.1.219.json"),
+            other => panic!("no fixture {}", other),
+        };
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn plain(arguments: &[Argument]) -> Vec<&str> {
+        arguments
+            .iter()
+            .filter_map(|a| match a {
+                Argument::Plain(value) => Some(value.as_str()),
+                Argument::Conditional { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loader_inherits_from_vanilla() {
+        let parent = fixture("1.21");
+        let parent_jvm = parent.arguments.as_ref().unwrap().jvm.len();
+        let parent_game = parent.arguments.as_ref().unwrap().game.len();
+        let merged = fixture("neoforge-21.1.219").inherit(parent);
+
+        // Child scalars win, parent fills the rest
+        assert_eq!(merged.id, "neoforge-21.1.219");
+        assert_eq!(merged.inherits_from, None);
+        assert_eq!(merged.main_class.as_deref(), Some("cpw.mods.bootstraplauncher.BootstrapLauncher"));
+        assert_eq!(merged.asset_index_id(), Some("17"));
+
+        // Parent arguments are kept, the child's appended after them
+        let arguments = merged.arguments.unwrap();
+        assert_eq!(arguments.jvm.len(), parent_jvm + 2);
+        assert_eq!(arguments.game.len(), parent_game + 10);
+        let jvm = plain(&arguments.jvm);
+        assert_eq!(jvm.first(), Some(&"-Djava.library.path=${natives_directory}"));
+        assert_eq!(jvm[jvm.len() - 3..], ["${classpath}", "-Djava.net.preferIPv6Addresses=system", "-DlibraryDirectory=${library_directory}"]);
+        let game = plain(&arguments.game);
+        assert_eq!(game[..2], ["--username", "${auth_player_name}"]);
+        assert_eq!(game[game.len() - 2..], ["--launchTarget", "forgeclient"]);
+
+        // Child libraries come first
+        assert_eq!(merged.libraries[0].name, "net.neoforged.fancymodloader:loader:4.0.42");
+        assert_eq!(merged.libraries[1].name, "com.google.guava:guava:32.1.2-jre");
+    }
+
+    #[test]
+    fn legacy_arguments_are_inherited() {
+        let mut child = fixture("1.8");
+        child.id = "forge-1.8".to_string();
+        child.minecraft_arguments = None;
+        child.libraries.clear();
+        let merged = child.inherit(fixture("1.8"));
+
+        assert!(merged.arguments.is_none());
+        assert!(merged.minecraft_arguments.unwrap().starts_with("--username ${auth_player_name}"));
+    }
+
+    #[test]
+    fn read_version_follows_the_chain() {
+        let dir = std::env::temp_dir().join(format!("crystal-version-chain-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (id, json) in [
+            ("1.21", include_str!("../tests/fixtures/versions/1.21.json")),
+            ("neoforge-21.1.219", include_str!("../tests/fixtures/versions/neoforge-21.1.219.json")),
+        ] {
+            let path = version_json_path(&dir, id).unwrap();
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, json).unwrap();
+        }
+
+        let (merged, chain) = read_version(&dir, "neoforge-21.1.219").unwrap();
+        assert_eq!(chain, ["neoforge-21.1.219", "1.21"]);
+        assert_eq!(merged.id, "neoforge-21.1.219");
+        assert!(read_version(&dir, "1.20").is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
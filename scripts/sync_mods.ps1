@@ -1,8 +1,21 @@
+param(
+    [string]$PackVersion = (Get-Date -Format "yyyy.MM.dd.HHmm"),
+    [string]$MinecraftVersion = "1.21.1",
+    [string]$NeoForgeVersion = "21.1.219",
+    # Hex Ed25519 secret key of the pack (keep it out of the repo)
+    [string]$SecretKeyFile = "$env:USERPROFILE\.crystaltides\pack_secret_key.txt",
+    # Create a new key pair in $SecretKeyFile and print the public key
+    [switch]$NewKeyPair
+)
+
 $ModsDir = "C:\Users\nacho\AppData\Roaming\.minecraft\mods"
 $SqlFile = "insert_mods.sql"
 $ErrorActionPreference = "Stop"
 
-# --- CONFIGURATION (UPDATE THESE) ---
+$ScriptDir = Split-Path -Parent $MyInvocation.MyCommand.Definition
+$NativeDll = Join-Path $ScriptDir "..\native\target\release\crystal_native.dll"
+$ManifestKey = "launcher/pack_manifest.json"
+
 # --- CONFIGURATION (UPDATE THESE) ---
 $BucketName = "ctlauncher"
 $R2PublicUrl = "https://pub-3a18f6cd71c44a49b8f2f2e48e14a744.r2.dev" 
@@ -12,6 +25,68 @@ Write-Host "Starting Mod Sync with Cloudflare R2 Support..." -ForegroundColor Cy
 Write-Host "Source: $ModsDir"
 Write-Host "Target Bucket: $BucketName"
 
+# 1. Native library (manifest_generate / manifest_sign)
+if (!(Test-Path $NativeDll)) {
+    Write-Host "ERROR: $NativeDll not found. Run 'cargo build --release' in native/ first." -ForegroundColor Red
+    exit 1
+}
+Add-Type -TypeDefinition @"
+using System;
+using System.Runtime.InteropServices;
+using System.Text;
+
+public static class CrystalNative {
+    const string Dll = @"$((Resolve-Path $NativeDll).Path)";
+
+    [DllImport(Dll, CallingConvention = CallingConvention.Cdecl)] static extern IntPtr crystal_core_new(byte[] config);
+    [DllImport(Dll, CallingConvention = CallingConvention.Cdecl)] public static extern void crystal_core_free(IntPtr core);
+    [DllImport(Dll, CallingConvention = CallingConvention.Cdecl)] static extern IntPtr crystal_last_error();
+    [DllImport(Dll, CallingConvention = CallingConvention.Cdecl)] static extern void free_string(IntPtr s);
+    [DllImport(Dll, CallingConvention = CallingConvention.Cdecl)] static extern IntPtr manifest_generate_keypair(IntPtr core);
+    [DllImport(Dll, CallingConvention = CallingConvention.Cdecl)] static extern IntPtr manifest_generate(IntPtr core, byte[] spec);
+    [DllImport(Dll, CallingConvention = CallingConvention.Cdecl)] static extern IntPtr manifest_sign(IntPtr core, byte[] manifest, byte[] secretKey);
+
+    static byte[] Utf8(string s) { return Encoding.UTF8.GetBytes(s + "\0"); }
+
+    static string Take(IntPtr p) {
+        if (p == IntPtr.Zero) {
+            IntPtr e = crystal_last_error();
+            throw new Exception(e == IntPtr.Zero ? "unknown native error" : Take(e));
+        }
+        int len = 0;
+        while (Marshal.ReadByte(p, len) != 0) len++;
+        byte[] bytes = new byte[len];
+        Marshal.Copy(p, bytes, 0, len);
+        free_string(p);
+        return Encoding.UTF8.GetString(bytes);
+    }
+
+    public static IntPtr CoreNew(string config) {
+        IntPtr core = crystal_core_new(Utf8(config));
+        if (core == IntPtr.Zero) Take(IntPtr.Zero);
+        return core;
+    }
+    public static string GenerateKeypair(IntPtr core) { return Take(manifest_generate_keypair(core)); }
+    public static string Generate(IntPtr core, string spec) { return Take(manifest_generate(core, Utf8(spec))); }
+    public static string Sign(IntPtr core, string manifest, string secretKey) { return Take(manifest_sign(core, Utf8(manifest), Utf8(secretKey))); }
+}
+"@
+
+# Hashes are cached next to the script between runs
+$Core = [CrystalNative]::CoreNew((@{ db_path = (Join-Path $ScriptDir ".pack_hashes.db") } | ConvertTo-Json -Compress))
+
+if ($NewKeyPair) {
+    $Pair = [CrystalNative]::GenerateKeypair($Core) | ConvertFrom-Json
+    New-Item -ItemType Directory -Force -Path (Split-Path -Parent $SecretKeyFile) | Out-Null
+    Set-Content -Path $SecretKeyFile -Value $Pair.secret_key -NoNewline
+    Write-Host "Secret key saved to $SecretKeyFile" -ForegroundColor Green
+    Write-Host "Public key (set CRYSTAL_PACK_PUBLIC_KEY before build.ps1): $($Pair.public_key)" -ForegroundColor Yellow
+}
+if (!(Test-Path $SecretKeyFile)) {
+    Write-Host "ERROR: $SecretKeyFile not found. Run with -NewKeyPair once to create the pack key." -ForegroundColor Red
+    exit 1
+}
+
 Write-Host "Checking rclone installation..."
 if (!(Get-Command rclone -ErrorAction SilentlyContinue)) {
     Write-Host "ERROR: rclone not found. Please install it (https://rclone.org/) and configure your R2 remote." -ForegroundColor Red
@@ -22,6 +97,7 @@ if (!(Get-Command rclone -ErrorAction SilentlyContinue)) {
 $SqlContent = "-- SQL to update official_mods with Proxy URLs`n"
 $SqlContent += "TRUNCATE TABLE official_mods;`n"
 $Files = Get-ChildItem -LiteralPath $ModsDir -Filter *.jar
+$ManifestFiles = @()
 
 Write-Host "Found $( $Files.Count ) mods."
 
@@ -41,6 +117,7 @@ foreach ($File in $Files) {
     $DownloadUrl = "$R2PublicUrl/$EncodedName"
     
     $NameSafe = $FileName.Replace("'", "''")
+    $ManifestFiles += @{ path = "mods/$FileName"; url = $DownloadUrl }
     $SqlContent += "INSERT INTO official_mods (name, version, download_url, sha1) VALUES ('$NameSafe', '1.0', '$DownloadUrl', '$Hash');`n"
     
     # Upload to R2 using rclone
@@ -53,9 +130,31 @@ foreach ($File in $Files) {
     }
 }
 
-$ScriptDir = Split-Path -Parent $MyInvocation.MyCommand.Definition
 $SqlFilePath = Join-Path $ScriptDir $SqlFile
 
 Set-Content -Path $SqlFilePath -Value $SqlContent
+
+# 3. Signed pack manifest (what the launcher actually trusts)
+Write-Host "`nGenerating signed pack manifest $PackVersion..." -ForegroundColor Cyan
+$Spec = @{
+    pack_version      = $PackVersion
+    minecraft_version = $MinecraftVersion
+    neoforge_version  = $NeoForgeVersion
+    root              = (Split-Path -Parent $ModsDir)
+    base_url          = $R2PublicUrl
+    files             = $ManifestFiles
+} | ConvertTo-Json -Depth 4 -Compress
+try {
+    $Manifest = [CrystalNative]::Generate($Core, $Spec)
+    $Signed = [CrystalNative]::Sign($Core, $Manifest, (Get-Content -Raw $SecretKeyFile).Trim())
+} finally {
+    [CrystalNative]::crystal_core_free($Core)
+}
+
+$ManifestPath = Join-Path $ScriptDir "pack_manifest.json"
+[System.IO.File]::WriteAllText($ManifestPath, $Signed)
+rclone copyto $ManifestPath "$($R2RemoteName):$($BucketName)/$ManifestKey"
+if ($LASTEXITCODE -ne 0) { throw "Manifest upload failed with code $LASTEXITCODE" }
+Write-Host "Manifest uploaded to $R2PublicUrl/$ManifestKey" -ForegroundColor Green
 Write-Host "`nDone! SQL script saved to $SqlFilePath" -ForegroundColor Cyan
 Write-Host "Run this SQL in Supabase Dashboard to update the mod list with Proxy URLs." -ForegroundColor Yellow